@@ -0,0 +1,7 @@
+-- ids of deleted history, kept so that deletions can be synced
+create table if not exists history_deleted (
+	id text primary key,
+	deleted_at integer not null
+);
+
+create index if not exists idx_history_deleted_deleted_at on history_deleted(deleted_at);
@@ -0,0 +1,5 @@
+-- whether history has been uploaded to the sync server. history downloaded
+-- from the server is saved as already uploaded, so it is never sent back
+alter table history add column uploaded integer not null default 0;
+
+create index if not exists idx_history_uploaded on history(uploaded);
@@ -0,0 +1,23 @@
+-- full text index of commands, so that searching doesn't have to scan every row.
+-- the trigram tokenizer lets like queries use the index
+create virtual table if not exists history_fts using fts5(
+	command,
+	content = 'history',
+	content_rowid = 'rowid',
+	tokenize = 'trigram'
+);
+
+insert into history_fts(history_fts) values('rebuild');
+
+create trigger if not exists history_fts_insert after insert on history begin
+	insert into history_fts(rowid, command) values (new.rowid, new.command);
+end;
+
+create trigger if not exists history_fts_delete after delete on history begin
+	insert into history_fts(history_fts, rowid, command) values ('delete', old.rowid, old.command);
+end;
+
+create trigger if not exists history_fts_update after update of command on history begin
+	insert into history_fts(history_fts, rowid, command) values ('delete', old.rowid, old.command);
+	insert into history_fts(rowid, command) values (new.rowid, new.command);
+end;
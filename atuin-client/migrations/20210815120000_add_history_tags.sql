@@ -0,0 +1,3 @@
+-- tags are kept on this machine only, and never synced. each is wrapped in
+-- commas, like ",deploy,prod,", so that one can be found with instr
+alter table history add column tags text not null default '';
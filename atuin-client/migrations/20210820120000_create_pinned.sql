@@ -0,0 +1,6 @@
+-- commands pinned to the top of the interactive search. like tags, these are
+-- kept on this machine only
+create table if not exists pinned (
+	command text primary key,
+	pinned_at integer not null
+);
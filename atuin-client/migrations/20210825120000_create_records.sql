@@ -0,0 +1,11 @@
+-- small bits of state that aren't history, such as aliases, see record.rs.
+-- value is null once deleted, and uploaded is set once the server has it
+create table if not exists records (
+	namespace text not null,
+	key text not null,
+	value text,
+	updated_at integer not null,
+	uploaded integer not null default 0,
+
+	primary key (namespace, key)
+);
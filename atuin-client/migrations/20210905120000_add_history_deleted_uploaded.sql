@@ -0,0 +1,5 @@
+-- whether a deletion has been uploaded to the sync server. deletions
+-- downloaded from the server are saved as already uploaded
+alter table history_deleted add column uploaded integer not null default 0;
+
+create index if not exists idx_history_deleted_uploaded on history_deleted(uploaded);
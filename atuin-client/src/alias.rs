@@ -0,0 +1,60 @@
+// Shell aliases, set with `atuin alias` and defined by `atuin init`. They are
+// kept as records, so they sync to every machine
+
+use eyre::{eyre, Result};
+
+pub const NAMESPACE: &str = "alias";
+
+const MAX_NAME_LENGTH: usize = 64;
+
+// Names are kept to what every supported shell accepts without quoting
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LENGTH
+        && !name.starts_with('-')
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+pub fn check(name: &str, value: &str) -> Result<()> {
+    if !valid_name(name) {
+        return Err(eyre!(
+            "invalid alias name {:?}, names are letters, numbers and - _ . only",
+            name
+        ));
+    }
+
+    // the init script defines each alias on a line of its own
+    if value.trim().is_empty() || value.contains('\n') {
+        return Err(eyre!("an alias has to be a single line, and not empty"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check, valid_name};
+
+    #[test]
+    fn test_valid_name() {
+        assert!(valid_name("ll"));
+        assert!(valid_name("git-st"));
+        assert!(valid_name("k8s.ctx"));
+
+        assert!(!valid_name(""));
+        assert!(!valid_name("-l"));
+        assert!(!valid_name("has space"));
+        assert!(!valid_name("semi;colon"));
+        assert!(!valid_name("quo'te"));
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(check("ll", "ls -l").is_ok());
+        assert!(check("ll", "").is_err());
+        assert!(check("ll", "ls\nrm -rf /").is_err());
+        assert!(check("l l", "ls").is_err());
+    }
+}
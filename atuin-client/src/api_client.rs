@@ -1,21 +1,41 @@
 use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
 
-use chrono::Utc;
 use eyre::{eyre, Result};
-use reqwest::header::{HeaderMap, AUTHORIZATION, USER_AGENT};
-use reqwest::{StatusCode, Url};
+use reqwest::header::{
+    HeaderMap, ACCEPT_ENCODING, AUTHORIZATION, CONTENT_ENCODING, CONTENT_TYPE, USER_AGENT,
+};
+use reqwest::{Certificate, Method, Proxy, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use sodiumoxide::crypto::secretbox;
 
 use atuin_common::api::{
-    AddHistoryRequest, CountResponse, LoginResponse, RegisterResponse, SyncHistoryResponse,
+    AddHistoryRequest, AddHistoryResponse, AddRecordsResponse, ChangePasswordRequest,
+    CountResponse, CreateTokenRequest, CreateTokenResponse, DeleteHistoryRequest, DeviceResponse,
+    IndexResponse, ListDevicesResponse, ListRecordsResponse, ListTokensResponse, LoginResponse,
+    RecordRequest, RegisterResponse, StatusResponse, SyncCursorResponse, SyncDeletedCursorResponse,
+    TokenResponse, TotpCodeRequest, TotpEnableResponse,
 };
+use atuin_common::compression::{self, Encoding};
 use atuin_common::utils::hash_str;
 
-use crate::encryption::{decode_key, decrypt};
+use crate::database::current_context;
+use crate::encryption::{
+    decode_key, decrypt, decrypt_record, load_encoded_key, load_pending_key, EncryptedHistory,
+};
 use crate::history::History;
+use crate::record::Record;
+use crate::settings::Settings;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+// Idempotent requests are tried again this many times, if the server can't be
+// reached or is briefly unavailable. The wait doubles after each attempt
+const MAX_RETRIES: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
 // TODO: remove all references to the encryption key from this
 // It should be handled *elsewhere*
 
@@ -24,34 +44,219 @@ pub struct Client<'a> {
     token: &'a str,
     key: secretbox::Key,
     client: reqwest::Client,
+
+    // the new key, while one is being rotated to. the server has some history
+    // encrypted with each until the rotation is finished
+    pending_key: Option<secretbox::Key>,
+
+    // the server tells us in its responses if it can take compressed bodies
+    request_encoding: Mutex<Option<Encoding>>,
+}
+
+// Responses from a server, or the proxy in front of it, that is down for a
+// moment
+fn unavailable(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+// Everything from the settings that the async and blocking clients both need
+struct HttpOptions {
+    // 0 in the settings is no timeout
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+
+    // trusted as well as the usual roots
+    certificates: Vec<Certificate>,
+    verify_tls: bool,
+
+    // without one, the proxy comes from HTTP_PROXY and HTTPS_PROXY
+    proxy: Option<Proxy>,
+    bypass_proxy: bool,
+}
+
+impl HttpOptions {
+    fn new(settings: &Settings) -> Result<Self> {
+        let secs = |s: u64| (s > 0).then(|| Duration::from_secs(s));
+
+        let certificates = if settings.ca_cert_path.is_empty() {
+            Vec::new()
+        } else {
+            let pem = std::fs::read_to_string(settings.ca_cert_path.as_str()).map_err(|e| {
+                eyre!(
+                    "failed to read ca_cert_path {}: {}",
+                    settings.ca_cert_path,
+                    e
+                )
+            })?;
+
+            split_pem(pem.as_str())
+                .iter()
+                .map(|c| Certificate::from_pem(c.as_bytes()))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+
+        if !settings.verify_tls {
+            warn!("verify_tls is off, the server's certificate is not being checked");
+        }
+
+        // reqwest reads HTTP_PROXY and HTTPS_PROXY itself, but NO_PROXY has to
+        // apply to the proxy setting too
+        let host = Url::parse(settings.sync_address.as_str())
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_string))
+            .unwrap_or_default();
+
+        let no_proxy = std::env::var("NO_PROXY")
+            .or_else(|_| std::env::var("no_proxy"))
+            .unwrap_or_default();
+
+        let proxy = if settings.proxy.is_empty() {
+            None
+        } else {
+            Some(
+                Proxy::all(settings.proxy.as_str())
+                    .map_err(|e| eyre!("invalid proxy {}: {}", settings.proxy, e))?,
+            )
+        };
+
+        Ok(Self {
+            timeout: secs(settings.network_timeout),
+            connect_timeout: secs(settings.network_connect_timeout),
+            certificates,
+            verify_tls: settings.verify_tls,
+            proxy,
+            bypass_proxy: bypass_proxy(host.as_str(), no_proxy.as_str()),
+        })
+    }
+}
+
+// Whether a host is in a NO_PROXY list, such as "localhost,.internal,10.0.0.1".
+// A domain covers its subdomains, with or without the leading dot
+fn bypass_proxy(host: &str, no_proxy: &str) -> bool {
+    let host = host.to_lowercase();
+
+    no_proxy
+        .split(',')
+        .map(|entry| entry.trim().trim_start_matches('.').to_lowercase())
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| {
+            entry == "*" || host == entry || host.ends_with(format!(".{}", entry).as_str())
+        })
+}
+
+// A CA bundle is usually several certificates, one after the other
+fn split_pem(pem: &str) -> Vec<String> {
+    const END: &str = "-----END CERTIFICATE-----";
+
+    pem.split_inclusive(END)
+        .filter(|c| c.contains(END))
+        .map(|c| c.trim().to_string())
+        .collect()
+}
+
+fn http_client(settings: &Settings) -> Result<reqwest::Client> {
+    let options = HttpOptions::new(settings)?;
+    let mut builder = reqwest::Client::builder().danger_accept_invalid_certs(!options.verify_tls);
+
+    if let Some(t) = options.timeout {
+        builder = builder.timeout(t);
+    }
+
+    if let Some(t) = options.connect_timeout {
+        builder = builder.connect_timeout(t);
+    }
+
+    for cert in options.certificates {
+        builder = builder.add_root_certificate(cert);
+    }
+
+    if options.bypass_proxy {
+        builder = builder.no_proxy();
+    } else if let Some(proxy) = options.proxy {
+        builder = builder.proxy(proxy);
+    }
+
+    Ok(builder.build()?)
+}
+
+fn blocking_http_client(settings: &Settings) -> Result<reqwest::blocking::Client> {
+    let options = HttpOptions::new(settings)?;
+    let mut builder = reqwest::blocking::Client::builder()
+        .timeout(options.timeout)
+        .danger_accept_invalid_certs(!options.verify_tls);
+
+    if let Some(t) = options.connect_timeout {
+        builder = builder.connect_timeout(t);
+    }
+
+    for cert in options.certificates {
+        builder = builder.add_root_certificate(cert);
+    }
+
+    if options.bypass_proxy {
+        builder = builder.no_proxy();
+    } else if let Some(proxy) = options.proxy {
+        builder = builder.proxy(proxy);
+    }
+
+    Ok(builder.build()?)
+}
+
+// This machine as the server knows it. Like the hostnames on history, only
+// the hash is ever sent
+pub fn this_host() -> String {
+    hash_str(current_context().hostname.as_str())
 }
 
 pub fn register(
-    address: &str,
+    settings: &Settings,
     username: &str,
     email: &str,
     password: &str,
+    invite: Option<&str>,
+    device: Option<&str>,
 ) -> Result<RegisterResponse> {
+    let host = this_host();
+
     let mut map = HashMap::new();
     map.insert("username", username);
     map.insert("email", email);
     map.insert("password", password);
+    map.insert("host", host.as_str());
 
-    let url = format!("{}/user/{}", address, username);
-    let resp = reqwest::blocking::get(url)?;
+    if let Some(invite) = invite {
+        map.insert("invite", invite);
+    }
+
+    if let Some(device) = device {
+        map.insert("device", device);
+    }
+
+    let client = blocking_http_client(settings)?;
+
+    let url = format!("{}/user/{}", settings.sync_address, username);
+    let resp = client.get(url).send()?;
 
     if resp.status().is_success() {
         return Err(eyre!("username already in use"));
     }
 
-    let url = format!("{}/register", address);
-    let client = reqwest::blocking::Client::new();
+    let url = format!("{}/register", settings.sync_address);
     let resp = client
         .post(url)
         .header(USER_AGENT, format!("atuin/{}", VERSION))
         .json(&map)
         .send()?;
 
+    if resp.status() == StatusCode::FORBIDDEN {
+        return Err(eyre!(
+            "this server is not open for registrations, an invite is required"
+        ));
+    }
+
     if !resp.status().is_success() {
         return Err(eyre!("failed to register user"));
     }
@@ -60,13 +265,30 @@ pub fn register(
     Ok(session)
 }
 
-pub fn login(address: &str, username: &str, password: &str) -> Result<LoginResponse> {
+pub fn login(
+    settings: &Settings,
+    username: &str,
+    password: &str,
+    totp: Option<&str>,
+    device: Option<&str>,
+) -> Result<LoginResponse> {
+    let host = this_host();
+
     let mut map = HashMap::new();
     map.insert("username", username);
     map.insert("password", password);
+    map.insert("host", host.as_str());
+
+    if let Some(totp) = totp {
+        map.insert("totp", totp);
+    }
 
-    let url = format!("{}/login", address);
-    let client = reqwest::blocking::Client::new();
+    if let Some(device) = device {
+        map.insert("device", device);
+    }
+
+    let url = format!("{}/login", settings.sync_address);
+    let client = blocking_http_client(settings)?;
 
     let resp = client
         .post(url)
@@ -74,6 +296,22 @@ pub fn login(address: &str, username: &str, password: &str) -> Result<LoginRespo
         .json(&map)
         .send()?;
 
+    // only sent once the password is right, if 2fa is enabled
+    if resp.status() == StatusCode::UNAUTHORIZED {
+        return match totp {
+            Some(_) => Err(eyre!("invalid 2fa code")),
+            None => Err(eyre!(
+                "2fa is enabled for this account, give a code with --code"
+            )),
+        };
+    }
+
+    if resp.status() == StatusCode::FORBIDDEN {
+        return Err(eyre!(
+            "this device has been revoked, and can no longer log in"
+        ));
+    }
+
     if resp.status() != reqwest::StatusCode::OK {
         return Err(eyre!("invalid login details"));
     }
@@ -82,16 +320,152 @@ pub fn login(address: &str, username: &str, password: &str) -> Result<LoginRespo
     Ok(session)
 }
 
+// Whether a request failed because the server couldn't be reached at all, as
+// opposed to the server turning it down
+pub fn is_unreachable(e: &eyre::Report) -> bool {
+    e.chain()
+        .filter_map(|e| e.downcast_ref::<reqwest::Error>())
+        .any(|e| e.is_connect() || e.is_timeout())
+}
+
+// Check the server is up, without needing to be logged in
+pub async fn ping(settings: &Settings) -> Result<()> {
+    let resp = http_client(settings)?
+        .get(settings.sync_address.as_str())
+        .header(USER_AGENT, format!("atuin/{}", VERSION))
+        .timeout(std::time::Duration::from_secs(5))
+        .send()
+        .await?;
+
+    if !resp.status().is_success() {
+        return Err(eyre!("the server responded with {}", resp.status()));
+    }
+
+    Ok(())
+}
+
 impl<'a> Client<'a> {
-    pub fn new(sync_addr: &'a str, token: &'a str, key: String) -> Result<Self> {
+    pub fn new(settings: &'a Settings) -> Result<Self> {
         Ok(Client {
-            sync_addr,
-            token,
-            key: decode_key(key)?,
-            client: reqwest::Client::new(),
+            sync_addr: settings.sync_address.as_str(),
+            token: settings.session_token.as_str(),
+            key: decode_key(load_encoded_key(settings)?)?,
+            client: http_client(settings)?,
+            pending_key: load_pending_key(settings)?,
+            request_encoding: Mutex::new(None),
         })
     }
 
+    fn decrypt(&self, h: &EncryptedHistory) -> Result<History> {
+        decrypt(h, &self.key).or_else(|e| match &self.pending_key {
+            Some(key) => decrypt(h, key),
+            None => Err(e),
+        })
+    }
+
+    fn decrypt_record(&self, r: &EncryptedHistory) -> Result<Record> {
+        decrypt_record(r, &self.key).or_else(|e| match &self.pending_key {
+            Some(key) => decrypt_record(r, key),
+            None => Err(e),
+        })
+    }
+
+    // Send a request. Idempotent ones are retried, with a growing wait, when
+    // the server can't be reached or responds that it is briefly unavailable
+    async fn send(&self, req: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let req = req.build()?;
+
+        let idempotent = matches!(
+            *req.method(),
+            Method::GET | Method::HEAD | Method::PUT | Method::DELETE
+        );
+
+        let mut delay = RETRY_DELAY;
+
+        for _ in 0..MAX_RETRIES {
+            // a body that can't be cloned can only be sent once
+            let attempt = match req.try_clone() {
+                Some(r) if idempotent => r,
+                _ => break,
+            };
+
+            match self.client.execute(attempt).await {
+                Ok(resp) if !unavailable(resp.status()) => return Ok(resp),
+                Ok(resp) => debug!("server responded with {}, retrying", resp.status()),
+                Err(e) if e.is_connect() || e.is_timeout() => debug!("{}, retrying", e),
+                Err(e) => return Err(e.into()),
+            }
+
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+
+        Ok(self.client.execute(req).await?)
+    }
+
+    fn learn_encoding(&self, resp: &reqwest::Response) {
+        if let Some(accept) = resp
+            .headers()
+            .get(ACCEPT_ENCODING)
+            .and_then(|h| h.to_str().ok())
+        {
+            *self.request_encoding.lock().unwrap() = Encoding::negotiate(accept);
+        }
+    }
+
+    // Serialize a request body, compressing it if the server accepts that
+    fn json_body(
+        &self,
+        req: reqwest::RequestBuilder,
+        body: &impl Serialize,
+    ) -> Result<reqwest::RequestBuilder> {
+        let body = serde_json::to_vec(body)?;
+        let req = req.header(CONTENT_TYPE, "application/json");
+
+        match *self.request_encoding.lock().unwrap() {
+            Some(e) if body.len() >= compression::MIN_SIZE => Ok(req
+                .header(CONTENT_ENCODING, e.as_str())
+                .body(e.encode(&body)?)),
+            _ => Ok(req.body(body)),
+        }
+    }
+
+    async fn decode_json<T: DeserializeOwned>(resp: reqwest::Response) -> Result<T> {
+        let encoding = resp
+            .headers()
+            .get(CONTENT_ENCODING)
+            .and_then(|h| h.to_str().ok())
+            .and_then(Encoding::from_name);
+
+        let body = resp.bytes().await?;
+
+        let body = match encoding {
+            Some(e) => e.decode(&body)?,
+            None => body.to_vec(),
+        };
+
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    // The server's version, and the oldest client it supports. Servers from
+    // before this was added only return some text, so give None for those
+    pub async fn index(&self) -> Result<Option<IndexResponse>> {
+        let url = Url::parse(self.sync_addr)?;
+
+        let req = self
+            .client
+            .get(url)
+            .header(USER_AGENT, format!("atuin/{}", VERSION));
+
+        let resp = self.send(req).await?;
+
+        if resp.status() != StatusCode::OK {
+            return Err(eyre!("the server responded with {}", resp.status()));
+        }
+
+        Ok(resp.json::<IndexResponse>().await.ok())
+    }
+
     pub async fn count(&self) -> Result<i64> {
         let url = format!("{}/sync/count", self.sync_addr);
         let url = Url::parse(url.as_str())?;
@@ -101,13 +475,13 @@ impl<'a> Client<'a> {
         let mut headers = HeaderMap::new();
         headers.insert(AUTHORIZATION, token);
 
-        let resp = self
+        let req = self
             .client
             .get(url)
             .header(USER_AGENT, format!("atuin/{}", VERSION))
-            .headers(headers)
-            .send()
-            .await?;
+            .headers(headers);
+
+        let resp = self.send(req).await?;
 
         if resp.status() != StatusCode::OK {
             return Err(eyre!("failed to get count (are you logged in?)"));
@@ -118,55 +492,557 @@ impl<'a> Client<'a> {
         Ok(count.count)
     }
 
-    pub async fn get_history(
+    pub async fn status(&self) -> Result<StatusResponse> {
+        let url = format!("{}/sync/status", self.sync_addr);
+        let url = Url::parse(url.as_str())?;
+        let token = format!("Token {}", self.token);
+        let token = token.parse()?;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, token);
+
+        let req = self
+            .client
+            .get(url)
+            .header(USER_AGENT, format!("atuin/{}", VERSION))
+            .headers(headers);
+
+        let resp = self.send(req).await?;
+
+        if resp.status() != StatusCode::OK {
+            return Err(eyre!("failed to get status (are you logged in?)"));
+        }
+
+        let status = resp.json::<StatusResponse>().await?;
+
+        Ok(status)
+    }
+
+    // fetch the next page of history after the given cursor, returning it
+    // along with the cursor to ask for next time
+    pub async fn get_history_after(
+        &self,
+        cursor: i64,
+        host: Option<String>,
+    ) -> Result<(Vec<History>, i64)> {
+        let (history, cursor) = self.get_raw_history_after(cursor, host).await?;
+
+        // most likely the key was rotated on another machine
+        let history = history
+            .iter()
+            .map(|h| {
+                serde_json::from_str(h)
+                    .map_err(eyre::Report::from)
+                    .and_then(|h| self.decrypt(&h))
+                    .map_err(|_| {
+                        eyre!("failed to decrypt history from the server. if your key was rotated on another machine, import the new key with atuin key import")
+                    })
+            })
+            .collect::<Result<Vec<History>>>()?;
+
+        Ok((history, cursor))
+    }
+
+    // the same, but as the server stores it, still encrypted
+    pub async fn get_raw_history_after(
         &self,
-        sync_ts: chrono::DateTime<Utc>,
-        history_ts: chrono::DateTime<Utc>,
+        cursor: i64,
         host: Option<String>,
-    ) -> Result<Vec<History>> {
+    ) -> Result<(Vec<String>, i64)> {
         let host = match host {
             None => hash_str(&format!("{}:{}", whoami::hostname(), whoami::username())),
             Some(h) => h,
         };
 
         let url = format!(
-            "{}/sync/history?sync_ts={}&history_ts={}&host={}",
-            self.sync_addr,
-            urlencoding::encode(sync_ts.to_rfc3339().as_str()),
-            urlencoding::encode(history_ts.to_rfc3339().as_str()),
-            host,
+            "{}/sync/cursor?cursor={}&host={}",
+            self.sync_addr, cursor, host,
         );
 
-        let resp = self
+        let req = self
             .client
             .get(url)
             .header(AUTHORIZATION, format!("Token {}", self.token))
             .header(USER_AGENT, format!("atuin/{}", VERSION))
-            .send()
-            .await?;
+            .header(ACCEPT_ENCODING, compression::ACCEPT);
 
-        let history = resp.json::<SyncHistoryResponse>().await?;
-        let history = history
-            .history
-            .iter()
-            .map(|h| serde_json::from_str(h).expect("invalid base64"))
-            .map(|h| decrypt(&h, &self.key).expect("failed to decrypt history! check your key"))
-            .collect();
+        let resp = self.send(req).await?;
+
+        if resp.status() != StatusCode::OK {
+            return Err(eyre!("failed to get history"));
+        }
 
-        Ok(history)
+        self.learn_encoding(&resp);
+
+        let resp = Self::decode_json::<SyncCursorResponse>(resp).await?;
+
+        Ok((resp.history, resp.cursor))
     }
 
-    pub async fn post_history(&self, history: &[AddHistoryRequest]) -> Result<()> {
+    // returns the ids of any history the server refused to store
+    pub async fn post_history(&self, history: &[AddHistoryRequest]) -> Result<Vec<String>> {
         let url = format!("{}/history", self.sync_addr);
         let url = Url::parse(url.as_str())?;
 
-        self.client
+        let req = self
+            .client
             .post(url)
+            .header(AUTHORIZATION, format!("Token {}", self.token))
+            .header(USER_AGENT, format!("atuin/{}", VERSION));
+
+        let resp = self.send(self.json_body(req, &history)?).await?;
+
+        if resp.status() == StatusCode::FORBIDDEN {
+            return Err(eyre!(
+                "this device has been revoked, and can no longer sync"
+            ));
+        }
+
+        if !resp.status().is_success() {
+            return Err(eyre!("failed to upload history"));
+        }
+
+        self.learn_encoding(&resp);
+
+        Ok(Self::rejected_history(resp).await)
+    }
+
+    // replace history already on the server, such as after changing key
+    pub async fn put_history(&self, history: &[AddHistoryRequest]) -> Result<Vec<String>> {
+        let url = format!("{}/history", self.sync_addr);
+        let url = Url::parse(url.as_str())?;
+
+        let req = self
+            .client
+            .put(url)
+            .header(AUTHORIZATION, format!("Token {}", self.token))
+            .header(USER_AGENT, format!("atuin/{}", VERSION));
+
+        let resp = self.send(self.json_body(req, &history)?).await?;
+
+        if !resp.status().is_success() {
+            return Err(eyre!("failed to replace history"));
+        }
+
+        self.learn_encoding(&resp);
+
+        Ok(Self::rejected_history(resp).await)
+    }
+
+    // older servers reply with an empty body
+    async fn rejected_history(resp: reqwest::Response) -> Vec<String> {
+        Self::decode_json::<AddHistoryResponse>(resp)
+            .await
+            .map_or_else(|_| Vec::new(), |r| r.rejected)
+    }
+
+    pub async fn delete_history(&self, history: &[DeleteHistoryRequest]) -> Result<()> {
+        let url = format!("{}/history", self.sync_addr);
+        let url = Url::parse(url.as_str())?;
+
+        let req = self
+            .client
+            .delete(url)
             .json(history)
             .header(AUTHORIZATION, format!("Token {}", self.token))
+            .header(USER_AGENT, format!("atuin/{}", VERSION));
+
+        let resp = self.send(req).await?;
+
+        if !resp.status().is_success() {
+            return Err(eyre!("failed to delete history"));
+        }
+
+        Ok(())
+    }
+
+    // fetch the next page of deletions after the given cursor, returning
+    // them along with the cursor to ask for next time
+    pub async fn get_deleted_after(&self, cursor: i64) -> Result<(Vec<String>, i64)> {
+        let url = format!("{}/sync/deleted/cursor?cursor={}", self.sync_addr, cursor);
+
+        let req = self
+            .client
+            .get(url)
+            .header(AUTHORIZATION, format!("Token {}", self.token))
+            .header(USER_AGENT, format!("atuin/{}", VERSION));
+
+        let resp = self.send(req).await?;
+
+        if resp.status() != StatusCode::OK {
+            return Err(eyre!("failed to get deleted history"));
+        }
+
+        let resp = resp.json::<SyncDeletedCursorResponse>().await?;
+
+        Ok((resp.deleted, resp.cursor))
+    }
+
+    // Every record the server has, decrypted. None if the server is too old to
+    // store records. Any that can't be decrypted, such as those from before a
+    // key was rotated, are left out
+    pub async fn get_records(&self) -> Result<Option<Vec<Record>>> {
+        let url = format!("{}/records", self.sync_addr);
+        let url = Url::parse(url.as_str())?;
+
+        let req = self
+            .client
+            .get(url)
+            .header(AUTHORIZATION, format!("Token {}", self.token))
             .header(USER_AGENT, format!("atuin/{}", VERSION))
-            .send()
-            .await?;
+            .header(ACCEPT_ENCODING, compression::ACCEPT);
+
+        let resp = self.send(req).await?;
+
+        if matches!(
+            resp.status(),
+            StatusCode::NOT_FOUND | StatusCode::IM_A_TEAPOT
+        ) {
+            return Ok(None);
+        }
+
+        if resp.status() != StatusCode::OK {
+            return Err(eyre!("failed to get records"));
+        }
+
+        self.learn_encoding(&resp);
+
+        let resp = Self::decode_json::<ListRecordsResponse>(resp).await?;
+
+        let records = resp
+            .records
+            .iter()
+            .filter_map(|r| {
+                let record = serde_json::from_str(r.data.as_str())
+                    .map_err(eyre::Report::from)
+                    .and_then(|data| self.decrypt_record(&data));
+
+                match record {
+                    Ok(record) => Some(record),
+                    Err(e) => {
+                        warn!("skipping record {}: {}", r.key, e);
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        Ok(Some(records))
+    }
+
+    // returns the keys of any records the server refused to store
+    pub async fn post_records(&self, records: &[RecordRequest]) -> Result<Vec<String>> {
+        let url = format!("{}/records", self.sync_addr);
+        let url = Url::parse(url.as_str())?;
+
+        let req = self
+            .client
+            .post(url)
+            .header(AUTHORIZATION, format!("Token {}", self.token))
+            .header(USER_AGENT, format!("atuin/{}", VERSION));
+
+        let resp = self.send(self.json_body(req, &records)?).await?;
+
+        if matches!(
+            resp.status(),
+            StatusCode::NOT_FOUND | StatusCode::IM_A_TEAPOT
+        ) {
+            return Err(eyre!(
+                "the server is too old to sync anything but history, it needs upgrading"
+            ));
+        }
+
+        if resp.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            return Err(eyre!(
+                "the server refused a record as too large. delete it, or upgrade the server to skip it"
+            ));
+        }
+
+        if !resp.status().is_success() {
+            return Err(eyre!("failed to upload records"));
+        }
+
+        self.learn_encoding(&resp);
+
+        let rejected = Self::decode_json::<AddRecordsResponse>(resp)
+            .await
+            .map_or_else(|_| Vec::new(), |r| r.rejected);
+
+        Ok(rejected)
+    }
+
+    pub async fn delete_account(&self) -> Result<()> {
+        let url = format!("{}/account", self.sync_addr);
+        let url = Url::parse(url.as_str())?;
+
+        let req = self
+            .client
+            .delete(url)
+            .header(AUTHORIZATION, format!("Token {}", self.token))
+            .header(USER_AGENT, format!("atuin/{}", VERSION));
+
+        let resp = self.send(req).await?;
+
+        if !resp.status().is_success() {
+            return Err(eyre!("failed to delete account"));
+        }
+
+        Ok(())
+    }
+
+    pub async fn change_password(&self, current_password: &str, new_password: &str) -> Result<()> {
+        let url = format!("{}/account/password", self.sync_addr);
+        let url = Url::parse(url.as_str())?;
+
+        let req = self
+            .client
+            .post(url)
+            .json(&ChangePasswordRequest {
+                current_password: current_password.to_string(),
+                new_password: new_password.to_string(),
+            })
+            .header(AUTHORIZATION, format!("Token {}", self.token))
+            .header(USER_AGENT, format!("atuin/{}", VERSION));
+
+        let resp = self.send(req).await?;
+
+        if resp.status() == StatusCode::UNAUTHORIZED {
+            return Err(eyre!("current password is incorrect"));
+        }
+
+        if !resp.status().is_success() {
+            return Err(eyre!("failed to change password"));
+        }
+
+        Ok(())
+    }
+
+    // Start setting up 2fa. It isn't enabled until confirm_totp is called
+    // with a code for the secret
+    pub async fn enable_totp(&self) -> Result<TotpEnableResponse> {
+        let url = format!("{}/account/2fa", self.sync_addr);
+        let url = Url::parse(url.as_str())?;
+
+        let req = self
+            .client
+            .post(url)
+            .header(AUTHORIZATION, format!("Token {}", self.token))
+            .header(USER_AGENT, format!("atuin/{}", VERSION));
+
+        let resp = self.send(req).await?;
+
+        if resp.status() == StatusCode::CONFLICT {
+            return Err(eyre!("2fa is already enabled"));
+        }
+
+        if !resp.status().is_success() {
+            return Err(eyre!("failed to enable 2fa"));
+        }
+
+        let totp = resp.json::<TotpEnableResponse>().await?;
+
+        Ok(totp)
+    }
+
+    pub async fn confirm_totp(&self, code: &str) -> Result<()> {
+        let url = format!("{}/account/2fa/confirm", self.sync_addr);
+        let url = Url::parse(url.as_str())?;
+
+        let req = self
+            .client
+            .post(url)
+            .json(&TotpCodeRequest {
+                code: code.to_string(),
+            })
+            .header(AUTHORIZATION, format!("Token {}", self.token))
+            .header(USER_AGENT, format!("atuin/{}", VERSION));
+
+        let resp = self.send(req).await?;
+
+        if resp.status() == StatusCode::UNAUTHORIZED {
+            return Err(eyre!("invalid 2fa code"));
+        }
+
+        if !resp.status().is_success() {
+            return Err(eyre!("failed to enable 2fa"));
+        }
+
+        Ok(())
+    }
+
+    pub async fn disable_totp(&self, code: &str) -> Result<()> {
+        let url = format!("{}/account/2fa", self.sync_addr);
+        let url = Url::parse(url.as_str())?;
+
+        let req = self
+            .client
+            .delete(url)
+            .json(&TotpCodeRequest {
+                code: code.to_string(),
+            })
+            .header(AUTHORIZATION, format!("Token {}", self.token))
+            .header(USER_AGENT, format!("atuin/{}", VERSION));
+
+        let resp = self.send(req).await?;
+
+        if resp.status() == StatusCode::CONFLICT {
+            return Err(eyre!("2fa is not enabled"));
+        }
+
+        if resp.status() == StatusCode::UNAUTHORIZED {
+            return Err(eyre!("invalid 2fa code"));
+        }
+
+        if !resp.status().is_success() {
+            return Err(eyre!("failed to disable 2fa"));
+        }
+
+        Ok(())
+    }
+
+    pub async fn list_tokens(&self) -> Result<Vec<TokenResponse>> {
+        let url = format!("{}/account/tokens", self.sync_addr);
+        let url = Url::parse(url.as_str())?;
+
+        let req = self
+            .client
+            .get(url)
+            .header(AUTHORIZATION, format!("Token {}", self.token))
+            .header(USER_AGENT, format!("atuin/{}", VERSION));
+
+        let resp = self.send(req).await?;
+
+        if !resp.status().is_success() {
+            return Err(eyre!("failed to list tokens"));
+        }
+
+        let tokens = resp.json::<ListTokensResponse>().await?;
+
+        Ok(tokens.tokens)
+    }
+
+    pub async fn create_token(&self, name: &str) -> Result<String> {
+        let url = format!("{}/account/tokens", self.sync_addr);
+        let url = Url::parse(url.as_str())?;
+
+        let req = self
+            .client
+            .post(url)
+            .json(&CreateTokenRequest {
+                name: name.to_string(),
+            })
+            .header(AUTHORIZATION, format!("Token {}", self.token))
+            .header(USER_AGENT, format!("atuin/{}", VERSION));
+
+        let resp = self.send(req).await?;
+
+        if resp.status() == StatusCode::CONFLICT {
+            return Err(eyre!("a token named {} already exists", name));
+        }
+
+        if resp.status() == StatusCode::BAD_REQUEST {
+            return Err(eyre!("token names must be between 1 and 64 characters"));
+        }
+
+        if !resp.status().is_success() {
+            return Err(eyre!("failed to create token"));
+        }
+
+        let token = resp.json::<CreateTokenResponse>().await?;
+
+        Ok(token.token)
+    }
+
+    pub async fn delete_token(&self, name: &str) -> Result<()> {
+        let url = format!(
+            "{}/account/tokens/{}",
+            self.sync_addr,
+            urlencoding::encode(name)
+        );
+        let url = Url::parse(url.as_str())?;
+
+        let req = self
+            .client
+            .delete(url)
+            .header(AUTHORIZATION, format!("Token {}", self.token))
+            .header(USER_AGENT, format!("atuin/{}", VERSION));
+
+        let resp = self.send(req).await?;
+
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Err(eyre!("no token named {}", name));
+        }
+
+        if !resp.status().is_success() {
+            return Err(eyre!("failed to revoke token"));
+        }
+
+        Ok(())
+    }
+
+    pub async fn list_devices(&self) -> Result<Vec<DeviceResponse>> {
+        let url = format!("{}/account/devices", self.sync_addr);
+        let url = Url::parse(url.as_str())?;
+
+        let req = self
+            .client
+            .get(url)
+            .header(AUTHORIZATION, format!("Token {}", self.token))
+            .header(USER_AGENT, format!("atuin/{}", VERSION));
+
+        let resp = self.send(req).await?;
+
+        if !resp.status().is_success() {
+            return Err(eyre!("failed to list devices"));
+        }
+
+        let devices = resp.json::<ListDevicesResponse>().await?;
+
+        Ok(devices.devices)
+    }
+
+    pub async fn revoke_device(&self, host: &str) -> Result<()> {
+        let url = format!(
+            "{}/account/devices/{}",
+            self.sync_addr,
+            urlencoding::encode(host)
+        );
+        let url = Url::parse(url.as_str())?;
+
+        let req = self
+            .client
+            .delete(url)
+            .header(AUTHORIZATION, format!("Token {}", self.token))
+            .header(USER_AGENT, format!("atuin/{}", VERSION));
+
+        let resp = self.send(req).await?;
+
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Err(eyre!("no device {}", host));
+        }
+
+        if !resp.status().is_success() {
+            return Err(eyre!("failed to revoke device"));
+        }
+
+        Ok(())
+    }
+
+    pub async fn logout(&self) -> Result<()> {
+        let url = format!("{}/logout", self.sync_addr);
+        let url = Url::parse(url.as_str())?;
+
+        let req = self
+            .client
+            .post(url)
+            .header(AUTHORIZATION, format!("Token {}", self.token))
+            .header(USER_AGENT, format!("atuin/{}", VERSION));
+
+        let resp = self.send(req).await?;
+
+        if !resp.status().is_success() {
+            return Err(eyre!("failed to logout"));
+        }
 
         Ok(())
     }
@@ -177,13 +1053,13 @@ impl<'a> Client<'a> {
         map.insert("password", password);
 
         let url = format!("{}/login", self.sync_addr);
-        let resp = self
+        let req = self
             .client
             .post(url)
             .json(&map)
-            .header(USER_AGENT, format!("atuin/{}", VERSION))
-            .send()
-            .await?;
+            .header(USER_AGENT, format!("atuin/{}", VERSION));
+
+        let resp = self.send(req).await?;
 
         if resp.status() != reqwest::StatusCode::OK {
             return Err(eyre!("invalid login details"));
@@ -194,3 +1070,46 @@ impl<'a> Client<'a> {
         Ok(session)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{bypass_proxy, split_pem};
+
+    #[test]
+    fn test_bypass_proxy() {
+        let no_proxy = "localhost, .internal,example.com,10.0.0.1";
+
+        assert!(bypass_proxy("localhost", no_proxy));
+        assert!(bypass_proxy("atuin.internal", no_proxy));
+        assert!(bypass_proxy("example.com", no_proxy));
+        assert!(bypass_proxy("api.EXAMPLE.com", no_proxy));
+        assert!(bypass_proxy("10.0.0.1", no_proxy));
+
+        assert!(!bypass_proxy("api.atuin.sh", no_proxy));
+        assert!(!bypass_proxy("notexample.com", no_proxy));
+        assert!(!bypass_proxy("api.atuin.sh", ""));
+
+        assert!(bypass_proxy("api.atuin.sh", "*"));
+    }
+
+    #[test]
+    fn test_split_pem() {
+        let bundle = "# internal root
+-----BEGIN CERTIFICATE-----
+AAAA
+-----END CERTIFICATE-----
+-----BEGIN CERTIFICATE-----
+BBBB
+-----END CERTIFICATE-----
+";
+
+        let certs = split_pem(bundle);
+
+        assert_eq!(certs.len(), 2);
+        assert!(certs[0].contains("AAAA"));
+        assert!(certs[1].starts_with("-----BEGIN CERTIFICATE-----"));
+        assert!(certs[1].contains("BBBB"));
+
+        assert!(split_pem("").is_empty());
+    }
+}
@@ -0,0 +1,281 @@
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use eyre::{eyre, Result};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::signal::unix::{signal, Signal, SignalKind};
+
+use crate::database::{Context, Database};
+use crate::history::History;
+use crate::settings::{FilterMode, SearchMode, Settings};
+use crate::sync;
+use crate::tags::TagRules;
+
+// The daemon speaks newline delimited JSON over a unix socket. Each line sent
+// is a Request, and is answered with a single Response line.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Request {
+    Start {
+        history: History,
+    },
+    End {
+        id: String,
+        exit: i64,
+        duration: Option<i64>,
+    },
+    Search {
+        limit: Option<i64>,
+        search_mode: SearchMode,
+        filter_mode: FilterMode,
+        context: Context,
+        query: String,
+        unique: bool,
+    },
+    Sync {
+        force: bool,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Response {
+    Ok,
+    History(Vec<History>),
+    Error(String),
+}
+
+pub fn socket_path() -> PathBuf {
+    atuin_common::utils::data_dir().join("atuin.sock")
+}
+
+pub struct Client {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    write: OwnedWriteHalf,
+}
+
+impl Client {
+    // Returns None if the daemon is not running, so callers can fall back to
+    // using the database directly
+    pub async fn connect() -> Option<Self> {
+        let stream = UnixStream::connect(socket_path()).await.ok()?;
+        let (read, write) = stream.into_split();
+
+        Some(Self {
+            lines: BufReader::new(read).lines(),
+            write,
+        })
+    }
+
+    pub async fn request(&mut self, request: &Request) -> Result<Response> {
+        let mut line = serde_json::to_string(request)?;
+        line.push('\n');
+
+        self.write.write_all(line.as_bytes()).await?;
+
+        let line = self
+            .lines
+            .next_line()
+            .await?
+            .ok_or_else(|| eyre!("daemon closed the connection"))?;
+
+        match serde_json::from_str(line.as_str())? {
+            Response::Error(e) => Err(eyre!("daemon error: {}", e)),
+            r => Ok(r),
+        }
+    }
+}
+
+struct Daemon<D> {
+    settings: Settings,
+    db: D,
+    tag_rules: TagRules,
+
+    // only run one background sync at a time
+    syncing: AtomicBool,
+}
+
+// The database is cloned for each request, so it should be cheap to clone and
+// share its connections between clones, as Sqlite does
+pub async fn serve<D>(settings: Settings, db: D) -> Result<()>
+where
+    D: Database + Clone + Send + Sync + 'static,
+{
+    let path = socket_path();
+
+    if path.exists() {
+        if UnixStream::connect(&path).await.is_ok() {
+            return Err(eyre!("the daemon is already running"));
+        }
+
+        // left behind by a daemon that did not exit cleanly
+        std::fs::remove_file(&path)?;
+    }
+
+    let listener = UnixListener::bind(&path)?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+
+    info!("listening on {:?}", path);
+
+    // the socket has to be removed on the way out, or it is left behind
+    let mut terminate = signal(SignalKind::terminate())?;
+
+    let daemon = Arc::new(Daemon {
+        tag_rules: TagRules::new(&settings),
+        settings,
+        db,
+        syncing: AtomicBool::new(false),
+    });
+
+    loop {
+        tokio::select! {
+            conn = listener.accept() => {
+                let (stream, _) = conn?;
+                let daemon = daemon.clone();
+
+                tokio::spawn(async move {
+                    if let Err(e) = handle(daemon, stream).await {
+                        warn!("daemon connection failed: {}", e);
+                    }
+                });
+            }
+
+            _ = shutdown(&mut terminate) => {
+                info!("shutting down");
+                std::fs::remove_file(&path)?;
+
+                return Ok(());
+            }
+        }
+    }
+}
+
+async fn shutdown(terminate: &mut Signal) {
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {},
+        _ = terminate.recv() => {},
+    }
+}
+
+async fn handle<D>(daemon: Arc<Daemon<D>>, stream: UnixStream) -> Result<()>
+where
+    D: Database + Clone + Send + Sync + 'static,
+{
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        let response = match serde_json::from_str::<Request>(line.as_str()) {
+            Ok(request) => respond(&daemon, request)
+                .await
+                .unwrap_or_else(|e| Response::Error(e.to_string())),
+            Err(e) => Response::Error(format!("invalid request: {}", e)),
+        };
+
+        let mut line = serde_json::to_string(&response)?;
+        line.push('\n');
+
+        write.write_all(line.as_bytes()).await?;
+    }
+
+    Ok(())
+}
+
+async fn respond<D>(daemon: &Arc<Daemon<D>>, request: Request) -> Result<Response>
+where
+    D: Database + Clone + Send + Sync + 'static,
+{
+    // the pool is shared between clones, so each request can have its own
+    let mut db = daemon.db.clone();
+
+    match request {
+        Request::Start { history } => {
+            db.save(&history).await?;
+
+            let tags = daemon.tag_rules.tags(history.command.as_str());
+            if !tags.is_empty() {
+                if let Err(e) = db.add_tags(history.id.as_str(), &tags).await {
+                    warn!("failed to tag history {}: {}", history.id, e);
+                }
+            }
+
+            Ok(Response::Ok)
+        }
+
+        Request::End { id, exit, duration } => {
+            let mut h = db.load(id.as_str()).await?;
+
+            if h.duration > 0 {
+                debug!("cannot end history - already has duration");
+                return Ok(Response::Ok);
+            }
+
+            h.end(exit, duration);
+
+            db.update(&h).await?;
+
+            if daemon.settings.should_sync()? {
+                background_sync(daemon.clone());
+            }
+
+            Ok(Response::Ok)
+        }
+
+        Request::Search {
+            limit,
+            search_mode,
+            filter_mode,
+            context,
+            query,
+            unique,
+        } => {
+            let results = db
+                .search(
+                    limit,
+                    search_mode,
+                    filter_mode,
+                    &context,
+                    query.as_str(),
+                    unique,
+                )
+                .await?;
+
+            Ok(Response::History(results))
+        }
+
+        Request::Sync { force } => {
+            sync::sync(&daemon.settings, force, &mut db).await?;
+
+            Ok(Response::Ok)
+        }
+    }
+}
+
+// Sync without making the client wait for it. The attempt is noted, so that
+// commands ending while it runs don't start another
+fn background_sync<D>(daemon: Arc<Daemon<D>>)
+where
+    D: Database + Clone + Send + Sync + 'static,
+{
+    if daemon.syncing.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    tokio::spawn(async move {
+        debug!("running periodic background sync");
+
+        if let Err(e) = Settings::save_sync_attempt() {
+            warn!("failed to save sync attempt: {}", e);
+        }
+
+        let mut db = daemon.db.clone();
+
+        if let Err(e) = sync::sync(&daemon.settings, false, &mut db).await {
+            warn!("background sync failed: {}", e);
+        }
+
+        daemon.syncing.store(false, Ordering::SeqCst);
+    });
+}
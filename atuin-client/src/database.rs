@@ -1,5 +1,7 @@
+use std::env;
 use std::path::Path;
 use std::str::FromStr;
+use std::time::Duration;
 
 use async_trait::async_trait;
 use chrono::prelude::*;
@@ -9,15 +11,51 @@ use eyre::Result;
 
 use sqlx::sqlite::{
     SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions, SqliteRow,
+    SqliteSynchronous,
 };
 use sqlx::Row;
 
+use atuin_common::utils::git_root;
+
 use super::history::History;
+use super::record::Record;
+use super::settings::{FilterMode, SearchMode};
+use super::tags;
+
+// Where a search is being run from, so results can be filtered to the
+// current host, workspace, directory or session. The workspace is the root of
+// the git repository we are in, or just the current directory outside of one
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Context {
+    pub session: String,
+    pub hostname: String,
+    pub cwd: String,
+    pub workspace: String,
+}
 
+pub fn current_context() -> Context {
+    let session = env::var("ATUIN_SESSION").unwrap_or_default();
+    let hostname = format!("{}:{}", whoami::hostname(), whoami::username());
+    let cwd = env::current_dir().map_or_else(|_| String::new(), |d| d.display().to_string());
+    let workspace =
+        git_root(Path::new(cwd.as_str())).map_or_else(|| cwd.clone(), |d| d.display().to_string());
+
+    Context {
+        session,
+        hostname,
+        cwd,
+        workspace,
+    }
+}
+
+// Where history is kept. Sqlite is the one atuin uses, but the rest of this
+// crate (sync, the daemon, import) only needs this, so another store can be
+// used with it. Every method is typed, rather than taking SQL.
 #[async_trait]
 pub trait Database {
     async fn save(&mut self, h: &History) -> Result<()>;
     async fn save_bulk(&mut self, h: &[History]) -> Result<()>;
+    async fn save_synced(&mut self, h: &[History]) -> Result<()>;
 
     async fn load(&self, id: &str) -> Result<History>;
     async fn list(&self, max: Option<usize>, unique: bool) -> Result<Vec<History>>;
@@ -28,19 +66,86 @@ pub trait Database {
     ) -> Result<Vec<History>>;
 
     async fn update(&self, h: &History) -> Result<()>;
+    async fn delete(&mut self, ids: &[String]) -> Result<()>;
+    // deletions from the sync server, that do not need uploading again
+    async fn delete_synced(&mut self, ids: &[String]) -> Result<()>;
+    async fn deleted_not_uploaded(&self) -> Result<Vec<String>>;
+    async fn set_deleted_uploaded(&mut self, ids: &[String]) -> Result<()>;
     async fn history_count(&self) -> Result<i64>;
 
+    // how many times this exact command has been ran, and how many of those
+    // failed
+    async fn command_stats(&self, command: &str) -> Result<(i64, i64)>;
+
+    async fn not_uploaded(&self, max: i64) -> Result<Vec<History>>;
+    async fn set_uploaded(&mut self, ids: &[String]) -> Result<()>;
+
     async fn first(&self) -> Result<History>;
     async fn last(&self) -> Result<History>;
     async fn before(&self, timestamp: chrono::DateTime<Utc>, count: i64) -> Result<Vec<History>>;
+    // history older than before, or past the newest keep, newest first. either
+    // limit can be left out
+    async fn expired(
+        &self,
+        before: Option<chrono::DateTime<Utc>>,
+        keep: Option<i64>,
+    ) -> Result<Vec<History>>;
+
+    // with unique, only the most recent run of each command is returned
+    async fn search(
+        &self,
+        limit: Option<i64>,
+        search_mode: SearchMode,
+        filter_mode: FilterMode,
+        context: &Context,
+        query: &str,
+        unique: bool,
+    ) -> Result<Vec<History>>;
+
+    // the commands most often ran after the previous one in the same session,
+    // preferring those ran in cwd. without a previous command, the commands
+    // most often ran in cwd
+    async fn predict(&self, previous: Option<&str>, cwd: &str, limit: i64) -> Result<Vec<String>>;
+
+    // give the space used by deleted history back to the filesystem. a store
+    // that does this by itself has nothing to do
+    async fn compact(&self) -> Result<()> {
+        Ok(())
+    }
 
-    async fn search(&self, limit: Option<i64>, query: &str) -> Result<Vec<History>>;
+    // tags are local to this machine, see tags.rs
+    async fn tags(&self, id: &str) -> Result<Vec<String>>;
+    async fn add_tags(&self, id: &str, tags: &[String]) -> Result<()>;
+    async fn remove_tags(&self, id: &str, tags: &[String]) -> Result<()>;
+    // the ids of history with every one of the tags
+    async fn tagged(&self, tags: &[String]) -> Result<Vec<String>>;
 
-    async fn query_history(&self, query: &str) -> Result<Vec<History>>;
+    // pins are by command, rather than by a single run of it
+    async fn pin(&self, command: &str) -> Result<()>;
+    async fn unpin(&self, command: &str) -> Result<()>;
+    // the most recent run of each pinned command, most recently pinned first
+    async fn pinned(&self) -> Result<Vec<History>>;
+
+    // records, see record.rs. saving one marks it to be uploaded
+    async fn save_record(&self, r: &Record) -> Result<()>;
+    // a record from the server, kept only if it is newer than ours
+    async fn save_synced_record(&self, r: &Record) -> Result<()>;
+    async fn record(&self, namespace: &str, key: &str) -> Result<Option<Record>>;
+    // the records in the namespace that haven't been deleted, by key
+    async fn records(&self, namespace: &str) -> Result<Vec<Record>>;
+    // every record, deleted or not
+    async fn all_records(&self) -> Result<Vec<Record>>;
+    async fn records_not_uploaded(&self) -> Result<Vec<Record>>;
+    async fn set_records_uploaded(&self, records: &[Record]) -> Result<()>;
 }
 
+// Database is also exported by atuin-server, so this makes it easier to use
+// both at once
+pub use self::Database as HistoryStore;
+
 // Intended for use on a developer machine and not a sync server.
 // TODO: implement IntoIterator
+#[derive(Clone)]
 pub struct Sqlite {
     pool: SqlitePool,
 }
@@ -57,8 +162,13 @@ impl Sqlite {
             }
         }
 
+        // every shell writes to the same database, often at the same moment.
+        // with WAL readers don't block writers, and writers wait their turn
+        // rather than failing with "database is locked"
         let opts = SqliteConnectOptions::from_str(path.as_os_str().to_str().unwrap())?
             .journal_mode(SqliteJournalMode::Wal)
+            .synchronous(SqliteSynchronous::Normal)
+            .busy_timeout(Duration::from_secs(10))
             .create_if_missing(true);
 
         let pool = SqlitePoolOptions::new().connect_with(opts).await?;
@@ -76,10 +186,14 @@ impl Sqlite {
         Ok(())
     }
 
-    async fn save_raw(tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>, h: &History) -> Result<()> {
+    async fn save_raw(
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        h: &History,
+        uploaded: bool,
+    ) -> Result<()> {
         sqlx::query(
-            "insert or ignore into history(id, timestamp, duration, exit, command, cwd, session, hostname)
-                values(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            "insert or ignore into history(id, timestamp, duration, exit, command, cwd, session, hostname, uploaded)
+                values(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
         )
         .bind(h.id.as_str())
         .bind(h.timestamp.timestamp_nanos())
@@ -89,12 +203,57 @@ impl Sqlite {
         .bind(h.cwd.as_str())
         .bind(h.session.as_str())
         .bind(h.hostname.as_str())
+        .bind(uploaded)
         .execute(tx)
         .await?;
 
         Ok(())
     }
 
+    // deleted history leaves a tombstone behind, so that the deletion can be
+    // synced to other machines
+    async fn delete_raw(pool: &SqlitePool, ids: &[String], uploaded: bool) -> Result<()> {
+        let deleted_at = Utc::now().timestamp_nanos();
+        let mut tx = pool.begin().await?;
+
+        for id in ids {
+            sqlx::query("delete from history where id = ?1")
+                .bind(id.as_str())
+                .execute(&mut tx)
+                .await?;
+
+            sqlx::query(
+                "insert or ignore into history_deleted(id, deleted_at, uploaded)
+                    values(?1, ?2, ?3)",
+            )
+            .bind(id.as_str())
+            .bind(deleted_at)
+            .bind(uploaded)
+            .execute(&mut tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    // The where clause for a filter mode, and the value to bind to it. This is
+    // used both in the outer query and the subquery that keeps only the newest
+    // of each command, so the columns are left unqualified
+    fn filter_clause(filter_mode: FilterMode, context: &Context) -> (&'static str, Option<String>) {
+        match filter_mode {
+            FilterMode::Global => ("", None),
+            FilterMode::Host => ("and hostname = ?2", Some(context.hostname.clone())),
+            FilterMode::Workspace => (
+                "and (cwd = ?2 or substr(cwd, 1, length(?2) + 1) = ?2 || '/')",
+                Some(context.workspace.clone()),
+            ),
+            FilterMode::Directory => ("and cwd = ?2", Some(context.cwd.clone())),
+            FilterMode::Session => ("and session = ?2", Some(context.session.clone())),
+        }
+    }
+
     fn query_history(row: SqliteRow) -> History {
         History {
             id: row.get("id"),
@@ -107,6 +266,15 @@ impl Sqlite {
             hostname: row.get("hostname"),
         }
     }
+
+    fn query_record(row: SqliteRow) -> Record {
+        Record {
+            namespace: row.get("namespace"),
+            key: row.get("key"),
+            value: row.get("value"),
+            updated_at: Utc.timestamp_nanos(row.get("updated_at")),
+        }
+    }
 }
 
 #[async_trait]
@@ -115,7 +283,7 @@ impl Database for Sqlite {
         debug!("saving history to sqlite");
 
         let mut tx = self.pool.begin().await?;
-        Self::save_raw(&mut tx, h).await?;
+        Self::save_raw(&mut tx, h, false).await?;
         tx.commit().await?;
 
         Ok(())
@@ -127,7 +295,22 @@ impl Database for Sqlite {
         let mut tx = self.pool.begin().await?;
 
         for i in h {
-            Self::save_raw(&mut tx, i).await?
+            Self::save_raw(&mut tx, i, false).await?
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    // history from the sync server, that does not need uploading again
+    async fn save_synced(&mut self, h: &[History]) -> Result<()> {
+        debug!("saving synced history to sqlite");
+
+        let mut tx = self.pool.begin().await?;
+
+        for i in h {
+            Self::save_raw(&mut tx, i, true).await?
         }
 
         tx.commit().await?;
@@ -169,6 +352,42 @@ impl Database for Sqlite {
         Ok(())
     }
 
+    async fn delete(&mut self, ids: &[String]) -> Result<()> {
+        debug!("deleting {} history items from sqlite", ids.len());
+
+        Self::delete_raw(&self.pool, ids, false).await
+    }
+
+    async fn delete_synced(&mut self, ids: &[String]) -> Result<()> {
+        debug!("deleting {} synced history items from sqlite", ids.len());
+
+        Self::delete_raw(&self.pool, ids, true).await
+    }
+
+    async fn deleted_not_uploaded(&self) -> Result<Vec<String>> {
+        let res: Vec<(String,)> =
+            sqlx::query_as("select id from history_deleted where uploaded = 0")
+                .fetch_all(&self.pool)
+                .await?;
+
+        Ok(res.into_iter().map(|r| r.0).collect())
+    }
+
+    async fn set_deleted_uploaded(&mut self, ids: &[String]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        for id in ids {
+            sqlx::query("update history_deleted set uploaded = 1 where id = ?1")
+                .bind(id.as_str())
+                .execute(&mut tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
     // make a unique list, that only shows the *newest* version of things
     async fn list(&self, max: Option<usize>, unique: bool) -> Result<Vec<History>> {
         debug!("listing history");
@@ -217,9 +436,9 @@ impl Database for Sqlite {
         let res = sqlx::query(
             "select * from history where timestamp >= ?1 and timestamp <= ?2 order by timestamp asc",
         )
-        .bind(from)
-        .bind(to)
-            .map(Self::query_history)
+        .bind(from.timestamp_nanos())
+        .bind(to.timestamp_nanos())
+        .map(Self::query_history)
         .fetch_all(&self.pool)
         .await?;
 
@@ -260,6 +479,27 @@ impl Database for Sqlite {
         Ok(res)
     }
 
+    async fn expired(
+        &self,
+        before: Option<chrono::DateTime<Utc>>,
+        keep: Option<i64>,
+    ) -> Result<Vec<History>> {
+        // a null ?1 matches nothing, and a limit of -1 keeps everything
+        let res = sqlx::query(
+            "select * from history
+            where timestamp < ?1
+            or id not in (select id from history order by timestamp desc limit ?2)
+            order by timestamp desc",
+        )
+        .bind(before.map(|b| b.timestamp_nanos()))
+        .bind(keep.unwrap_or(-1))
+        .map(Self::query_history)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(res)
+    }
+
     async fn history_count(&self) -> Result<i64> {
         let res: (i64,) = sqlx::query_as("select count(1) from history")
             .fetch_one(&self.pool)
@@ -268,24 +508,281 @@ impl Database for Sqlite {
         Ok(res.0)
     }
 
-    async fn search(&self, limit: Option<i64>, query: &str) -> Result<Vec<History>> {
+    async fn command_stats(&self, command: &str) -> Result<(i64, i64)> {
+        let res: (i64, i64) = sqlx::query_as(
+            "select count(1), coalesce(sum(exit != 0 and duration != -1), 0)
+            from history
+            where command = ?1",
+        )
+        .bind(command)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(res)
+    }
+
+    // the server never replaces history it already has, so a command that is
+    // still running waits until it ends. imported history has no duration
+    // either, and never will, so anything over a day old goes as it is
+    async fn not_uploaded(&self, max: i64) -> Result<Vec<History>> {
+        let running_since = Utc::now() - chrono::Duration::days(1);
+
+        let res = sqlx::query(
+            "select * from history
+            where uploaded = 0 and (duration >= 0 or timestamp < ?2)
+            order by timestamp asc limit ?1",
+        )
+        .bind(max)
+        .bind(running_since.timestamp_nanos())
+        .map(Self::query_history)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(res)
+    }
+
+    async fn set_uploaded(&mut self, ids: &[String]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        for id in ids {
+            sqlx::query("update history set uploaded = 1 where id = ?1")
+                .bind(id.as_str())
+                .execute(&mut tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn search(
+        &self,
+        limit: Option<i64>,
+        search_mode: SearchMode,
+        filter_mode: FilterMode,
+        context: &Context,
+        query: &str,
+        unique: bool,
+    ) -> Result<Vec<History>> {
         let query = query.to_string().replace("*", "%"); // allow wildcard char
         let limit = limit.map_or("".to_owned(), |l| format!("limit {}", l));
 
-        let res = sqlx::query(
+        let query = match search_mode {
+            SearchMode::Prefix => format!("{}%", query),
+            // every character of the query has to appear, in order. the
+            // results are ranked by the caller
+            SearchMode::Fuzzy => query
+                .split_whitespace()
+                .collect::<String>()
+                .split("")
+                .collect::<Vec<&str>>()
+                .join("%"),
+        };
+
+        let (filter, filter_value) = Self::filter_clause(filter_mode, context);
+
+        // the full text index works on trigrams, so needs at least three
+        // characters in a row to be of any use. it is only used for the outer
+        // query, the subquery already has the command index
+        let matching = if search_mode == SearchMode::Prefix
+            && query.split('%').any(|part| part.chars().count() >= 3)
+        {
+            "rowid in (select rowid from history_fts where command like ?1)"
+        } else {
+            "command like ?1"
+        };
+
+        let unique = if unique {
             format!(
-                "select * from history h
-            where command like ?1 || '%' 
-            and timestamp = (
-                    select max(timestamp) from history 
-                    where h.command = history.command
+                "and timestamp = (
+                    select max(timestamp) from history
+                    where h.command = history.command {filter}
+                )",
+                filter = filter
+            )
+        } else {
+            String::new()
+        };
+
+        let sql = format!(
+            "select * from history h
+            where {matching} {filter}
+            {unique}
+            order by timestamp desc {limit}",
+            matching = matching,
+            filter = filter,
+            unique = unique,
+            limit = limit,
+        );
+
+        let mut res = sqlx::query(sql.as_str()).bind(query);
+
+        if let Some(filter_value) = filter_value {
+            res = res.bind(filter_value);
+        }
+
+        let res = res.map(Self::query_history).fetch_all(&self.pool).await?;
+
+        Ok(res)
+    }
+
+    async fn predict(&self, previous: Option<&str>, cwd: &str, limit: i64) -> Result<Vec<String>> {
+        let res: Vec<(String,)> = match previous {
+            Some(previous) => {
+                sqlx::query_as(
+                    "select command from (
+                        select command, cwd,
+                            lag(command) over (partition by session order by timestamp) as previous
+                        from history
+                    )
+                    where previous = ?1
+                    group by command
+                    order by sum(cwd = ?2) desc, count(1) desc
+                    limit ?3",
+                )
+                .bind(previous)
+                .bind(cwd)
+                .bind(limit)
+                .fetch_all(&self.pool)
+                .await?
+            }
+            None => {
+                sqlx::query_as(
+                    "select command from history
+                    group by command
+                    order by sum(cwd = ?1) desc, count(1) desc
+                    limit ?2",
                 )
-            order by timestamp desc {}",
-                limit.clone()
+                .bind(cwd)
+                .bind(limit)
+                .fetch_all(&self.pool)
+                .await?
+            }
+        };
+
+        Ok(res.into_iter().map(|r| r.0).collect())
+    }
+
+    async fn compact(&self) -> Result<()> {
+        sqlx::query("vacuum").execute(&self.pool).await?;
+
+        // history has no integer primary key, so vacuum is free to renumber
+        // its rowids, which the full text index is keyed on
+        sqlx::query("insert into history_fts(history_fts) values('rebuild')")
+            .execute(&self.pool)
+            .await?;
+
+        // otherwise the space stays in the write ahead log until the next
+        // checkpoint
+        sqlx::query("pragma wal_checkpoint(truncate)")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn tags(&self, id: &str) -> Result<Vec<String>> {
+        let res: (String,) = sqlx::query_as("select tags from history where id = ?1")
+            .bind(id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(tags::decode(res.0.as_str()))
+    }
+
+    async fn add_tags(&self, id: &str, new: &[String]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let res: (String,) = sqlx::query_as("select tags from history where id = ?1")
+            .bind(id)
+            .fetch_one(&mut tx)
+            .await?;
+
+        let mut tags = tags::decode(res.0.as_str());
+        tags.extend_from_slice(new);
+
+        sqlx::query("update history set tags = ?2 where id = ?1")
+            .bind(id)
+            .bind(tags::encode(&tags))
+            .execute(&mut tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn remove_tags(&self, id: &str, old: &[String]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let res: (String,) = sqlx::query_as("select tags from history where id = ?1")
+            .bind(id)
+            .fetch_one(&mut tx)
+            .await?;
+
+        let mut tags = tags::decode(res.0.as_str());
+        tags.retain(|t| !old.contains(t));
+
+        sqlx::query("update history set tags = ?2 where id = ?1")
+            .bind(id)
+            .bind(tags::encode(&tags))
+            .execute(&mut tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn tagged(&self, tags: &[String]) -> Result<Vec<String>> {
+        // one placeholder for each tag
+        let clauses: String = (1..=tags.len())
+            .map(|i| format!(" and instr(tags, ?{}) > 0", i))
+            .collect();
+        let query = format!("select id from history where 1 = 1{}", clauses);
+
+        let mut query = sqlx::query_as(query.as_str());
+
+        for tag in tags {
+            query = query.bind(format!(",{},", tag));
+        }
+
+        let res: Vec<(String,)> = query.fetch_all(&self.pool).await?;
+
+        Ok(res.into_iter().map(|r| r.0).collect())
+    }
+
+    async fn pin(&self, command: &str) -> Result<()> {
+        sqlx::query("insert or ignore into pinned(command, pinned_at) values(?1, ?2)")
+            .bind(command)
+            .bind(Utc::now().timestamp_nanos())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn unpin(&self, command: &str) -> Result<()> {
+        sqlx::query("delete from pinned where command = ?1")
+            .bind(command)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn pinned(&self) -> Result<Vec<History>> {
+        let res = sqlx::query(
+            "select h.* from history h
+            join pinned p on p.command = h.command
+            where h.timestamp = (
+                select max(timestamp) from history
+                where command = h.command
             )
-            .as_str(),
+            group by h.command
+            order by p.pinned_at desc",
         )
-        .bind(query)
         .map(Self::query_history)
         .fetch_all(&self.pool)
         .await?;
@@ -293,12 +790,243 @@ impl Database for Sqlite {
         Ok(res)
     }
 
-    async fn query_history(&self, query: &str) -> Result<Vec<History>> {
-        let res = sqlx::query(query)
-            .map(Self::query_history)
+    async fn save_record(&self, r: &Record) -> Result<()> {
+        sqlx::query(
+            "insert or replace into records(namespace, key, value, updated_at, uploaded)
+                values(?1, ?2, ?3, ?4, 0)",
+        )
+        .bind(r.namespace.as_str())
+        .bind(r.key.as_str())
+        .bind(r.value.as_deref())
+        .bind(r.updated_at.timestamp_nanos())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn save_synced_record(&self, r: &Record) -> Result<()> {
+        sqlx::query(
+            "insert into records(namespace, key, value, updated_at, uploaded)
+                values(?1, ?2, ?3, ?4, 1)
+            on conflict(namespace, key) do update
+            set value = excluded.value, updated_at = excluded.updated_at, uploaded = 1
+            where records.updated_at < excluded.updated_at",
+        )
+        .bind(r.namespace.as_str())
+        .bind(r.key.as_str())
+        .bind(r.value.as_deref())
+        .bind(r.updated_at.timestamp_nanos())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn record(&self, namespace: &str, key: &str) -> Result<Option<Record>> {
+        let res = sqlx::query("select * from records where namespace = ?1 and key = ?2")
+            .bind(namespace)
+            .bind(key)
+            .map(Self::query_record)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(res)
+    }
+
+    async fn records(&self, namespace: &str) -> Result<Vec<Record>> {
+        let res = sqlx::query(
+            "select * from records
+            where namespace = ?1
+            and value is not null
+            order by key asc",
+        )
+        .bind(namespace)
+        .map(Self::query_record)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(res)
+    }
+
+    async fn all_records(&self) -> Result<Vec<Record>> {
+        let res = sqlx::query("select * from records")
+            .map(Self::query_record)
             .fetch_all(&self.pool)
             .await?;
 
         Ok(res)
     }
+
+    async fn records_not_uploaded(&self) -> Result<Vec<Record>> {
+        let res = sqlx::query("select * from records where uploaded = 0")
+            .map(Self::query_record)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(res)
+    }
+
+    // only if unchanged since, or a change made while uploading would never
+    // be uploaded itself
+    async fn set_records_uploaded(&self, records: &[Record]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        for r in records {
+            sqlx::query(
+                "update records set uploaded = 1
+                where namespace = ?1 and key = ?2 and updated_at = ?3",
+            )
+            .bind(r.namespace.as_str())
+            .bind(r.key.as_str())
+            .bind(r.updated_at.timestamp_nanos())
+            .execute(&mut tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+
+    use atuin_common::utils::uuid_v4;
+
+    use super::{Context, Database, Sqlite};
+    use crate::history::History;
+    use crate::settings::{FilterMode, SearchMode};
+
+    fn history(command: &str) -> History {
+        History::new(
+            Utc::now(),
+            String::from(command),
+            String::from("/"),
+            0,
+            1,
+            Some(String::from("session")),
+            Some(String::from("host:user")),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_search_after_vacuum() {
+        let path = std::env::temp_dir().join(format!("atuin-test-{}.db", uuid_v4()));
+        let mut db = Sqlite::new(&path).await.unwrap();
+
+        let first = history("ls -la");
+        db.save(&first).await.unwrap();
+        db.save(&history("cargo build")).await.unwrap();
+        db.save(&history("cargo test")).await.unwrap();
+
+        // leave a gap in the rowids, so that vacuum renumbers the rest
+        db.delete(&[first.id]).await.unwrap();
+        db.compact().await.unwrap();
+
+        let context = Context {
+            session: String::from("session"),
+            hostname: String::from("host:user"),
+            cwd: String::from("/"),
+            workspace: String::from("/"),
+        };
+
+        let found = db
+            .search(
+                None,
+                SearchMode::Prefix,
+                FilterMode::Global,
+                &context,
+                "cargo t",
+                false,
+            )
+            .await
+            .unwrap();
+
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].command, "cargo test");
+    }
+
+    #[tokio::test]
+    async fn test_range() {
+        let path = std::env::temp_dir().join(format!("atuin-test-{}.db", uuid_v4()));
+        let mut db = Sqlite::new(&path).await.unwrap();
+
+        for (day, command) in &[(1, "ls"), (2, "cargo build"), (3, "cargo test")] {
+            let mut h = history(command);
+            h.timestamp = Utc.ymd(2021, 5, *day).and_hms(12, 0, 0);
+            db.save(&h).await.unwrap();
+        }
+
+        let found = db
+            .range(
+                Utc.ymd(2021, 5, 2).and_hms(0, 0, 0),
+                Utc.ymd(2021, 5, 3).and_hms(23, 59, 59),
+            )
+            .await
+            .unwrap();
+
+        std::fs::remove_file(&path).ok();
+
+        let commands: Vec<&str> = found.iter().map(|h| h.command.as_str()).collect();
+        assert_eq!(commands, vec!["cargo build", "cargo test"]);
+    }
+
+    #[tokio::test]
+    async fn test_not_uploaded_skips_running() {
+        let path = std::env::temp_dir().join(format!("atuin-test-{}.db", uuid_v4()));
+        let mut db = Sqlite::new(&path).await.unwrap();
+
+        let mut running = history("cargo build");
+        running.duration = -1;
+        db.save(&running).await.unwrap();
+
+        let mut imported = history("ls");
+        imported.timestamp = Utc.ymd(2021, 5, 1).and_hms(12, 0, 0);
+        imported.duration = -1;
+        db.save(&imported).await.unwrap();
+
+        let found = db.not_uploaded(10).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].command, "ls");
+
+        running.duration = 1;
+        db.update(&running).await.unwrap();
+
+        let found = db.not_uploaded(10).await.unwrap();
+
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(found.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_deleted_not_uploaded() {
+        let path = std::env::temp_dir().join(format!("atuin-test-{}.db", uuid_v4()));
+        let mut db = Sqlite::new(&path).await.unwrap();
+
+        let local = history("ls");
+        let synced = history("cargo build");
+        db.save(&local).await.unwrap();
+        db.save(&synced).await.unwrap();
+
+        db.delete(&[local.id.clone()]).await.unwrap();
+        db.delete_synced(&[synced.id]).await.unwrap();
+
+        assert_eq!(
+            db.deleted_not_uploaded().await.unwrap(),
+            vec![local.id.clone()]
+        );
+
+        db.set_deleted_uploaded(&[local.id]).await.unwrap();
+        let found = db.deleted_not_uploaded().await.unwrap();
+
+        std::fs::remove_file(&path).ok();
+
+        assert!(found.is_empty());
+    }
 }
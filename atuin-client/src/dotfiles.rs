@@ -0,0 +1,57 @@
+// Environment variables set with `atuin dotfiles var`, and exported by `atuin
+// init`. Like aliases, they are kept as records so they sync to every machine
+
+use eyre::{eyre, Result};
+
+pub const VAR_NAMESPACE: &str = "dotfiles.var";
+
+// Names have to be usable as a variable in every supported shell
+pub fn valid_var_name(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+pub fn check_var(name: &str, value: &str) -> Result<()> {
+    if !valid_var_name(name) {
+        return Err(eyre!(
+            "invalid variable name {:?}, names are letters, numbers and _, and can't start with a number",
+            name
+        ));
+    }
+
+    if value.contains('\n') {
+        return Err(eyre!("a variable has to be a single line"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check_var, valid_var_name};
+
+    #[test]
+    fn test_valid_var_name() {
+        assert!(valid_var_name("EDITOR"));
+        assert!(valid_var_name("_private"));
+        assert!(valid_var_name("GOPATH2"));
+
+        assert!(!valid_var_name(""));
+        assert!(!valid_var_name("2FAST"));
+        assert!(!valid_var_name("MY-VAR"));
+        assert!(!valid_var_name("A B"));
+    }
+
+    #[test]
+    fn test_check_var() {
+        assert!(check_var("EDITOR", "nvim").is_ok());
+        assert!(check_var("EMPTY", "").is_ok());
+        assert!(check_var("EDITOR", "nvim\nrm -rf /").is_err());
+    }
+}
@@ -7,21 +7,111 @@
 // secretbox. The data is then sent to the server, where it is stored. All
 // clients must share the secret in order to be able to sync, as it is needed
 // to decrypt
+// The key can also be derived from a passphrase, so that it can be restored by
+// typing it in rather than copying the key around
+// secretbox is the default, but XChaCha20-Poly1305 can be chosen instead. Both
+// use the same key, and each item records which was used to encrypt it, so
+// history encrypted either way can always be read back
 
 use std::fs::File;
 use std::io::prelude::*;
 use std::path::PathBuf;
 
+use bip39::Mnemonic;
 use eyre::{eyre, Result};
-use sodiumoxide::crypto::secretbox;
+use serde::{de::DeserializeOwned, Serialize};
+use sodiumoxide::crypto::{aead::xchacha20poly1305_ietf, pwhash::argon2id13, secretbox};
+
+use atuin_common::utils::hash_str;
 
 use crate::history::History;
+use crate::record::Record;
 use crate::settings::Settings;
 
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub enum Algorithm {
+    #[serde(rename = "secretbox")]
+    SecretBox,
+
+    #[serde(rename = "xchacha20poly1305")]
+    XChaCha20Poly1305,
+}
+
+impl Default for Algorithm {
+    // everything encrypted before there was a choice is secretbox
+    fn default() -> Self {
+        Self::SecretBox
+    }
+}
+
+impl Algorithm {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn cipher(self) -> &'static dyn Cipher {
+        match self {
+            Self::SecretBox => &SecretBox,
+            Self::XChaCha20Poly1305 => &XChaCha20Poly1305,
+        }
+    }
+}
+
+// A way of encrypting history with the secret key. Each returns its ciphertext
+// along with the nonce it used, which is needed to open it again
+pub trait Cipher {
+    fn seal(&self, plaintext: &[u8], key: &secretbox::Key) -> Result<(Vec<u8>, Vec<u8>)>;
+    fn open(&self, ciphertext: &[u8], nonce: &[u8], key: &secretbox::Key) -> Result<Vec<u8>>;
+}
+
+pub struct SecretBox;
+
+impl Cipher for SecretBox {
+    fn seal(&self, plaintext: &[u8], key: &secretbox::Key) -> Result<(Vec<u8>, Vec<u8>)> {
+        let nonce = secretbox::gen_nonce();
+        let ciphertext = secretbox::seal(plaintext, &nonce, key);
+
+        Ok((ciphertext, nonce.0.to_vec()))
+    }
+
+    fn open(&self, ciphertext: &[u8], nonce: &[u8], key: &secretbox::Key) -> Result<Vec<u8>> {
+        let nonce = secretbox::Nonce::from_slice(nonce).ok_or_else(|| eyre!("invalid nonce"))?;
+
+        secretbox::open(ciphertext, &nonce, key)
+            .map_err(|_| eyre!("failed to open secretbox - invalid key?"))
+    }
+}
+
+pub struct XChaCha20Poly1305;
+
+impl Cipher for XChaCha20Poly1305 {
+    fn seal(&self, plaintext: &[u8], key: &secretbox::Key) -> Result<(Vec<u8>, Vec<u8>)> {
+        let key = xchacha20poly1305_ietf::Key(key.0);
+        let nonce = xchacha20poly1305_ietf::gen_nonce();
+        let ciphertext = xchacha20poly1305_ietf::seal(plaintext, None, &nonce, &key);
+
+        Ok((ciphertext, nonce.0.to_vec()))
+    }
+
+    fn open(&self, ciphertext: &[u8], nonce: &[u8], key: &secretbox::Key) -> Result<Vec<u8>> {
+        let key = xchacha20poly1305_ietf::Key(key.0);
+        let nonce = xchacha20poly1305_ietf::Nonce::from_slice(nonce)
+            .ok_or_else(|| eyre!("invalid nonce"))?;
+
+        xchacha20poly1305_ietf::open(ciphertext, None, &nonce, &key)
+            .map_err(|_| eyre!("failed to decrypt history - invalid key?"))
+    }
+}
+
+// The algorithm is left out for secretbox, so that history looks exactly as it
+// did before there was a choice
 #[derive(Debug, Serialize, Deserialize)]
 pub struct EncryptedHistory {
     pub ciphertext: Vec<u8>,
-    pub nonce: secretbox::Nonce,
+    pub nonce: Vec<u8>,
+
+    #[serde(default, skip_serializing_if = "Algorithm::is_default")]
+    pub algorithm: Algorithm,
 }
 
 // Loads the secret key, will create + save if it doesn't exist
@@ -60,6 +150,109 @@ pub fn load_encoded_key(settings: &Settings) -> Result<String> {
     }
 }
 
+pub fn save_key(settings: &Settings, key: secretbox::Key) -> Result<()> {
+    let encoded = encode_key(key)?;
+
+    let mut file = File::create(settings.key_path.as_str())?;
+    file.write_all(encoded.as_bytes())?;
+
+    Ok(())
+}
+
+// While a key is being rotated, the new key is kept next to the old one until
+// everything on the server has been re-encrypted with it. Should the rotation
+// be interrupted, it can then be finished with the same key, rather than
+// leaving history on the server that no saved key can read
+fn pending_key_path(settings: &Settings) -> String {
+    format!("{}.pending", settings.key_path)
+}
+
+pub fn load_pending_key(settings: &Settings) -> Result<Option<secretbox::Key>> {
+    let path = pending_key_path(settings);
+
+    if !PathBuf::from(path.as_str()).exists() {
+        return Ok(None);
+    }
+
+    let key = std::fs::read_to_string(path)?;
+
+    Ok(Some(decode_key(key)?))
+}
+
+pub fn save_pending_key(settings: &Settings, key: &secretbox::Key) -> Result<()> {
+    let encoded = encode_key(key.clone())?;
+
+    let mut file = File::create(pending_key_path(settings))?;
+    file.write_all(encoded.as_bytes())?;
+    file.sync_all()?;
+
+    Ok(())
+}
+
+// Everything has been re-encrypted, so the pending key replaces the old one
+pub fn commit_pending_key(settings: &Settings) -> Result<()> {
+    std::fs::rename(pending_key_path(settings), settings.key_path.as_str())?;
+
+    Ok(())
+}
+
+// Derive a key from a passphrase with argon2. The salt has to be the same on
+// every machine, so it is derived from the username rather than random
+pub fn derive_key(username: &str, passphrase: &str) -> Result<secretbox::Key> {
+    sodiumoxide::init().map_err(|_| eyre!("failed to initialise libsodium"))?;
+
+    let salt = hash_str(format!("atuin:{}", username).as_str());
+    let salt = argon2id13::Salt::from_slice(&salt.as_bytes()[..argon2id13::SALTBYTES])
+        .ok_or_else(|| eyre!("failed to build salt"))?;
+
+    let mut key = secretbox::Key([0; secretbox::KEYBYTES]);
+
+    {
+        let secretbox::Key(ref mut buf) = key;
+
+        argon2id13::derive_key(
+            buf,
+            passphrase.as_bytes(),
+            &salt,
+            argon2id13::OPSLIMIT_MODERATE,
+            argon2id13::MEMLIMIT_MODERATE,
+        )
+        .map_err(|_| eyre!("failed to derive key from passphrase"))?;
+    }
+
+    Ok(key)
+}
+
+// The key as a BIP39 word list, which is a lot easier to transcribe than
+// base64. 32 bytes of key gives 24 words.
+pub fn key_to_mnemonic(key: &secretbox::Key) -> Result<String> {
+    let mnemonic = Mnemonic::from_entropy(&key.0)?;
+
+    Ok(mnemonic.to_string())
+}
+
+pub fn key_from_mnemonic(phrase: &str) -> Result<secretbox::Key> {
+    let mnemonic = Mnemonic::parse(phrase)?;
+
+    secretbox::Key::from_slice(&mnemonic.to_entropy())
+        .ok_or_else(|| eyre!("mnemonic is the wrong length for a key"))
+}
+
+// Parse a key as printed by `atuin key`, either base64 or a mnemonic
+pub fn parse_exported_key(input: &str) -> Result<secretbox::Key> {
+    let input = input.trim();
+
+    // a mnemonic is a list of words, base64 never has spaces
+    if input.contains(char::is_whitespace) {
+        return key_from_mnemonic(input);
+    }
+
+    let decoded = base64::decode(input).map_err(|_| eyre!("key is not valid base64"))?;
+    let decoded = String::from_utf8(decoded).map_err(|_| eyre!("key is not valid"))?;
+
+    decode_key(decoded).map_err(|_| eyre!("key is not valid, is it the right length?"))
+}
+
 pub fn encode_key(key: secretbox::Key) -> Result<String> {
     let buf = rmp_serde::to_vec(&key)?;
     let buf = base64::encode(buf);
@@ -74,24 +267,58 @@ pub fn decode_key(key: String) -> Result<secretbox::Key> {
     Ok(buf)
 }
 
-pub fn encrypt(history: &History, key: &secretbox::Key) -> Result<EncryptedHistory> {
+fn seal<T: Serialize>(
+    value: &T,
+    key: &secretbox::Key,
+    algorithm: Algorithm,
+) -> Result<EncryptedHistory> {
     // serialize with msgpack
-    let buf = rmp_serde::to_vec(history)?;
+    let buf = rmp_serde::to_vec(value)?;
+
+    let (ciphertext, nonce) = algorithm.cipher().seal(&buf, key)?;
 
-    let nonce = secretbox::gen_nonce();
+    Ok(EncryptedHistory {
+        ciphertext,
+        nonce,
+        algorithm,
+    })
+}
 
-    let ciphertext = secretbox::seal(&buf, &nonce, key);
+fn open<T: DeserializeOwned>(encrypted: &EncryptedHistory, key: &secretbox::Key) -> Result<T> {
+    let plaintext =
+        encrypted
+            .algorithm
+            .cipher()
+            .open(&encrypted.ciphertext, &encrypted.nonce, key)?;
 
-    Ok(EncryptedHistory { ciphertext, nonce })
+    let value = rmp_serde::from_read_ref(&plaintext)?;
+
+    Ok(value)
+}
+
+pub fn encrypt(
+    history: &History,
+    key: &secretbox::Key,
+    algorithm: Algorithm,
+) -> Result<EncryptedHistory> {
+    seal(history, key, algorithm)
 }
 
 pub fn decrypt(encrypted_history: &EncryptedHistory, key: &secretbox::Key) -> Result<History> {
-    let plaintext = secretbox::open(&encrypted_history.ciphertext, &encrypted_history.nonce, key)
-        .map_err(|_| eyre!("failed to open secretbox - invalid key?"))?;
+    open(encrypted_history, key)
+}
 
-    let history = rmp_serde::from_read_ref(&plaintext)?;
+// Records are sealed just like history, so the server can't tell them apart
+pub fn encrypt_record(
+    record: &Record,
+    key: &secretbox::Key,
+    algorithm: Algorithm,
+) -> Result<EncryptedHistory> {
+    seal(record, key, algorithm)
+}
 
-    Ok(history)
+pub fn decrypt_record(encrypted: &EncryptedHistory, key: &secretbox::Key) -> Result<Record> {
+    open(encrypted, key)
 }
 
 #[cfg(test)]
@@ -100,7 +327,10 @@ mod test {
 
     use crate::history::History;
 
-    use super::{decrypt, encrypt};
+    use super::{
+        decrypt, derive_key, encrypt, key_from_mnemonic, key_to_mnemonic, Algorithm,
+        EncryptedHistory,
+    };
 
     #[test]
     fn test_encrypt_decrypt() {
@@ -117,8 +347,8 @@ mod test {
             Some("booop".to_string()),
         );
 
-        let e1 = encrypt(&history, &key1).unwrap();
-        let e2 = encrypt(&history, &key2).unwrap();
+        let e1 = encrypt(&history, &key1, Algorithm::SecretBox).unwrap();
+        let e2 = encrypt(&history, &key2, Algorithm::SecretBox).unwrap();
 
         assert_ne!(e1.ciphertext, e2.ciphertext);
         assert_ne!(e1.nonce, e2.nonce);
@@ -136,4 +366,59 @@ mod test {
             Err(_) => {}
         };
     }
+
+    #[test]
+    fn test_algorithms() {
+        let key = secretbox::gen_key();
+
+        let history = History::new(
+            chrono::Utc::now(),
+            "ls".to_string(),
+            "/home/ellie".to_string(),
+            0,
+            1,
+            Some("beep boop".to_string()),
+            Some("booop".to_string()),
+        );
+
+        let secretbox = encrypt(&history, &key, Algorithm::SecretBox).unwrap();
+        let xchacha = encrypt(&history, &key, Algorithm::XChaCha20Poly1305).unwrap();
+
+        // the tag is only written out when it isn't secretbox, and goes along
+        // with the ciphertext so the right algorithm is used to decrypt
+        let secretbox = serde_json::to_string(&secretbox).unwrap();
+        let xchacha = serde_json::to_string(&xchacha).unwrap();
+        assert!(!secretbox.contains("algorithm"));
+        assert!(xchacha.contains(r#""algorithm":"xchacha20poly1305""#));
+
+        for data in &[secretbox, xchacha] {
+            let encrypted: EncryptedHistory = serde_json::from_str(data.as_str()).unwrap();
+            assert_eq!(decrypt(&encrypted, &key).unwrap(), history);
+        }
+    }
+
+    #[test]
+    fn test_derive_key() {
+        let key1 = derive_key("ellie", "correct horse battery staple").unwrap();
+        let key2 = derive_key("ellie", "correct horse battery staple").unwrap();
+        let key3 = derive_key("ellie", "incorrect horse battery staple").unwrap();
+        let key4 = derive_key("conrad", "correct horse battery staple").unwrap();
+
+        assert_eq!(key1, key2);
+        assert_ne!(key1, key3);
+        assert_ne!(key1, key4);
+    }
+
+    #[test]
+    fn test_mnemonic() {
+        let key = secretbox::gen_key();
+
+        let mnemonic = key_to_mnemonic(&key).unwrap();
+        assert_eq!(mnemonic.split_whitespace().count(), 24);
+
+        let decoded = key_from_mnemonic(mnemonic.as_str()).unwrap();
+        assert_eq!(decoded, key);
+
+        assert!(key_from_mnemonic("not a valid mnemonic").is_err());
+    }
 }
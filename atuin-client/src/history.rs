@@ -2,9 +2,14 @@ use std::env;
 use std::hash::{Hash, Hasher};
 
 use chrono::Utc;
+use eyre::{eyre, Result};
+use regex::RegexSet;
 
 use atuin_common::utils::uuid_v4;
 
+use crate::secrets::secret_patterns;
+use crate::settings::Settings;
+
 // Any new fields MUST be Optional<>!
 #[derive(Debug, Clone, Serialize, Deserialize, Ord, PartialOrd, sqlx::FromRow)]
 pub struct History {
@@ -45,6 +50,85 @@ impl History {
             hostname,
         }
     }
+
+    // Record how the command finished. Shells that time commands themselves pass
+    // the duration in, otherwise it's however long it has been since it started.
+    pub fn end(&mut self, exit: i64, duration: Option<i64>) {
+        self.exit = exit;
+        self.duration = duration.unwrap_or_else(|| {
+            chrono::Utc::now().timestamp_nanos() - self.timestamp.timestamp_nanos()
+        });
+    }
+
+    pub fn should_save(&self, settings: &Settings) -> Result<bool> {
+        Ok(!HistoryFilter::new(settings)?.is_match(self))
+    }
+}
+
+// Convert a cwd_filter glob into a regex. A single * does not cross
+// directories, but ** does. As these are directories, anything below a
+// matching directory matches too.
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    let mut chars = glob.trim_end_matches('/').chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                regex.push_str(".*");
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            c => regex.push_str(regex::escape(c.to_string().as_str()).as_str()),
+        }
+    }
+
+    regex.push_str("(/.*)?$");
+    regex
+}
+
+// The secrets filter, plus any user configured history_filter and cwd_filter
+// patterns. Compiling these is not free, so build this once when checking a
+// lot of history, such as when importing.
+pub struct HistoryFilter {
+    secrets: Option<RegexSet>,
+    filter: RegexSet,
+    cwd: RegexSet,
+}
+
+impl HistoryFilter {
+    pub fn new(settings: &Settings) -> Result<Self> {
+        let secrets = if settings.secrets_filter {
+            Some(secret_patterns())
+        } else {
+            None
+        };
+
+        let filter = RegexSet::new(&settings.history_filter)
+            .map_err(|e| eyre!("invalid history_filter pattern: {}", e))?;
+
+        let cwd = settings
+            .cwd_filter
+            .iter()
+            .map(|glob| Ok(glob_to_regex(shellexpand::full(glob)?.as_ref())))
+            .collect::<Result<Vec<String>>>()?;
+        let cwd = RegexSet::new(cwd).map_err(|e| eyre!("invalid cwd_filter pattern: {}", e))?;
+
+        Ok(Self {
+            secrets,
+            filter,
+            cwd,
+        })
+    }
+
+    pub fn is_match(&self, h: &History) -> bool {
+        let command = h.command.as_str();
+
+        self.secrets.as_ref().map_or(false, |s| s.is_match(command))
+            || self.filter.is_match(command)
+            || self.cwd.is_match(h.cwd.as_str())
+    }
 }
 
 impl PartialEq for History {
@@ -64,3 +148,32 @@ impl Hash for History {
         self.command.hash(state);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use regex::Regex;
+
+    use super::glob_to_regex;
+
+    fn glob_match(glob: &str, path: &str) -> bool {
+        Regex::new(glob_to_regex(glob).as_str())
+            .unwrap()
+            .is_match(path)
+    }
+
+    #[test]
+    fn test_glob_to_regex() {
+        assert!(glob_match("/home/ellie/secret", "/home/ellie/secret"));
+        assert!(glob_match("/home/ellie/secret/", "/home/ellie/secret"));
+        assert!(glob_match("/home/ellie/secret", "/home/ellie/secret/src"));
+        assert!(!glob_match("/home/ellie/secret", "/home/ellie/secrets"));
+        assert!(!glob_match("/home/ellie/secret", "/home/ellie"));
+
+        assert!(glob_match("/home/*/secret", "/home/ellie/secret"));
+        assert!(!glob_match("/home/*/secret", "/home/ellie/work/secret"));
+        assert!(glob_match("/home/**/secret", "/home/ellie/work/secret"));
+        assert!(glob_match("/tmp/project-?", "/tmp/project-1"));
+        assert!(glob_match("/tmp/a.b", "/tmp/a.b"));
+        assert!(!glob_match("/tmp/a.b", "/tmp/axb"));
+    }
+}
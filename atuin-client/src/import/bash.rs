@@ -1,9 +1,10 @@
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
 use std::{fs::File, path::Path};
 
+use chrono::prelude::*;
+use chrono::Utc;
 use eyre::{eyre, Result};
 
-use super::count_lines;
 use crate::history::History;
 
 #[derive(Debug)]
@@ -12,18 +13,24 @@ pub struct Bash {
 
     pub loc: u64,
     pub counter: i64,
+
+    // several commands can share the same timestamp, keep them in order
+    last_timestamp: Option<i64>,
+    same_timestamp: i64,
 }
 
 impl Bash {
     pub fn new(path: impl AsRef<Path>) -> Result<Self> {
         let file = File::open(path)?;
         let mut buf = BufReader::new(file);
-        let loc = count_lines(&mut buf)?;
+        let loc = count_commands(&mut buf)?;
 
         Ok(Self {
             file: buf,
             loc: loc as u64,
             counter: 0,
+            last_timestamp: None,
+            same_timestamp: 0,
         })
     }
 
@@ -38,6 +45,24 @@ impl Bash {
     }
 }
 
+// With HISTTIMEFORMAT set, bash writes the time a command was ran on the line
+// before it, as #<unix timestamp>
+fn parse_timestamp(line: &str) -> Option<i64> {
+    line.trim_end().strip_prefix('#')?.parse().ok()
+}
+
+// the progress bar counts commands, so skip the timestamp lines
+fn count_commands(buf: &mut BufReader<File>) -> Result<usize> {
+    let commands = buf
+        .lines()
+        .filter_map(std::result::Result::ok)
+        .filter(|l| parse_timestamp(l).is_none())
+        .count();
+    buf.seek(SeekFrom::Start(0))?;
+
+    Ok(commands)
+}
+
 impl Iterator for Bash {
     type Item = Result<History>;
 
@@ -49,6 +74,16 @@ impl Iterator for Bash {
         }
 
         let mut line = line.unwrap();
+        let mut timestamp = None;
+
+        while let Some(t) = parse_timestamp(line.as_str()) {
+            timestamp = Some(t);
+
+            line = match self.read_line()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e)),
+            };
+        }
 
         while line.ends_with("\\\n") {
             let next_line = self.read_line()?;
@@ -60,9 +95,22 @@ impl Iterator for Bash {
             line.push_str(next_line.unwrap().as_str());
         }
 
-        let time = chrono::Utc::now();
-        let offset = chrono::Duration::seconds(self.counter);
-        let time = time - offset;
+        let time = if let Some(timestamp) = timestamp {
+            if self.last_timestamp == Some(timestamp) {
+                self.same_timestamp += 1;
+            } else {
+                self.same_timestamp = 0;
+            }
+
+            self.last_timestamp = Some(timestamp);
+
+            let offset = chrono::Duration::milliseconds(self.same_timestamp);
+            Utc.timestamp(timestamp, 0) + offset
+        } else {
+            let time = chrono::Utc::now();
+            let offset = chrono::Duration::seconds(self.counter);
+            time - offset
+        };
 
         self.counter += 1;
 
@@ -77,3 +125,57 @@ impl Iterator for Bash {
         )))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use chrono::prelude::*;
+    use chrono::Utc;
+
+    use super::{parse_timestamp, Bash};
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("#1613322469\n"), Some(1_613_322_469));
+        assert_eq!(parse_timestamp("# a comment\n"), None);
+        assert_eq!(parse_timestamp("cargo install atuin\n"), None);
+    }
+
+    #[test]
+    fn test_parse_file_with_timestamps() {
+        let path = std::env::temp_dir().join(format!("atuin_bash_{}", std::process::id()));
+        let mut file = std::fs::File::create(&path).unwrap();
+
+        file.write_all(
+            b"#1613322469
+cargo install atuin
+#1613322470
+ls
+#1613322470
+cd ..
+",
+        )
+        .unwrap();
+
+        let bash = Bash::new(&path).unwrap();
+        assert_eq!(bash.loc, 3);
+
+        let history: Vec<_> = bash.map(Result::unwrap).collect();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(history.len(), 3);
+
+        assert_eq!(history[0].command, "cargo install atuin");
+        assert_eq!(history[0].timestamp, Utc.timestamp(1_613_322_469, 0));
+
+        assert_eq!(history[1].command, "ls");
+        assert_eq!(history[1].timestamp, Utc.timestamp(1_613_322_470, 0));
+
+        assert_eq!(history[2].command, "cd ..");
+        assert_eq!(
+            history[2].timestamp,
+            Utc.timestamp_millis(1_613_322_470_001)
+        );
+    }
+}
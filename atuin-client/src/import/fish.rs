@@ -0,0 +1,194 @@
+// import fish history!
+// fish keeps its history in a yaml-ish file, with entries like
+//
+// - cmd: cargo install atuin
+//   when: 1613322469
+//   paths:
+//     - atuin
+
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::{fs::File, path::Path};
+
+use chrono::prelude::*;
+use chrono::Utc;
+use eyre::{eyre, Result};
+
+use crate::history::History;
+
+#[derive(Debug)]
+pub struct Fish {
+    file: BufReader<File>,
+
+    pub loc: u64,
+    pub counter: i64,
+
+    // fish writes the timestamp after the command, so we have to hold on to a
+    // command until we find the next one
+    command: Option<String>,
+    timestamp: Option<DateTime<Utc>>,
+}
+
+impl Fish {
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::open(path)?;
+        let mut buf = BufReader::new(file);
+        let loc = count_commands(&mut buf)?;
+
+        Ok(Self {
+            file: buf,
+            loc: loc as u64,
+            counter: 0,
+            command: None,
+            timestamp: None,
+        })
+    }
+
+    fn read_line(&mut self) -> Option<Result<String>> {
+        let mut line = String::new();
+
+        match self.file.read_line(&mut line) {
+            Ok(0) => None,
+            Ok(_) => Some(Ok(line)),
+            Err(e) => Some(Err(eyre!("failed to read line: {}", e))), // we can skip past things like invalid utf8
+        }
+    }
+
+    fn take_history(&mut self) -> Option<History> {
+        let command = self.command.take()?;
+
+        let time = self.timestamp.take().unwrap_or_else(|| {
+            let time = chrono::Utc::now();
+            let offset = chrono::Duration::seconds(self.counter);
+            time - offset
+        });
+
+        self.counter += 1;
+
+        Some(History::new(
+            time,
+            command,
+            String::from("unknown"),
+            -1,
+            -1,
+            None,
+            None,
+        ))
+    }
+}
+
+// the progress bar counts commands, not lines
+fn count_commands(buf: &mut BufReader<File>) -> Result<usize> {
+    let commands = buf
+        .lines()
+        .filter_map(std::result::Result::ok)
+        .filter(|l| l.starts_with("- cmd:"))
+        .count();
+    buf.seek(SeekFrom::Start(0))?;
+
+    Ok(commands)
+}
+
+// fish escapes newlines and backslashes in commands, so that every command
+// fits on a single line
+fn unescape(command: &str) -> String {
+    let mut unescaped = String::with_capacity(command.len());
+    let mut chars = command.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('\\') => unescaped.push('\\'),
+            Some(c) => {
+                unescaped.push('\\');
+                unescaped.push(c);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+
+    unescaped
+}
+
+impl Iterator for Fish {
+    type Item = Result<History>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.read_line() {
+                Some(Ok(line)) => line,
+                Some(Err(e)) => return Some(Err(e)), // :(
+                None => return self.take_history().map(Ok),
+            };
+
+            if let Some(command) = line.strip_prefix("- cmd:") {
+                let previous = self.take_history();
+                self.command = Some(unescape(command.trim()));
+
+                if let Some(previous) = previous {
+                    return Some(Ok(previous));
+                }
+            } else if let Some(when) = line.trim_start().strip_prefix("when:") {
+                self.timestamp = when.trim().parse::<i64>().ok().map(|t| Utc.timestamp(t, 0));
+            }
+
+            // anything else is metadata we don't use, such as paths
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use chrono::prelude::*;
+    use chrono::Utc;
+
+    use super::{unescape, Fish};
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape("cargo install atuin"), "cargo install atuin");
+        assert_eq!(unescape("echo foo\\nbar"), "echo foo\nbar");
+        assert_eq!(unescape("echo foo\\\\nbar"), "echo foo\\nbar");
+        assert_eq!(unescape("echo \\$HOME"), "echo \\$HOME");
+    }
+
+    #[test]
+    fn test_parse_file() {
+        let path = std::env::temp_dir().join(format!("atuin_fish_{}", std::process::id()));
+        let mut file = std::fs::File::create(&path).unwrap();
+
+        file.write_all(
+            b"- cmd: cargo install atuin
+  when: 1613322469
+  paths:
+    - atuin
+- cmd: for i in 1 2\\n  echo $i\\nend
+  when: 1613322470
+- cmd: ls
+",
+        )
+        .unwrap();
+
+        let fish = Fish::new(&path).unwrap();
+        assert_eq!(fish.loc, 3);
+
+        let history: Vec<_> = fish.map(Result::unwrap).collect();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(history.len(), 3);
+
+        assert_eq!(history[0].command, "cargo install atuin");
+        assert_eq!(history[0].timestamp, Utc.timestamp(1_613_322_469, 0));
+
+        assert_eq!(history[1].command, "for i in 1 2\n  echo $i\nend");
+        assert_eq!(history[1].timestamp, Utc.timestamp(1_613_322_470, 0));
+
+        assert_eq!(history[2].command, "ls");
+    }
+}
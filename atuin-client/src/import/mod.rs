@@ -4,7 +4,10 @@ use std::io::{BufRead, BufReader, Seek, SeekFrom};
 use eyre::Result;
 
 pub mod bash;
+pub mod fish;
+pub mod resh;
 pub mod zsh;
+pub mod zsh_histdb;
 
 // this could probably be sped up
 fn count_lines(buf: &mut BufReader<File>) -> Result<usize> {
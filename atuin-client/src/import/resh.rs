@@ -0,0 +1,145 @@
+// import history from resh!
+// https://github.com/curusarn/resh
+//
+// resh writes one json record per line, with a lot more in each than we
+// care about. Only the fields we can map onto history are read here.
+
+use std::io::{BufRead, BufReader};
+use std::{fs::File, path::Path};
+
+use chrono::prelude::*;
+use chrono::Utc;
+use eyre::{eyre, Result};
+
+use super::count_lines;
+use crate::history::History;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReshEntry {
+    pub cmd_line: String,
+    #[serde(default)]
+    pub exit_code: i64,
+    #[serde(default)]
+    pub pwd: String,
+    #[serde(default)]
+    pub host: String,
+    #[serde(default)]
+    pub login: String,
+    #[serde(default)]
+    pub session_id: String,
+    #[serde(default)]
+    pub realtime_before: f64,
+    #[serde(default)]
+    pub realtime_after: f64,
+}
+
+impl From<ReshEntry> for History {
+    #[allow(clippy::cast_possible_truncation)]
+    fn from(entry: ReshEntry) -> Self {
+        // resh records times as fractional seconds since the epoch
+        let secs = entry.realtime_before.trunc() as i64;
+        let nanos = (entry.realtime_before.fract() * 1_000_000_000_f64) as u32;
+        let timestamp = Utc.timestamp(secs, nanos);
+
+        let duration = if entry.realtime_after > entry.realtime_before {
+            ((entry.realtime_after - entry.realtime_before) * 1_000_000_000_f64) as i64
+        } else {
+            -1
+        };
+
+        let cwd = if entry.pwd.is_empty() {
+            String::from("unknown")
+        } else {
+            entry.pwd
+        };
+
+        let session = if entry.session_id.is_empty() {
+            None
+        } else {
+            Some(entry.session_id.replace("-", ""))
+        };
+
+        let hostname = if entry.host.is_empty() || entry.login.is_empty() {
+            None
+        } else {
+            Some(format!("{}:{}", entry.host, entry.login))
+        };
+
+        Self::new(
+            timestamp,
+            entry.cmd_line,
+            cwd,
+            entry.exit_code,
+            duration,
+            session,
+            hostname,
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct Resh {
+    file: BufReader<File>,
+
+    pub loc: u64,
+}
+
+impl Resh {
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::open(path)?;
+        let mut buf = BufReader::new(file);
+        let loc = count_lines(&mut buf)?;
+
+        Ok(Self {
+            file: buf,
+            loc: loc as u64,
+        })
+    }
+}
+
+impl Iterator for Resh {
+    type Item = Result<History>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = String::new();
+
+        match self.file.read_line(&mut line) {
+            Ok(0) => None,
+            Ok(_) => Some(
+                serde_json::from_str::<ReshEntry>(line.as_str())
+                    .map(History::from)
+                    .map_err(|e| eyre!("failed to parse resh record: {}", e)),
+            ),
+            Err(e) => Some(Err(eyre!("failed to read line: {}", e))), // we can skip past things like invalid utf8
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::prelude::*;
+    use chrono::Utc;
+
+    use crate::history::History;
+
+    use super::ReshEntry;
+
+    #[test]
+    fn test_parse_entry() {
+        let entry: ReshEntry = serde_json::from_str(
+            r#"{"cmdLine":"cargo install atuin","exitCode":1,"shell":"zsh","pwd":"/home/ellie","host":"laptop","login":"ellie","sessionId":"0d3c1a64-7f8e-4b4e-9a0b-1d2e3f4a5b6c","realtimeBefore":1613322469.5,"realtimeAfter":1613322471.5}"#,
+        )
+        .unwrap();
+
+        let history = History::from(entry);
+
+        assert_eq!(history.command, "cargo install atuin");
+        assert_eq!(history.exit, 1);
+        assert_eq!(history.cwd, "/home/ellie");
+        assert_eq!(history.hostname, "laptop:ellie");
+        assert_eq!(history.session, "0d3c1a647f8e4b4e9a0b1d2e3f4a5b6c");
+        assert_eq!(history.duration, 2_000_000_000);
+        assert_eq!(history.timestamp, Utc.timestamp(1_613_322_469, 500_000_000));
+    }
+}
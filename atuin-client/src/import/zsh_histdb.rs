@@ -0,0 +1,98 @@
+// import history from zsh-histdb!
+// https://github.com/larkery/zsh-histdb
+//
+// histdb already records most of what we do, spread across three tables
+// - commands, the text of each command
+// - places, the host and directory a command ran in
+// - history, which joins the two with a session, exit status and timing
+
+use std::path::Path;
+use std::str::FromStr;
+
+use chrono::prelude::*;
+use chrono::Utc;
+use eyre::Result;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+
+use atuin_common::utils::hash_str;
+
+use crate::history::History;
+
+#[derive(Debug, sqlx::FromRow)]
+struct HistDbEntry {
+    start_time: Option<i64>,
+    duration: Option<i64>,
+    exit_status: Option<i64>,
+    session: Option<i64>,
+    argv: Option<String>,
+    host: Option<String>,
+    dir: Option<String>,
+}
+
+impl From<HistDbEntry> for History {
+    fn from(entry: HistDbEntry) -> Self {
+        let host = entry.host.unwrap_or_else(whoami::hostname);
+
+        // histdb numbers sessions per host, but we use a uuid. Hash the two
+        // together, so that commands from the same session stay grouped
+        let session = entry.session.map(|s| {
+            let mut session = hash_str(format!("{}:{}", host, s).as_str());
+            session.truncate(32);
+            session
+        });
+
+        Self::new(
+            Utc.timestamp(entry.start_time.unwrap_or(0), 0),
+            entry.argv.unwrap_or_default(),
+            entry.dir.unwrap_or_else(|| String::from("unknown")),
+            entry.exit_status.unwrap_or(-1),
+            entry.duration.map_or(-1, |d| d * 1_000_000_000),
+            session,
+            Some(format!("{}:{}", host, whoami::username())),
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct ZshHistDb {
+    entries: std::vec::IntoIter<HistDbEntry>,
+
+    pub loc: u64,
+}
+
+impl ZshHistDb {
+    pub async fn new(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        debug!("opening zsh-histdb database at {:?}", path);
+
+        let opts =
+            SqliteConnectOptions::from_str(path.as_os_str().to_str().unwrap())?.read_only(true);
+        let pool = SqlitePoolOptions::new().connect_with(opts).await?;
+
+        let entries: Vec<HistDbEntry> = sqlx::query_as(
+            "select history.start_time, history.duration, history.exit_status,
+                history.session, commands.argv, places.host, places.dir
+            from history
+            left join commands on history.command_id = commands.id
+            left join places on history.place_id = places.id
+            order by history.start_time",
+        )
+        .fetch_all(&pool)
+        .await?;
+
+        pool.close().await;
+
+        Ok(Self {
+            loc: entries.len() as u64,
+            entries: entries.into_iter(),
+        })
+    }
+}
+
+impl Iterator for ZshHistDb {
+    type Item = Result<History>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next().map(|e| Ok(e.into()))
+    }
+}
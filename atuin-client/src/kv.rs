@@ -0,0 +1,78 @@
+// A general store for small values that should follow the user between
+// machines, set with `atuin kv`. Values are kept as records, under a namespace
+// of the user's choosing, so they sync like aliases do
+
+use eyre::{eyre, Result};
+
+use crate::record;
+
+const MAX_NAMESPACE_LENGTH: usize = 64;
+const MAX_KEY_LENGTH: usize = 256;
+
+// The namespace the records are stored under. Prefixed, so that no namespace
+// can be chosen that clashes with atuin's own, such as aliases
+pub fn namespace(name: &str) -> Result<String> {
+    // a record is hashed by its namespace and key joined by a colon, so one
+    // in a namespace would let two records share a hash
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAMESPACE_LENGTH
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'));
+
+    if !valid {
+        return Err(eyre!(
+            "invalid namespace {:?}, namespaces are letters, numbers and - _ . only",
+            name
+        ));
+    }
+
+    Ok(format!("kv.{}", name))
+}
+
+pub fn check_key(key: &str) -> Result<()> {
+    if key.is_empty() {
+        return Err(eyre!("the key can't be empty"));
+    }
+
+    if key.len() > MAX_KEY_LENGTH {
+        return Err(eyre!(
+            "the key is {} bytes, over the limit of {} bytes",
+            key.len(),
+            MAX_KEY_LENGTH
+        ));
+    }
+
+    Ok(())
+}
+
+// Values are checked when they are set, rather than left for the server to
+// refuse when they sync
+pub fn check_value(value: &str) -> Result<()> {
+    record::check_length(value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check_key, check_value, namespace};
+
+    #[test]
+    fn test_namespace() {
+        assert_eq!(namespace("default").unwrap(), "kv.default");
+        assert_eq!(namespace("work.laptop").unwrap(), "kv.work.laptop");
+
+        assert!(namespace("").is_err());
+        assert!(namespace("a:b").is_err());
+        assert!(namespace("has space").is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(check_key("dotfiles-branch").is_ok());
+        assert!(check_key("").is_err());
+        assert!(check_key("k".repeat(257).as_str()).is_err());
+
+        assert!(check_value("main").is_ok());
+        assert!(check_value("v".repeat(8 * 1024).as_str()).is_err());
+    }
+}
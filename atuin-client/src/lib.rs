@@ -4,10 +4,19 @@ extern crate log;
 #[macro_use]
 extern crate serde_derive;
 
+pub mod alias;
 pub mod api_client;
+pub mod daemon;
 pub mod database;
+pub mod dotfiles;
 pub mod encryption;
 pub mod history;
 pub mod import;
+pub mod kv;
+pub mod record;
+pub mod retention;
+pub mod scripts;
+pub mod secrets;
 pub mod settings;
 pub mod sync;
+pub mod tags;
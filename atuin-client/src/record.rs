@@ -0,0 +1,52 @@
+// Records are small bits of state that aren't history, such as aliases. Each
+// is a value under a key in a namespace, and is synced through the server
+// encrypted, with the most recent change to a key winning. Deleting one leaves
+// it behind without a value, so that the deletion syncs too
+
+use chrono::{DateTime, Utc};
+
+use eyre::{eyre, Result};
+
+use atuin_common::utils::hash_str;
+
+// Encrypting a value makes it around four times larger, and servers turn
+// away anything over 32KiB by default, so values are held well under that
+pub const MAX_VALUE_LENGTH: usize = 7 * 1024;
+
+pub fn check_length(value: &str) -> Result<()> {
+    if value.len() > MAX_VALUE_LENGTH {
+        return Err(eyre!(
+            "value is {} bytes, over the limit of {} bytes",
+            value.len(),
+            MAX_VALUE_LENGTH
+        ));
+    }
+
+    Ok(())
+}
+
+// Like history, this is encrypted as msgpack without field names, so fields
+// can't be added without breaking older clients
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Record {
+    pub namespace: String,
+    pub key: String,
+    pub value: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Record {
+    pub fn new(namespace: &str, key: &str, value: Option<String>) -> Self {
+        Self {
+            namespace: String::from(namespace),
+            key: String::from(key),
+            value,
+            updated_at: Utc::now(),
+        }
+    }
+
+    // what the server knows the record by, in place of the namespace and key
+    pub fn hash(&self) -> String {
+        hash_str(format!("{}:{}", self.namespace, self.key).as_str())
+    }
+}
@@ -0,0 +1,120 @@
+use chrono::Utc;
+use eyre::{eyre, Result};
+
+use crate::database::Database;
+use crate::history::History;
+use crate::settings::{Retention, Settings};
+
+// Which history is past the retention limits, newest first. Anything older than
+// max_age goes, and so does anything beyond the newest max_entries. An empty
+// max_age or a max_entries of 0 means no limit.
+pub async fn expired(retention: &Retention, db: &(impl Database + Send)) -> Result<Vec<History>> {
+    let cutoff = if retention.max_age.is_empty() {
+        None
+    } else {
+        let age = humantime::parse_duration(retention.max_age.as_str())
+            .map_err(|e| eyre!("invalid retention.max_age {:?}: {}", retention.max_age, e))?;
+
+        Some(Utc::now() - chrono::Duration::from_std(age)?)
+    };
+
+    let keep = if retention.max_entries > 0 {
+        Some(retention.max_entries)
+    } else {
+        None
+    };
+
+    db.expired(cutoff, keep).await
+}
+
+pub fn enabled(retention: &Retention) -> bool {
+    !retention.max_age.is_empty() || retention.max_entries > 0
+}
+
+// Delete everything past the retention limits. Like any other deletion, this
+// is synced, so the server copy is trimmed too. Returns how many were deleted
+pub async fn expire(settings: &Settings, db: &mut (impl Database + Send)) -> Result<usize> {
+    if !enabled(&settings.retention) {
+        return Ok(0);
+    }
+
+    let ids: Vec<String> = expired(&settings.retention, db)
+        .await?
+        .into_iter()
+        .map(|h| h.id)
+        .collect();
+
+    if !ids.is_empty() {
+        debug!("expiring {} history items", ids.len());
+        db.delete(&ids).await?;
+    }
+
+    Ok(ids.len())
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, Utc};
+
+    use atuin_common::utils::uuid_v4;
+
+    use super::expired;
+    use crate::database::{Database, Sqlite};
+    use crate::history::History;
+    use crate::settings::Retention;
+
+    fn retention(max_age: &str, max_entries: i64) -> Retention {
+        Retention {
+            max_age: max_age.into(),
+            max_entries,
+        }
+    }
+
+    async fn ids(db: &Sqlite, r: Retention) -> Vec<String> {
+        expired(&r, db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|h| h.id)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_expired() {
+        let path = std::env::temp_dir().join(format!("atuin-test-{}.db", uuid_v4()));
+        let mut db = Sqlite::new(&path).await.unwrap();
+
+        let h = |days: i64| {
+            History::new(
+                Utc::now() - Duration::days(days),
+                "ls".into(),
+                "/".into(),
+                0,
+                0,
+                None,
+                None,
+            )
+        };
+
+        let history = vec![h(400), h(1), h(30), h(100)];
+        db.save_bulk(&history).await.unwrap();
+
+        assert!(ids(&db, retention("", 0)).await.is_empty());
+
+        assert_eq!(
+            ids(&db, retention("60days", 0)).await,
+            vec![history[3].id.clone(), history[0].id.clone()]
+        );
+
+        assert_eq!(
+            ids(&db, retention("", 1)).await,
+            vec![
+                history[2].id.clone(),
+                history[3].id.clone(),
+                history[0].id.clone()
+            ]
+        );
+
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,92 @@
+// Named scripts, saved with `atuin scripts` and ran by name. Like aliases, they
+// are kept as records so they sync to every machine
+
+use eyre::{eyre, Result};
+
+use crate::alias;
+use crate::history::History;
+use crate::record;
+
+pub const NAMESPACE: &str = "scripts";
+
+// Named like aliases, so a script can be given the same name as the alias
+// that runs it
+pub fn check(name: &str, script: &str) -> Result<()> {
+    if !alias::valid_name(name) {
+        return Err(eyre!(
+            "invalid script name {:?}, names are letters, numbers and - _ . only",
+            name
+        ));
+    }
+
+    if script.trim().is_empty() {
+        return Err(eyre!("the script is empty"));
+    }
+
+    record::check_length(script)
+}
+
+// A script of the most recent commands, newest first as history is listed,
+// oldest first in the script. The command saving the script has already been
+// recorded by the time it runs, so it is skipped
+pub fn from_history(history: &[History], skip: Option<&str>, count: usize) -> String {
+    let mut commands: Vec<&str> = history
+        .iter()
+        .filter(|h| Some(h.id.as_str()) != skip)
+        .take(count)
+        .map(|h| h.command.trim())
+        .collect();
+
+    commands.reverse();
+
+    let mut script = commands.join("\n");
+    script.push('\n');
+
+    script
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+
+    use crate::history::History;
+
+    use super::{check, from_history};
+
+    fn history(command: &str) -> History {
+        History::new(
+            Utc::now(),
+            command.to_string(),
+            "/home/ellie".to_string(),
+            0,
+            1,
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn test_from_history() {
+        let saving = history("atuin scripts save deploy --from-last 2");
+        let history = vec![
+            saving.clone(),
+            history("git push"),
+            history("cargo test "),
+            history("ls"),
+        ];
+
+        assert_eq!(
+            from_history(&history, Some(saving.id.as_str()), 2),
+            "cargo test\ngit push\n"
+        );
+        assert_eq!(from_history(&history, None, 1), saving.command + "\n");
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(check("deploy", "git push\n").is_ok());
+        assert!(check("deploy", " \n").is_err());
+        assert!(check("de ploy", "git push").is_err());
+        assert!(check("deploy", "x".repeat(8 * 1024).as_str()).is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use std::fs::{create_dir_all, File};
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 use chrono::prelude::*;
 use chrono::Utc;
@@ -8,23 +9,300 @@ use config::{Config, Environment, File as ConfigFile};
 use eyre::{eyre, Result};
 use parse_duration::parse;
 
+use crate::encryption::Algorithm;
+use crate::tags::TagRule;
+
 pub const HISTORY_PAGE_SIZE: i64 = 100;
 
+// Everything atuin keeps in its data directory. The database goes together
+// with its write ahead log, so is kept apart
+const DB_FILES: &[&str] = &["history.db", "history.db-wal", "history.db-shm"];
+const DATA_FILES: &[&str] = &[
+    "key",
+    "session",
+    "last_sync_time",
+    "last_sync_attempt",
+    "sync_cursor",
+    "deleted_cursor",
+];
+
+// Left in the data directory when moving legacy files fails
+const MIGRATE_FAILED: &str = "migrate_failed";
+
+// Older versions put everything straight into $XDG_DATA_HOME and
+// $XDG_CONFIG_HOME, rather than an atuin directory inside them. Anything found
+// there, or left in ~/.local/share/atuin and ~/.config/atuin from before the XDG
+// variables were set, is moved to where it belongs now. Nothing is overwritten.
+fn migrate_legacy_paths(config_dir: &Path, data_dir: &Path) {
+    // a move that failed once, such as across filesystems, will fail every
+    // time, so it is only tried and reported the once
+    let failed = data_dir.join(MIGRATE_FAILED);
+    if failed.exists() {
+        return;
+    }
+
+    let home = PathBuf::from(std::env::var("HOME").unwrap_or_default());
+
+    // each group is moved all together or not at all, so that the database
+    // is never split from its write ahead log
+    let mut moves: Vec<Vec<(PathBuf, PathBuf)>> = Vec::new();
+    let data_moves = |from: &Path| {
+        let db = DB_FILES
+            .iter()
+            .map(|f| (from.join(f), data_dir.join(f)))
+            .collect();
+
+        let rest = DATA_FILES
+            .iter()
+            .map(|f| vec![(from.join(f), data_dir.join(f))])
+            .collect::<Vec<_>>();
+
+        std::iter::once(db).chain(rest)
+    };
+
+    if let Ok(xdg) = std::env::var("XDG_DATA_HOME") {
+        let xdg = PathBuf::from(xdg);
+
+        // the names are common enough that the database has to be there too
+        if xdg.join("history.db").exists() {
+            moves.extend(data_moves(&xdg));
+        }
+
+        let legacy = home.join(".local").join("share").join("atuin");
+        if legacy != data_dir {
+            moves.extend(data_moves(&legacy));
+        }
+    }
+
+    if let Ok(xdg) = std::env::var("XDG_CONFIG_HOME") {
+        let xdg = PathBuf::from(xdg);
+
+        let config = xdg.join("config.toml");
+        if std::fs::read_to_string(&config).map_or(false, |c| c.contains("sync_address")) {
+            moves.push(vec![(config, config_dir.join("config.toml"))]);
+        }
+
+        let legacy = home.join(".config").join("atuin");
+        if legacy != config_dir {
+            moves.push(vec![(
+                legacy.join("config.toml"),
+                config_dir.join("config.toml"),
+            )]);
+            moves.push(vec![(
+                legacy.join("server.toml"),
+                config_dir.join("server.toml"),
+            )]);
+        }
+    }
+
+    for group in moves {
+        let group: Vec<(PathBuf, PathBuf)> = group
+            .into_iter()
+            .filter(|(from, _)| from.exists())
+            .collect();
+
+        if group.is_empty() || group.iter().any(|(_, to)| to.exists()) {
+            continue;
+        }
+
+        if let Err(e) = move_all(&group) {
+            eprintln!(
+                "atuin: {}. move the files by hand, this won't be tried again",
+                e
+            );
+
+            if let Err(e) = std::fs::write(&failed, e.to_string()) {
+                eprintln!("atuin: failed to write {}: {}", failed.display(), e);
+            }
+
+            return;
+        }
+    }
+}
+
+// Moves every file, or if one fails, puts back those already moved
+fn move_all(group: &[(PathBuf, PathBuf)]) -> Result<()> {
+    for (i, (from, to)) in group.iter().enumerate() {
+        if let Err(e) = std::fs::rename(from, to) {
+            for (from, to) in &group[..i] {
+                std::fs::rename(to, from).ok();
+            }
+
+            return Err(eyre!(
+                "failed to move {} to {}: {}",
+                from.display(),
+                to.display(),
+                e
+            ));
+        }
+    }
+
+    for (from, to) in group {
+        eprintln!("atuin: moved {} to {}", from.display(), to.display());
+    }
+
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub enum SearchMode {
+    #[serde(rename = "prefix")]
+    Prefix,
+
+    #[serde(rename = "fuzzy")]
+    Fuzzy,
+}
+
+impl FromStr for SearchMode {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "prefix" => Ok(Self::Prefix),
+            "fuzzy" => Ok(Self::Fuzzy),
+            _ => Err(eyre!("invalid search mode {}, expected prefix or fuzzy", s)),
+        }
+    }
+}
+
+// How keys behave in the interactive search. The vim modes only differ in
+// which mode the search starts in
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub enum KeymapMode {
+    #[serde(rename = "emacs")]
+    Emacs,
+
+    #[serde(rename = "vim-normal")]
+    VimNormal,
+
+    #[serde(rename = "vim-insert")]
+    VimInsert,
+}
+
+// What goes between the commands marked in the interactive search, when they
+// are accepted together
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum JoinMode {
+    And,
+    Newline,
+}
+
+impl JoinMode {
+    pub const fn separator(self) -> &'static str {
+        match self {
+            Self::And => " && ",
+            Self::Newline => "\n",
+        }
+    }
+}
+
+// Which history the interactive search shows. Cycled through in the UI.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterMode {
+    Global,
+    Host,
+    Workspace,
+    Directory,
+    Session,
+}
+
+impl FilterMode {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Global => "global",
+            Self::Host => "host",
+            Self::Workspace => "workspace",
+            Self::Directory => "directory",
+            Self::Session => "session",
+        }
+    }
+
+    // the order these are cycled through in the UI
+    pub const fn next(self) -> Self {
+        match self {
+            Self::Global => Self::Host,
+            Self::Host => Self::Workspace,
+            Self::Workspace => Self::Directory,
+            Self::Directory => Self::Session,
+            Self::Session => Self::Global,
+        }
+    }
+}
+
+// Key chords for each action in the interactive search, such as "ctrl-f"
+#[derive(Clone, Debug, Deserialize)]
+pub struct Keys {
+    pub accept: String,
+    pub edit: String,
+    pub mark: String,
+    pub copy: String,
+    pub inspect: String,
+    pub delete: String,
+    pub pin: String,
+    pub exit: String,
+    pub cycle_filter: String,
+    pub toggle_session: String,
+    pub up: String,
+    pub down: String,
+}
+
+// How much history to keep. An empty max_age, or a max_entries of 0, is no
+// limit
+#[derive(Clone, Debug, Deserialize)]
+pub struct Retention {
+    pub max_age: String,
+    pub max_entries: i64,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct Settings {
     pub dialect: String,
     pub auto_sync: bool,
+    pub offline: bool,
     pub sync_address: String,
     pub sync_frequency: String,
+    pub sync_batch_size: i64,
+
+    // in seconds, for a whole request and for connecting. 0 is no limit
+    pub network_timeout: u64,
+    pub network_connect_timeout: u64,
+
+    // a PEM bundle of extra CAs to trust, for servers behind an internal CA
+    pub ca_cert_path: String,
+    pub verify_tls: bool,
+
+    // empty to use HTTP_PROXY and HTTPS_PROXY
+    pub proxy: String,
     pub db_path: String,
     pub key_path: String,
+    pub encryption: Algorithm,
     pub session_path: String,
+    pub search_mode: SearchMode,
+    pub dedup: bool,
+    pub show_details: bool,
+    pub show_preview: bool,
+    pub keymap_mode: KeymapMode,
+    pub keys: Keys,
+    pub join_marked: JoinMode,
+    pub enter_accept: bool,
+    pub inline_height: u16,
+    pub confirm_delete: bool,
+    pub secrets_filter: bool,
+    pub history_filter: Vec<String>,
+    pub cwd_filter: Vec<String>,
+    pub tag_rules: Vec<TagRule>,
+    pub retention: Retention,
 
     // This is automatically loaded when settings is created. Do not set in
     // config! Keep secrets and settings apart.
     pub session_token: String,
 }
 
+// An api token to use instead of a login session, for scripts and CI
+pub const API_TOKEN_VAR: &str = "ATUIN_API_TOKEN";
+
 impl Settings {
     pub fn save_sync_time() -> Result<()> {
         let data_dir = atuin_common::utils::data_dir();
@@ -53,21 +331,130 @@ impl Settings {
         Ok(time.with_timezone(&Utc))
     }
 
+    pub fn save_sync_attempt() -> Result<()> {
+        let data_dir = atuin_common::utils::data_dir();
+        let data_dir = data_dir.as_path();
+
+        let sync_attempt_path = data_dir.join("last_sync_attempt");
+
+        std::fs::write(sync_attempt_path, Utc::now().to_rfc3339())?;
+
+        Ok(())
+    }
+
+    pub fn last_sync_attempt() -> Result<chrono::DateTime<Utc>> {
+        let data_dir = atuin_common::utils::data_dir();
+        let data_dir = data_dir.as_path();
+
+        let sync_attempt_path = data_dir.join("last_sync_attempt");
+
+        if !sync_attempt_path.exists() {
+            return Ok(Utc.ymd(1970, 1, 1).and_hms(0, 0, 0));
+        }
+
+        let time = std::fs::read_to_string(sync_attempt_path)?;
+        let time = chrono::DateTime::parse_from_rfc3339(time.as_str())?;
+
+        Ok(time.with_timezone(&Utc))
+    }
+
+    // the server's sync cursor, as of the last history we downloaded
+    pub fn sync_cursor() -> Result<i64> {
+        let data_dir = atuin_common::utils::data_dir();
+        let data_dir = data_dir.as_path();
+
+        let sync_cursor_path = data_dir.join("sync_cursor");
+
+        if !sync_cursor_path.exists() {
+            return Ok(0);
+        }
+
+        let cursor = std::fs::read_to_string(sync_cursor_path)?;
+
+        Ok(cursor.trim().parse()?)
+    }
+
+    pub fn save_sync_cursor(cursor: i64) -> Result<()> {
+        let data_dir = atuin_common::utils::data_dir();
+        let data_dir = data_dir.as_path();
+
+        let sync_cursor_path = data_dir.join("sync_cursor");
+
+        std::fs::write(sync_cursor_path, cursor.to_string())?;
+
+        Ok(())
+    }
+
+    // the server's cursor for deletions, as of the last we applied
+    pub fn deleted_cursor() -> Result<i64> {
+        let data_dir = atuin_common::utils::data_dir();
+        let data_dir = data_dir.as_path();
+
+        let deleted_cursor_path = data_dir.join("deleted_cursor");
+
+        if !deleted_cursor_path.exists() {
+            return Ok(0);
+        }
+
+        let cursor = std::fs::read_to_string(deleted_cursor_path)?;
+
+        Ok(cursor.trim().parse()?)
+    }
+
+    pub fn save_deleted_cursor(cursor: i64) -> Result<()> {
+        let data_dir = atuin_common::utils::data_dir();
+        let data_dir = data_dir.as_path();
+
+        let deleted_cursor_path = data_dir.join("deleted_cursor");
+
+        std::fs::write(deleted_cursor_path, cursor.to_string())?;
+
+        Ok(())
+    }
+
+    // Logged in, or given an api token
+    pub fn can_sync(&self) -> bool {
+        Path::new(self.session_path.as_str()).exists() || std::env::var(API_TOKEN_VAR).is_ok()
+    }
+
     pub fn should_sync(&self) -> Result<bool> {
-        if !self.auto_sync {
+        if !self.auto_sync || self.offline || !self.can_sync() {
             return Ok(false);
         }
 
+        // a sync that was started but has not finished (or failed) counts
+        // too, so that a slow or broken sync is not retried on every command
+        let last = std::cmp::max(Settings::last_sync()?, Settings::last_sync_attempt()?);
+
         match parse(self.sync_frequency.as_str()) {
             Ok(d) => {
                 let d = chrono::Duration::from_std(d).unwrap();
-                Ok(Utc::now() - Settings::last_sync()? >= d)
+                Ok(Utc::now() - last >= d)
             }
             Err(e) => Err(eyre!("failed to check sync: {}", e)),
         }
     }
 
-    pub fn new() -> Result<Self> {
+    pub fn config_file() -> PathBuf {
+        let mut config_file = if let Ok(p) = std::env::var("ATUIN_CONFIG_DIR") {
+            PathBuf::from(p)
+        } else {
+            atuin_common::utils::config_dir()
+        };
+
+        config_file.push("config.toml");
+        config_file
+    }
+
+    // The commented example config, written out when there isn't one yet. It
+    // lists every setting
+    pub const fn example_config() -> &'static str {
+        include_str!("../config.toml")
+    }
+
+    // Every setting, with the defaults, config file and environment merged, but
+    // not yet checked
+    pub fn builder() -> Result<Config> {
         let config_dir = atuin_common::utils::config_dir();
         let config_dir = config_dir.as_path();
 
@@ -77,15 +464,12 @@ impl Settings {
         create_dir_all(config_dir)?;
         create_dir_all(data_dir)?;
 
-        let mut config_file = if let Ok(p) = std::env::var("ATUIN_CONFIG_DIR") {
-            PathBuf::from(p)
-        } else {
-            let mut config_file = PathBuf::new();
-            config_file.push(config_dir);
-            config_file
-        };
+        // legacy files belong to the default profile
+        if atuin_common::utils::profile().is_none() {
+            migrate_legacy_paths(config_dir, data_dir);
+        }
 
-        config_file.push("config.toml");
+        let config_file = Self::config_file();
 
         let mut s = Config::new();
 
@@ -95,21 +479,57 @@ impl Settings {
 
         s.set_default("db_path", db_path.to_str())?;
         s.set_default("key_path", key_path.to_str())?;
+        s.set_default("encryption", "secretbox")?;
         s.set_default("session_path", session_path.to_str())?;
         s.set_default("dialect", "us")?;
         s.set_default("auto_sync", true)?;
+        s.set_default("offline", false)?;
         s.set_default("sync_frequency", "1h")?;
         s.set_default("sync_address", "https://api.atuin.sh")?;
+        s.set_default("sync_batch_size", 1000)?;
+        s.set_default("network_timeout", 30)?;
+        s.set_default("network_connect_timeout", 5)?;
+        s.set_default("ca_cert_path", "")?;
+        s.set_default("verify_tls", true)?;
+        s.set_default("proxy", "")?;
+        s.set_default("search_mode", "prefix")?;
+        s.set_default("dedup", true)?;
+        s.set_default("show_details", true)?;
+        s.set_default("show_preview", true)?;
+        s.set_default("keymap_mode", "emacs")?;
+        s.set_default("join_marked", "and")?;
+        s.set_default("enter_accept", false)?;
+        s.set_default("inline_height", 0)?;
+        s.set_default("confirm_delete", true)?;
+        s.set_default("keys.accept", "enter")?;
+        s.set_default("keys.edit", "right")?;
+        s.set_default("keys.mark", "tab")?;
+        s.set_default("keys.copy", "ctrl-y")?;
+        s.set_default("keys.inspect", "ctrl-o")?;
+        s.set_default("keys.delete", "ctrl-d")?;
+        s.set_default("keys.pin", "ctrl-p")?;
+        s.set_default("keys.exit", "esc")?;
+        s.set_default("keys.cycle_filter", "ctrl-f")?;
+        s.set_default("keys.toggle_session", "ctrl-s")?;
+        s.set_default("keys.up", "up")?;
+        s.set_default("keys.down", "down")?;
+        s.set_default("secrets_filter", true)?;
+        s.set_default("history_filter", Vec::<String>::new())?;
+        s.set_default("cwd_filter", Vec::<String>::new())?;
+        s.set_default("tag_rules", Vec::<String>::new())?;
+        s.set_default("retention.max_age", "")?;
+        s.set_default("retention.max_entries", 0)?;
 
         if config_file.exists() {
             s.merge(ConfigFile::with_name(config_file.to_str().unwrap()))?;
         } else {
-            let example_config = include_bytes!("../config.toml");
             let mut file = File::create(config_file)?;
-            file.write_all(example_config)?;
+            file.write_all(Self::example_config().as_bytes())?;
         }
 
-        s.merge(Environment::with_prefix("atuin").separator("_"))?;
+        // ATUIN_SYNC_ADDRESS sets sync_address, and a double underscore reaches
+        // into a table, so ATUIN_KEYS__ACCEPT sets keys.accept
+        s.merge(Environment::with_prefix("atuin").separator("__"))?;
 
         // all paths should be expanded
         let db_path = s.get_str("db_path")?;
@@ -124,9 +544,22 @@ impl Settings {
         let session_path = shellexpand::full(session_path.as_str())?;
         s.set("session_path", session_path.to_string())?;
 
+        let ca_cert_path = s.get_str("ca_cert_path")?;
+        let ca_cert_path = shellexpand::full(ca_cert_path.as_str())?;
+        s.set("ca_cert_path", ca_cert_path.to_string())?;
+
+        Ok(s)
+    }
+
+    pub fn new() -> Result<Self> {
+        let mut s = Self::builder()?;
+        let session_path = s.get_str("session_path")?;
+
         // Finally, set the auth token
-        if Path::new(session_path.to_string().as_str()).exists() {
-            let token = std::fs::read_to_string(session_path.to_string())?;
+        if let Ok(token) = std::env::var(API_TOKEN_VAR) {
+            s.set("session_token", token.trim())?;
+        } else if Path::new(session_path.as_str()).exists() {
+            let token = std::fs::read_to_string(session_path.as_str())?;
             s.set("session_token", token.trim())?;
         } else {
             s.set("session_token", "not logged in")?;
@@ -1,147 +1,363 @@
-use std::convert::TryInto;
+use std::convert::TryFrom;
 
-use chrono::prelude::*;
-use eyre::Result;
+use eyre::{eyre, Result};
+use sodiumoxide::crypto::secretbox;
 
-use atuin_common::{api::AddHistoryRequest, utils::hash_str};
+use atuin_common::{
+    api::{AddHistoryRequest, DeleteHistoryRequest, RecordRequest},
+    utils::{hash_str, version_older},
+};
 
 use crate::api_client;
 use crate::database::Database;
-use crate::encryption::{encrypt, load_encoded_key, load_key};
+use crate::encryption::{
+    commit_pending_key, encode_key, encrypt, encrypt_record, load_key, load_pending_key,
+    save_pending_key, Algorithm,
+};
+use crate::history::History;
+use crate::record::Record;
+use crate::retention;
 use crate::settings::{Settings, HISTORY_PAGE_SIZE};
 
-// Currently sync is kinda naive, and basically just pages backwards through
-// history. This means newly added stuff shows up properly! We also just use
-// the total count in each database to indicate whether a sync is needed.
-// I think this could be massively improved! If we had a way of easily
-// indicating count per time period (hour, day, week, year, etc) then we can
-// easily pinpoint where we are missing data and what needs downloading. Start
-// with year, then find the week, then the day, then the hour, then download it
-// all! The current naive approach will do for now.
-
-// Check if remote has things we don't, and if so, download them.
-// Returns (num downloaded, total local)
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// Sync is incremental in both directions. The server gives every piece of
+// history it stores an index that only goes up, and we keep a cursor of the
+// last one we downloaded. Each sync then only asks for history after the
+// cursor. Locally, history is marked once it has been uploaded (history that
+// came from the server is saved already marked), so only new history is sent.
+// Deletions are synced the same way, with a cursor and marks of their own.
+
+// Download anything the server has had since our cursor.
+// Returns the number of items downloaded
 async fn sync_download(
     force: bool,
     client: &api_client::Client<'_>,
     db: &mut (impl Database + Send),
-) -> Result<(i64, i64)> {
+) -> Result<usize> {
     debug!("starting sync download");
 
-    let remote_count = client.count().await?;
+    // forcing starts from the beginning, and includes history from this host
+    let mut cursor = if force { 0 } else { Settings::sync_cursor()? };
+    let host = if force { Some(String::from("")) } else { None };
 
-    let initial_local = db.history_count().await?;
-    let mut local_count = initial_local;
+    let mut downloaded = 0;
 
-    let mut last_sync = if force {
-        Utc.timestamp_millis(0)
-    } else {
-        Settings::last_sync()?
-    };
+    loop {
+        let (page, next) = client.get_history_after(cursor, host.clone()).await?;
 
-    let mut last_timestamp = Utc.timestamp_millis(0);
+        db.save_synced(&page).await?;
+        Settings::save_sync_cursor(next)?;
 
-    let host = if force { Some(String::from("")) } else { None };
+        downloaded += page.len();
+        debug!("download cursor: {}", next);
+
+        if page.len() < usize::try_from(HISTORY_PAGE_SIZE)? {
+            break;
+        }
+
+        cursor = next;
+    }
 
-    while remote_count > local_count {
-        let page = client
-            .get_history(last_sync, last_timestamp, host.clone())
-            .await?;
+    Ok(downloaded)
+}
 
-        db.save_bulk(&page).await?;
+fn encrypt_request(
+    h: History,
+    key: &secretbox::Key,
+    algorithm: Algorithm,
+) -> Result<AddHistoryRequest> {
+    let data = encrypt(&h, key, algorithm)?;
+    let data = serde_json::to_string(&data)?;
+
+    Ok(AddHistoryRequest {
+        id: h.id,
+        timestamp: h.timestamp,
+        data,
+        hostname: hash_str(h.hostname.as_str()),
+    })
+}
 
-        local_count = db.history_count().await?;
+// Upload anything we have not uploaded yet.
+// Returns the number of items uploaded
+async fn sync_upload(
+    settings: &Settings,
+    client: &api_client::Client<'_>,
+    db: &mut (impl Database + Send),
+) -> Result<usize> {
+    debug!("starting sync upload");
+
+    let key = load_key(settings)?; // encryption key
+    let batch_size = std::cmp::max(settings.sync_batch_size, 1);
+    let mut uploaded = 0;
 
-        if page.len() < HISTORY_PAGE_SIZE.try_into().unwrap() {
+    loop {
+        let page = db.not_uploaded(batch_size).await?;
+
+        if page.is_empty() {
             break;
         }
 
-        let page_last = page
-            .last()
-            .expect("could not get last element of page")
-            .timestamp;
-
-        // in the case of a small sync frequency, it's possible for history to
-        // be "lost" between syncs. In this case we need to rewind the sync
-        // timestamps
-        if page_last == last_timestamp {
-            last_timestamp = Utc.timestamp_millis(0);
-            last_sync = last_sync - chrono::Duration::hours(1);
-        } else {
-            last_timestamp = page_last;
-        }
+        let ids: Vec<String> = page.iter().map(|h| h.id.clone()).collect();
+
+        let buffer = page
+            .iter()
+            .cloned()
+            .map(|h| encrypt_request(h, &key, settings.encryption))
+            .collect::<Result<Vec<AddHistoryRequest>>>()?;
+
+        // anything the server refused is marked uploaded all the same, or it
+        // would be sent again on every sync
+        let rejected = client.post_history(&buffer).await?;
+        warn_rejected(&page, &rejected);
+
+        db.set_uploaded(&ids).await?;
+
+        uploaded += ids.len();
+        debug!("uploaded {} items", uploaded);
     }
 
-    Ok((local_count - initial_local, local_count))
+    Ok(uploaded)
 }
 
-// Check if we have things remote doesn't, and if so, upload them
-async fn sync_upload(
-    settings: &Settings,
-    _force: bool,
+fn warn_rejected(history: &[History], rejected: &[String]) {
+    for h in history.iter().filter(|h| rejected.contains(&h.id)) {
+        eprintln!(
+            "the server refused to store history {}, it will not sync: {}",
+            h.id, h.command
+        );
+    }
+}
+
+// Deleting history leaves a tombstone behind. Upload any we have not uploaded
+// yet, so that other machines can delete their copy too
+async fn sync_delete_upload(
     client: &api_client::Client<'_>,
     db: &mut (impl Database + Send),
 ) -> Result<()> {
-    debug!("starting sync upload");
+    debug!("starting sync delete upload");
+
+    let deleted = db.deleted_not_uploaded().await?;
 
-    let initial_remote_count = client.count().await?;
-    let mut remote_count = initial_remote_count;
+    if deleted.is_empty() {
+        return Ok(());
+    }
 
-    let local_count = db.history_count().await?;
+    debug!("uploading {} deletions", deleted.len());
 
-    debug!("remote has {}, we have {}", remote_count, local_count);
+    let buffer: Vec<DeleteHistoryRequest> = deleted
+        .iter()
+        .cloned()
+        .map(|client_id| DeleteHistoryRequest { client_id })
+        .collect();
 
-    let key = load_key(settings)?; // encryption key
+    client.delete_history(&buffer).await?;
+
+    db.set_deleted_uploaded(&deleted).await
+}
+
+// Apply any deletions the server has had since our cursor, which it keeps
+// apart from the history one
+async fn sync_delete_download(
+    force: bool,
+    client: &api_client::Client<'_>,
+    db: &mut (impl Database + Send),
+) -> Result<()> {
+    debug!("starting sync delete download");
+
+    let mut cursor = if force {
+        0
+    } else {
+        Settings::deleted_cursor()?
+    };
 
-    // first just try the most recent set
+    loop {
+        let (page, next) = client.get_deleted_after(cursor).await?;
 
-    let mut cursor = Utc::now();
+        debug!("applying {} deletions", page.len());
 
-    while local_count > remote_count {
-        let last = db.before(cursor, HISTORY_PAGE_SIZE).await?;
-        let mut buffer = Vec::<AddHistoryRequest>::new();
+        db.delete_synced(&page).await?;
+        Settings::save_deleted_cursor(next)?;
 
-        if last.is_empty() {
+        if page.len() < usize::try_from(HISTORY_PAGE_SIZE)? {
             break;
         }
 
-        for i in last {
-            let data = encrypt(&i, &key)?;
-            let data = serde_json::to_string(&data)?;
+        cursor = next;
+    }
+
+    Ok(())
+}
 
-            let add_hist = AddHistoryRequest {
-                id: i.id,
-                timestamp: i.timestamp,
-                data,
-                hostname: hash_str(i.hostname.as_str()),
-            };
+fn encrypt_record_request(
+    r: &Record,
+    key: &secretbox::Key,
+    algorithm: Algorithm,
+) -> Result<RecordRequest> {
+    let data = encrypt_record(r, key, algorithm)?;
+    let data = serde_json::to_string(&data)?;
+
+    Ok(RecordRequest {
+        key: r.hash(),
+        data,
+        updated_at: r.updated_at,
+    })
+}
 
-            buffer.push(add_hist);
+// There are few records, and they are small, so rather than keeping a cursor
+// every one is downloaded each time. Whichever copy was changed last wins.
+// Returns the number downloaded and uploaded
+async fn sync_records(
+    settings: &Settings,
+    client: &api_client::Client<'_>,
+    db: &mut (impl Database + Send),
+) -> Result<(usize, usize)> {
+    debug!("starting record sync");
+
+    let downloaded = match client.get_records().await? {
+        Some(records) => records,
+        None => {
+            debug!("the server does not support records, skipping");
+            return Ok((0, 0));
         }
+    };
+
+    for r in &downloaded {
+        db.save_synced_record(r).await?;
+    }
 
-        // anything left over outside of the 100 block size
-        client.post_history(&buffer).await?;
-        cursor = buffer.last().unwrap().timestamp;
-        remote_count = client.count().await?;
+    let key = load_key(settings)?;
+    let upload = db.records_not_uploaded().await?;
 
-        debug!("upload cursor: {:?}", cursor);
+    // each page is marked uploaded once posted, refused or not, as history is
+    for page in upload.chunks(record_batch_size(settings)?) {
+        post_records(settings, client, page, &key).await?;
+        db.set_records_uploaded(page).await?;
+    }
+
+    Ok((downloaded.len(), upload.len()))
+}
+
+fn record_batch_size(settings: &Settings) -> Result<usize> {
+    Ok(usize::try_from(std::cmp::max(settings.sync_batch_size, 1))?)
+}
+
+async fn post_records(
+    settings: &Settings,
+    client: &api_client::Client<'_>,
+    records: &[Record],
+    key: &secretbox::Key,
+) -> Result<()> {
+    let buffer = records
+        .iter()
+        .map(|r| encrypt_record_request(r, key, settings.encryption))
+        .collect::<Result<Vec<RecordRequest>>>()?;
+
+    let rejected = client.post_records(&buffer).await?;
+
+    for r in records.iter().filter(|r| rejected.contains(&r.hash())) {
+        eprintln!(
+            "the server refused to store {} {}, it will not sync",
+            r.namespace, r.key
+        );
     }
 
     Ok(())
 }
 
+// Re-encrypt all of our history with a new key, and replace what the server
+// has with it. The new key is the one given, or else a fresh one. It is saved
+// as pending before anything is uploaded, and only replaces the old one once
+// everything has been. If this fails part way through, running it again
+// finishes the job with the same key. Returns the new key.
+pub async fn rotate_key(
+    settings: &Settings,
+    db: &mut (impl Database + Send),
+    key: Option<secretbox::Key>,
+) -> Result<String> {
+    let client = api_client::Client::new(settings)?;
+
+    let key = match (key, load_pending_key(settings)?) {
+        (Some(_), Some(_)) => {
+            return Err(eyre!(
+                "a key rotation was interrupted, finish it first with atuin key rotate"
+            ));
+        }
+        (None, Some(key)) => {
+            debug!("resuming an interrupted key rotation");
+            key
+        }
+        (key, None) => {
+            let key = key.unwrap_or_else(secretbox::gen_key);
+            save_pending_key(settings, &key)?;
+            key
+        }
+    };
+
+    let history = db.list(None, false).await?;
+
+    let batch_size = usize::try_from(std::cmp::max(settings.sync_batch_size, 1))?;
+
+    for page in history.chunks(batch_size) {
+        let buffer = page
+            .iter()
+            .cloned()
+            .map(|h| encrypt_request(h, &key, settings.encryption))
+            .collect::<Result<Vec<AddHistoryRequest>>>()?;
+
+        let rejected = client.put_history(&buffer).await?;
+        warn_rejected(page, &rejected);
+    }
+
+    // records keep their time, so that these replace the copies on the server
+    // without winning over any newer change. any newer change is fetched
+    // first, or it would win over the re-encrypted copy, and be left
+    // encrypted with the old key
+    sync_records(settings, &client, db).await?;
+
+    for page in db.all_records().await?.chunks(record_batch_size(settings)?) {
+        post_records(settings, &client, page, &key).await?;
+    }
+
+    commit_pending_key(settings)?;
+
+    encode_key(key)
+}
+
 pub async fn sync(settings: &Settings, force: bool, db: &mut (impl Database + Send)) -> Result<()> {
-    let client = api_client::Client::new(
-        settings.sync_address.as_str(),
-        settings.session_token.as_str(),
-        load_encoded_key(settings)?,
-    )?;
+    let client = api_client::Client::new(settings)?;
+
+    // carry on regardless, in case it's only the newest features that are
+    // missing. any request that does fail will say so
+    if let Some(index) = client.index().await? {
+        if version_older(VERSION, index.min_client_version.as_str()) {
+            eprintln!(
+                "atuin {} is too old for this server (version {}), which needs at least {}. please upgrade",
+                VERSION, index.version, index.min_client_version
+            );
+        }
+    }
+
+    // before uploading, so that anything expired is deleted from the server
+    // by this sync
+    let expired = retention::expire(settings, db).await?;
+    debug!("sync expired {}", expired);
 
-    sync_upload(settings, force, &client, db).await?;
+    sync_delete_upload(&client, db).await?;
+
+    let upload = sync_upload(settings, &client, db).await?;
+    debug!("sync uploaded {}", upload);
 
     let download = sync_download(force, &client, db).await?;
+    debug!("sync downloaded {}", download);
+
+    sync_delete_download(force, &client, db).await?;
 
-    debug!("sync downloaded {}", download.0);
+    let (records_down, records_up) = sync_records(settings, &client, db).await?;
+    debug!(
+        "sync records downloaded {}, uploaded {}",
+        records_down, records_up
+    );
 
     Settings::save_sync_time()?;
 
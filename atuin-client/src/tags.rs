@@ -0,0 +1,130 @@
+// Tags label history, either by hand with `atuin history tag`, or as commands
+// are ran by matching tag_rules. They are kept in the local database only, as
+// history is synced in a fixed format that older clients have to be able to
+// read
+
+use eyre::{eyre, Result};
+use regex::Regex;
+
+use crate::settings::Settings;
+
+const MAX_TAG_LENGTH: usize = 64;
+
+// Tags are stored together in one column, so they can't have a comma, and are
+// kept to characters that are easy to type on the command line
+pub fn valid(tag: &str) -> bool {
+    !tag.is_empty()
+        && tag.len() <= MAX_TAG_LENGTH
+        && tag
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ':' | '/'))
+}
+
+pub fn check(tags: &[String]) -> Result<()> {
+    match tags.iter().find(|t| !valid(t)) {
+        Some(tag) => Err(eyre!(
+            "invalid tag {:?}, tags are letters, numbers and - _ . : / only",
+            tag
+        )),
+        None => Ok(()),
+    }
+}
+
+// As the database column, each tag wrapped in commas
+pub fn encode(tags: &[String]) -> String {
+    let mut tags: Vec<&str> = tags.iter().map(String::as_str).collect();
+    tags.sort_unstable();
+    tags.dedup();
+
+    if tags.is_empty() {
+        return String::new();
+    }
+
+    format!(",{},", tags.join(","))
+}
+
+pub fn decode(tags: &str) -> Vec<String> {
+    tags.split(',')
+        .filter(|t| !t.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TagRule {
+    pub tag: String,
+    pub pattern: String,
+}
+
+// The tag_rules setting, ready to match commands against
+pub struct TagRules {
+    rules: Vec<(String, Regex)>,
+}
+
+impl TagRules {
+    // a bad rule is skipped with a warning, rather than breaking the shell's
+    // hook for every command
+    pub fn new(settings: &Settings) -> Self {
+        let rules = settings
+            .tag_rules
+            .iter()
+            .filter_map(|rule| {
+                if !valid(rule.tag.as_str()) {
+                    warn!("invalid tag {:?} in tag_rules, skipping it", rule.tag);
+                    return None;
+                }
+
+                match Regex::new(rule.pattern.as_str()) {
+                    Ok(pattern) => Some((rule.tag.clone(), pattern)),
+                    Err(e) => {
+                        warn!(
+                            "invalid tag_rules pattern for {}, skipping it: {}",
+                            rule.tag, e
+                        );
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        Self { rules }
+    }
+
+    // every tag with a pattern matching the command
+    pub fn tags(&self, command: &str) -> Vec<String> {
+        self.rules
+            .iter()
+            .filter(|(_, pattern)| pattern.is_match(command))
+            .map(|(tag, _)| tag.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode, encode, valid};
+
+    #[test]
+    fn test_encode_decode() {
+        let tags = vec![
+            String::from("prod"),
+            String::from("deploy"),
+            String::from("prod"),
+        ];
+
+        assert_eq!(encode(&tags), ",deploy,prod,");
+        assert_eq!(decode(",deploy,prod,"), vec!["deploy", "prod"]);
+
+        assert_eq!(encode(&[]), "");
+        assert!(decode("").is_empty());
+    }
+
+    #[test]
+    fn test_valid() {
+        assert!(valid("deploy"));
+        assert!(valid("team/infra"));
+        assert!(!valid(""));
+        assert!(!valid("a,b"));
+        assert!(!valid("two words"));
+    }
+}
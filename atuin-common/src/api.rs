@@ -10,6 +10,16 @@ pub struct RegisterRequest {
     pub email: String,
     pub username: String,
     pub password: String,
+
+    // only needed when the server does not have open registration
+    pub invite: Option<String>,
+
+    // the hashed hostname and a name for the device registering, see
+    // LoginRequest
+    #[serde(default)]
+    pub host: Option<String>,
+    #[serde(default)]
+    pub device: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -21,6 +31,17 @@ pub struct RegisterResponse {
 pub struct LoginRequest {
     pub username: String,
     pub password: String,
+
+    // only needed when the user has 2fa enabled
+    #[serde(default)]
+    pub totp: Option<String>,
+
+    // the hashed hostname of the device logging in, and a name to recognise
+    // it by. the session is tied to the device, so that it can be revoked
+    #[serde(default)]
+    pub host: Option<String>,
+    #[serde(default)]
+    pub device: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -28,6 +49,61 @@ pub struct LoginResponse {
     pub session: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TotpEnableResponse {
+    pub secret: String,
+
+    // an otpauth:// link, for authenticator apps
+    pub url: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TotpCodeRequest {
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateTokenRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateTokenResponse {
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub name: String,
+    pub created_at: chrono::DateTime<Utc>,
+    pub last_used_at: Option<chrono::DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListTokensResponse {
+    pub tokens: Vec<TokenResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceResponse {
+    pub host: String,
+    pub name: String,
+    pub created_at: chrono::DateTime<Utc>,
+    pub last_login_at: chrono::DateTime<Utc>,
+    pub revoked_at: Option<chrono::DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListDevicesResponse {
+    pub devices: Vec<DeviceResponse>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AddHistoryRequest {
     pub id: String,
@@ -36,11 +112,63 @@ pub struct AddHistoryRequest {
     pub hostname: String,
 }
 
+// The ids of any history that wasn't stored, for being over the size limit or
+// not looking encrypted. Servers from before this was added turn away the
+// whole upload instead
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AddHistoryResponse {
+    pub rejected: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteHistoryRequest {
+    pub client_id: String,
+}
+
+// Records are small bits of state other than history, such as aliases. The key
+// is a hash and the data is encrypted, so all the server knows about a record
+// is when it was last changed. The most recent change to each key wins
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordRequest {
+    pub key: String,
+    pub data: String,
+    pub updated_at: chrono::DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordResponse {
+    pub key: String,
+    pub data: String,
+    pub updated_at: chrono::DateTime<Utc>,
+}
+
+// The keys of any records that weren't stored, for being over the size limit
+// or not looking encrypted. Servers from before this was added turn away the
+// whole upload instead
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AddRecordsResponse {
+    pub rejected: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListRecordsResponse {
+    pub records: Vec<RecordResponse>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CountResponse {
     pub count: i64,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatusResponse {
+    pub username: String,
+    pub count: i64,
+
+    // the (hashed) hosts that have uploaded history
+    pub hosts: Vec<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SyncHistoryRequest {
     pub sync_ts: chrono::DateTime<chrono::FixedOffset>,
@@ -53,6 +181,57 @@ pub struct SyncHistoryResponse {
     pub history: Vec<String>,
 }
 
+// History is synced by cursor. The cursor is an index that only ever goes up
+// as the server stores history, so each sync only has to ask for what came
+// after the last one
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SyncCursorRequest {
+    pub cursor: i64,
+    pub host: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SyncCursorResponse {
+    pub history: Vec<String>,
+    pub cursor: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SyncDeletedRequest {
+    pub since: chrono::DateTime<chrono::FixedOffset>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SyncDeletedResponse {
+    pub deleted: Vec<String>,
+}
+
+// Deletions are synced by cursor too, with one of their own
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SyncDeletedCursorRequest {
+    pub cursor: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SyncDeletedCursorResponse {
+    pub deleted: Vec<String>,
+    pub cursor: i64,
+}
+
+// Returned from the index, so that clients can check they are new enough to
+// sync with the server
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IndexResponse {
+    pub homage: String,
+    pub version: String,
+    pub min_client_version: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HealthResponse {
+    pub status: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ErrorResponse {
     pub reason: String,
@@ -0,0 +1,131 @@
+use std::io::{Read, Write};
+
+use eyre::{eyre, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+
+// The encodings we can compress bodies with, in order of preference. Sent as
+// Accept-Encoding by both sides - the client to ask for compressed responses,
+// and the server to say which request bodies it can take.
+pub const ACCEPT: &str = "zstd, gzip";
+
+// Bodies smaller than this aren't worth the effort
+pub const MIN_SIZE: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    Zstd,
+    Gzip,
+}
+
+impl Encoding {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Zstd => "zstd",
+            Self::Gzip => "gzip",
+        }
+    }
+
+    // parse a Content-Encoding header
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "zstd" => Some(Self::Zstd),
+            "gzip" | "x-gzip" => Some(Self::Gzip),
+            _ => None,
+        }
+    }
+
+    // pick the best encoding from an Accept-Encoding header, ignoring any
+    // quality values other than q=0
+    pub fn negotiate(accept: &str) -> Option<Self> {
+        let accepted: Vec<Self> = accept
+            .split(',')
+            .filter_map(|e| {
+                let mut parts = e.split(';');
+                let name = parts.next()?;
+
+                if parts.any(|p| p.trim() == "q=0") {
+                    return None;
+                }
+
+                Self::from_name(name)
+            })
+            .collect();
+
+        [Self::Zstd, Self::Gzip]
+            .iter()
+            .copied()
+            .find(|e| accepted.contains(e))
+    }
+
+    pub fn encode(self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Self::Zstd => Ok(zstd::encode_all(data, 0)?),
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+
+                Ok(encoder.finish()?)
+            }
+        }
+    }
+
+    pub fn decode(self, data: &[u8]) -> Result<Vec<u8>> {
+        self.decode_limited(data, 0)
+    }
+
+    // stops as soon as the decoded data goes over max bytes, rather than
+    // decompressing all of it first, as a small body can expand to a huge
+    // one. 0 is no limit
+    pub fn decode_limited(self, data: &[u8], max: u64) -> Result<Vec<u8>> {
+        let mut reader: Box<dyn Read + '_> = match self {
+            Self::Zstd => Box::new(zstd::stream::read::Decoder::new(data)?),
+            Self::Gzip => Box::new(GzDecoder::new(data)),
+        };
+
+        let mut decoded = Vec::new();
+
+        if max == 0 {
+            reader.read_to_end(&mut decoded)?;
+            return Ok(decoded);
+        }
+
+        reader.take(max + 1).read_to_end(&mut decoded)?;
+
+        if decoded.len() as u64 > max {
+            return Err(eyre!("decoded data is over the limit of {} bytes", max));
+        }
+
+        Ok(decoded)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Encoding;
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(Encoding::negotiate("gzip, zstd"), Some(Encoding::Zstd));
+        assert_eq!(
+            Encoding::negotiate("gzip, deflate, br"),
+            Some(Encoding::Gzip)
+        );
+        assert_eq!(Encoding::negotiate("zstd;q=0, gzip"), Some(Encoding::Gzip));
+        assert_eq!(Encoding::negotiate("identity"), None);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data = "ls -lah\n".repeat(1000);
+
+        for e in &[Encoding::Zstd, Encoding::Gzip] {
+            let encoded = e.encode(data.as_bytes()).unwrap();
+            assert!(encoded.len() < data.len());
+            assert_eq!(e.decode(&encoded).unwrap(), data.as_bytes());
+
+            assert!(e.decode_limited(&encoded, data.len() as u64).is_ok());
+            assert!(e.decode_limited(&encoded, 100).is_err());
+        }
+    }
+}
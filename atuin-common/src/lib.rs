@@ -2,4 +2,5 @@
 extern crate serde_derive;
 
 pub mod api;
+pub mod compression;
 pub mod utils;
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crypto::digest::Digest;
 use crypto::sha2::Sha256;
@@ -30,6 +30,36 @@ pub fn uuid_v4() -> String {
     Uuid::new_v4().to_simple().to_string()
 }
 
+// Walk up from path to find the root of the git repository it is in, if any
+pub fn git_root(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .find(|p| p.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+// Set by --profile, or by the user. Each profile has its own config, database,
+// key and session, in a directory of its own
+pub const PROFILE_VAR: &str = "ATUIN_PROFILE";
+
+pub fn profile() -> Option<String> {
+    std::env::var(PROFILE_VAR).ok().filter(|p| !p.is_empty())
+}
+
+// Profile names become directory names, so keep them to something safe
+pub fn valid_profile(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn with_profile(dir: PathBuf) -> PathBuf {
+    match profile() {
+        Some(p) => dir.join("profiles").join(p),
+        None => dir,
+    }
+}
+
 pub fn config_dir() -> PathBuf {
     // TODO: more reliable, more tested
     // I don't want to use ProjectDirs, it puts config in awkward places on
@@ -37,14 +67,10 @@ pub fn config_dir() -> PathBuf {
     let home = std::env::var("HOME").expect("$HOME not found");
     let home = PathBuf::from(home);
 
-    std::env::var("XDG_CONFIG_HOME").map_or_else(
-        |_| {
-            let mut config = home.clone();
-            config.push(".config");
-            config.push("atuin");
-            config
-        },
-        PathBuf::from,
+    with_profile(
+        std::env::var("XDG_CONFIG_HOME")
+            .map_or_else(|_| home.join(".config"), PathBuf::from)
+            .join("atuin"),
     )
 }
 
@@ -55,14 +81,128 @@ pub fn data_dir() -> PathBuf {
     let home = std::env::var("HOME").expect("$HOME not found");
     let home = PathBuf::from(home);
 
-    std::env::var("XDG_DATA_HOME").map_or_else(
-        |_| {
-            let mut data = home.clone();
-            data.push(".local");
-            data.push("share");
-            data.push("atuin");
-            data
-        },
-        PathBuf::from,
+    with_profile(
+        std::env::var("XDG_DATA_HOME")
+            .map_or_else(|_| home.join(".local").join("share"), PathBuf::from)
+            .join("atuin"),
     )
 }
+
+// Compare dotted version numbers, such as 0.6.2. Anything after a - is
+// ignored, as are parts that aren't numbers
+pub fn version_older(version: &str, than: &str) -> bool {
+    let parse = |v: &str| -> Vec<u64> {
+        v.split('-')
+            .next()
+            .unwrap_or_default()
+            .split('.')
+            .map(|n| n.parse().unwrap_or(0))
+            .collect()
+    };
+
+    parse(version) < parse(than)
+}
+
+// Whether history data is shaped like the client's encrypted history: a
+// ciphertext, its nonce, and which algorithm was used if it isn't secretbox.
+// This can't prove anything is encrypted, but it does catch a client sending
+// plain commands
+pub fn looks_encrypted(data: &str) -> bool {
+    // the nonce and authentication tag of both secretbox and XChaCha20-Poly1305
+    const NONCE_BYTES: usize = 24;
+    const MAC_BYTES: usize = 16;
+    const ALGORITHMS: &[&str] = &["secretbox", "xchacha20poly1305"];
+
+    let value: serde_json::Value = match serde_json::from_str(data) {
+        Ok(v) => v,
+        Err(_) => return false,
+    };
+
+    let fields = match value.get("algorithm") {
+        None => 2,
+        Some(a) if a.as_str().map_or(false, |a| ALGORITHMS.contains(&a)) => 3,
+        Some(_) => return false,
+    };
+
+    // anything else alongside them could be plaintext
+    if value.as_object().map_or(true, |o| o.len() != fields) {
+        return false;
+    }
+
+    let bytes = |key: &str| -> Option<usize> {
+        let array = value.get(key)?.as_array()?;
+
+        array
+            .iter()
+            .all(|b| b.as_u64().map_or(false, |b| b <= 255))
+            .then(|| array.len())
+    };
+
+    matches!(
+        (bytes("ciphertext"), bytes("nonce")),
+        (Some(c), Some(NONCE_BYTES)) if c >= MAC_BYTES
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{looks_encrypted, valid_profile, version_older};
+
+    #[test]
+    fn test_version_older() {
+        assert!(version_older("0.6.2", "0.7.0"));
+        assert!(version_older("0.6.2", "0.6.10"));
+        assert!(!version_older("0.6.2", "0.6.2"));
+        assert!(!version_older("1.0.0", "0.9.9"));
+        assert!(!version_older("0.7.0-beta", "0.7.0"));
+    }
+
+    #[test]
+    fn test_valid_profile() {
+        assert!(valid_profile("work"));
+        assert!(valid_profile("side-project_2"));
+        assert!(!valid_profile(""));
+        assert!(!valid_profile("../work"));
+        assert!(!valid_profile("my work"));
+    }
+
+    #[test]
+    fn test_looks_encrypted() {
+        let nonce = vec![7; 24];
+
+        let encrypted = serde_json::json!({ "ciphertext": vec![1; 40], "nonce": nonce });
+        assert!(looks_encrypted(encrypted.to_string().as_str()));
+
+        let short = serde_json::json!({ "ciphertext": vec![1; 4], "nonce": nonce });
+        assert!(!looks_encrypted(short.to_string().as_str()));
+
+        let not_bytes = serde_json::json!({ "ciphertext": vec![1000; 40], "nonce": nonce });
+        assert!(!looks_encrypted(not_bytes.to_string().as_str()));
+
+        let extra = serde_json::json!({
+            "ciphertext": vec![1; 40],
+            "nonce": nonce,
+            "command": "ls -la",
+        });
+        assert!(!looks_encrypted(extra.to_string().as_str()));
+
+        let tagged = serde_json::json!({
+            "ciphertext": vec![1; 40],
+            "nonce": nonce,
+            "algorithm": "xchacha20poly1305",
+        });
+        assert!(looks_encrypted(tagged.to_string().as_str()));
+
+        let unknown = serde_json::json!({
+            "ciphertext": vec![1; 40],
+            "nonce": nonce,
+            "algorithm": "ls -la",
+        });
+        assert!(!looks_encrypted(unknown.to_string().as_str()));
+
+        assert!(!looks_encrypted("ls -la"));
+        assert!(!looks_encrypted(
+            r#"{"command": "ls -la", "cwd": "/home/ellie"}"#
+        ));
+    }
+}
@@ -0,0 +1,2 @@
+-- deleted history is kept as a tombstone, so that clients can learn about it
+alter table history add column deleted_at timestamp;
@@ -0,0 +1,8 @@
+-- invites allow registering on a server without open registration
+create table invites (
+	id bigserial primary key,
+	token varchar(128) unique not null,
+	user_id bigint,                 -- the user that registered with this invite
+	created_at timestamp not null default current_timestamp,
+	used_at timestamp
+);
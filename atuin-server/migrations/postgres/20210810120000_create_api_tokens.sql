@@ -0,0 +1,12 @@
+-- long lived tokens for scripts and CI, that can be revoked without logging
+-- out. only the sha256 of a token is stored, it's shown once when created
+create table api_tokens (
+	id bigserial primary key,
+	user_id bigint not null,
+	name text not null,
+	token varchar(128) unique not null,
+	created_at timestamp not null default current_timestamp,
+	last_used_at timestamp,
+
+	unique(user_id, name)
+);
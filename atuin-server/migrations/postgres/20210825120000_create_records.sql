@@ -0,0 +1,11 @@
+-- small encrypted records that aren't history, such as aliases. key is a hash
+-- made by the client, and updated_at is when the client last changed it
+create table records (
+	id bigserial primary key,
+	user_id bigint not null,
+	key text not null,
+	data text not null,
+	updated_at timestamp not null,
+
+	unique(user_id, key)
+);
@@ -0,0 +1,3 @@
+-- the device a token was created from, so that revoking the device revokes its
+-- tokens too
+alter table api_tokens add column device_id bigint;
@@ -0,0 +1,10 @@
+-- deletions are synced by cursor, like history. each tombstone is numbered in
+-- the order it was made, rather than by time, so clock skew can't lose any
+create sequence history_deleted_seq;
+
+alter table history add column deleted_seq bigint;
+
+update history set deleted_seq = nextval('history_deleted_seq')
+where deleted_at is not null;
+
+create index history_deleted_seq_idx on history(user_id, deleted_seq);
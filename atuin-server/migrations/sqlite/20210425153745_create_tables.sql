@@ -0,0 +1,28 @@
+create table history (
+	id integer primary key autoincrement,
+	client_id text not null unique, -- the client-generated ID
+	user_id integer not null,       -- allow multiple users
+	hostname text not null,         -- a unique identifier from the client (can be hashed, random, whatever)
+	timestamp timestamp not null,   -- one of the few non-encrypted metadatas
+
+	data text not null,             -- store the actual history data, encrypted. I don't wanna know!
+
+	created_at timestamp not null default current_timestamp,
+	deleted_at timestamp            -- deleted history is kept as a tombstone
+);
+
+create table users (
+	id integer primary key autoincrement,
+	username text not null unique,
+	email text not null unique,
+	password text not null unique
+);
+
+create unique index email_unique_idx on users (lower(email));
+create unique index username_unique_idx on users (lower(username));
+
+create table sessions (
+	id integer primary key autoincrement,
+	user_id integer not null,
+	token text unique not null
+);
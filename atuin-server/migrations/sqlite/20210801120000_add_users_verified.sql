@@ -0,0 +1,8 @@
+-- with smtp configured, new users have to verify their email address before
+-- they can sync. everyone that registered before then is already verified
+alter table users add column verified_at timestamp;
+alter table users add column verification_token text;
+
+update users set verified_at = current_timestamp;
+
+create unique index users_verification_token_idx on users (verification_token);
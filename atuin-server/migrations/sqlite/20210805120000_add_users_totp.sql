@@ -0,0 +1,4 @@
+-- the secret is stored when 2fa is being set up, and only enabled once the
+-- user has confirmed it with a code
+alter table users add column totp_secret text;
+alter table users add column totp_enabled boolean not null default false;
@@ -0,0 +1,12 @@
+-- long lived tokens for scripts and CI, that can be revoked without logging
+-- out. only the sha256 of a token is stored, it's shown once when created
+create table api_tokens (
+	id integer primary key autoincrement,
+	user_id integer not null,
+	name text not null,
+	token text unique not null,
+	created_at timestamp not null default current_timestamp,
+	last_used_at timestamp,
+
+	unique(user_id, name)
+);
@@ -0,0 +1,16 @@
+-- the machines a user syncs from. host is the hash of the hostname, as the
+-- client uploads history with, so the server never learns the real one
+create table devices (
+	id integer primary key autoincrement,
+	user_id integer not null,
+	host text not null,
+	name text not null,
+	created_at timestamp not null default current_timestamp,
+	last_login_at timestamp not null default current_timestamp,
+	revoked_at timestamp,
+
+	unique(user_id, host)
+);
+
+-- the device each session was created for, if the client said
+alter table sessions add column device_id integer;
@@ -0,0 +1,7 @@
+-- deletions are synced by cursor, like history. each tombstone is numbered in
+-- the order it was made, rather than by time, so clock skew can't lose any
+alter table history add column deleted_seq integer;
+
+update history set deleted_seq = id where deleted_at is not null;
+
+create index history_deleted_seq_idx on history(user_id, deleted_seq);
@@ -5,19 +5,82 @@ use sqlx::postgres::PgPoolOptions;
 
 use crate::settings::HISTORY_PAGE_SIZE;
 
-use super::models::{History, NewHistory, NewSession, NewUser, Session, User};
+use super::models::{
+    ApiToken, Device, History, NewHistory, NewRecord, NewSession, NewUser, Record, Session, User,
+};
+
+mod sqlite;
+
+pub use sqlite::Sqlite;
 
 #[async_trait]
 pub trait Database {
     async fn get_session(&self, token: &str) -> Result<Session>;
+    // a session or token belonging to a revoked device isn't found
     async fn get_session_user(&self, token: &str) -> Result<User>;
+    // takes the hash of the token, and marks it as used
+    async fn get_api_token_user(&self, token: &str) -> Result<User>;
     async fn add_session(&self, session: &NewSession) -> Result<()>;
+    async fn delete_session(&self, token: &str) -> Result<()>;
 
     async fn get_user(&self, username: String) -> Result<User>;
     async fn get_user_session(&self, u: &User) -> Result<Session>;
-    async fn add_user(&self, user: NewUser) -> Result<i64>;
+    // returns None if the user's invite has already been used
+    async fn add_user(&self, user: NewUser) -> Result<Option<i64>>;
+    // returns false if no user has the token
+    async fn verify_user(&self, token: &str) -> Result<bool>;
+    async fn set_user_verified(&self, user: &User) -> Result<()>;
+    async fn set_user_totp(&self, user: &User, secret: Option<&str>, enabled: bool) -> Result<()>;
+    async fn list_users(&self) -> Result<Vec<User>>;
+    async fn delete_user(&self, user: &User) -> Result<()>;
+    async fn update_user_password(&self, user: &User, password: &str) -> Result<()>;
+    async fn delete_user_sessions(&self, user: &User) -> Result<()>;
+    async fn delete_other_sessions(&self, user: &User, token: &str) -> Result<()>;
+
+    // expects the token to already be hashed
+    async fn add_api_token(
+        &self,
+        user: &User,
+        name: &str,
+        token: &str,
+        device_id: Option<i64>,
+    ) -> Result<()>;
+    async fn list_api_tokens(&self, user: &User) -> Result<Vec<ApiToken>>;
+    // returns false if the user has no token with the name
+    async fn delete_api_token(&self, user: &User, name: &str) -> Result<bool>;
+
+    // registers the device, or renames it and marks it as logged in if it
+    // already is. a revoked device is left as it is
+    async fn add_device(&self, user_id: i64, host: &str, name: &str) -> Result<Device>;
+    async fn has_devices(&self, user_id: i64) -> Result<bool>;
+    async fn list_devices(&self, user: &User) -> Result<Vec<Device>>;
+    // also deletes the device's sessions and api tokens, along with any that
+    // aren't tied to a device. returns false if the user has no device with
+    // the host
+    async fn revoke_device(&self, user: &User, host: &str) -> Result<bool>;
+
+    // a record only replaces the one already stored under its key if it was
+    // changed at the same time or later
+    async fn add_records(&self, records: &[NewRecord]) -> Result<()>;
+    async fn list_records(&self, user: &User) -> Result<Vec<Record>>;
+
+    async fn add_invite(&self, token: &str) -> Result<()>;
+    async fn valid_invite(&self, token: &str) -> Result<bool>;
+
+    // check the database can be reached
+    async fn ping(&self) -> Result<()>;
+
+    // wait for any queries to finish, then close every connection
+    async fn close(&self);
+
+    async fn migrate(&self) -> Result<()>;
+    // the migrations embedded in this build that have not been applied yet
+    async fn pending_migrations(&self) -> Result<Vec<String>>;
 
     async fn count_history(&self, user: &User) -> Result<i64>;
+    // the number of users, and of history across all of them
+    async fn totals(&self) -> Result<(i64, i64)>;
+    async fn list_hosts(&self, user: &User) -> Result<Vec<String>>;
     async fn list_history(
         &self,
         user: &User,
@@ -25,7 +88,23 @@ pub trait Database {
         since: chrono::NaiveDateTime,
         host: String,
     ) -> Result<Vec<History>>;
+    async fn list_history_after(
+        &self,
+        user: &User,
+        cursor: i64,
+        host: String,
+    ) -> Result<Vec<History>>;
     async fn add_history(&self, history: &[NewHistory]) -> Result<()>;
+    async fn replace_history(&self, history: &[NewHistory]) -> Result<()>;
+    async fn delete_history(&self, user: &User, client_ids: &[String]) -> Result<()>;
+    async fn deleted_history(
+        &self,
+        user: &User,
+        since: chrono::NaiveDateTime,
+    ) -> Result<Vec<String>>;
+    // the client id of each deletion after the cursor, with its place in the
+    // order deletions were made, which is the cursor to ask for next time
+    async fn deleted_history_after(&self, user: &User, cursor: i64) -> Result<Vec<(String, i64)>>;
 }
 
 #[derive(Clone)]
@@ -40,12 +119,49 @@ impl Postgres {
             .connect(uri)
             .await?;
 
-        sqlx::migrate!("./migrations").run(&pool).await?;
-
         Ok(Self { pool })
     }
 }
 
+// Every embedded migration not in applied, by name
+pub fn pending(migrator: &sqlx::migrate::Migrator, applied: &[i64]) -> Vec<String> {
+    migrator
+        .migrations
+        .iter()
+        .filter(|m| !applied.contains(&m.version))
+        .map(|m| format!("{}_{}", m.version, m.description))
+        .collect()
+}
+
+// Ids come from a sequence, so two uploads at once can commit out of order,
+// the later id first. A client syncing in between would move its cursor past
+// the earlier id before that history was visible, and never see it. Uploads
+// for the same user take turns instead, holding the lock until they commit,
+// so a user's history is always committed in id order. Deletions are numbered
+// the same way, and take the same lock
+async fn lock_user(
+    tx: &mut sqlx::Transaction<'_, sqlx::postgres::Postgres>,
+    user_id: i64,
+) -> Result<()> {
+    sqlx::query("select pg_advisory_xact_lock($1)")
+        .bind(user_id)
+        .execute(tx)
+        .await?;
+
+    Ok(())
+}
+
+async fn lock_history(
+    tx: &mut sqlx::Transaction<'_, sqlx::postgres::Postgres>,
+    history: &[NewHistory<'_>],
+) -> Result<()> {
+    if let Some(h) = history.first() {
+        lock_user(tx, h.user_id).await?;
+    }
+
+    Ok(())
+}
+
 #[async_trait]
 impl Database for Postgres {
     async fn get_session(&self, token: &str) -> Result<Session> {
@@ -78,10 +194,13 @@ impl Database for Postgres {
 
     async fn get_session_user(&self, token: &str) -> Result<User> {
         let res: Option<User> = sqlx::query_as::<_, User>(
-            "select * from users 
-            inner join sessions 
-            on users.id = sessions.user_id 
-            and sessions.token = $1",
+            "select users.* from users
+            inner join sessions
+            on users.id = sessions.user_id
+            and sessions.token = $1
+            left join devices
+            on devices.id = sessions.device_id
+            where devices.revoked_at is null",
         )
         .bind(token)
         .fetch_optional(&self.pool)
@@ -94,10 +213,35 @@ impl Database for Postgres {
         }
     }
 
+    async fn get_api_token_user(&self, token: &str) -> Result<User> {
+        let res: Option<User> = sqlx::query_as::<_, User>(
+            "select users.* from users
+            inner join api_tokens
+            on users.id = api_tokens.user_id
+            and api_tokens.token = $1
+            left join devices
+            on devices.id = api_tokens.device_id
+            where devices.revoked_at is null",
+        )
+        .bind(token)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let user = res.ok_or_else(|| eyre!("could not find user"))?;
+
+        sqlx::query("update api_tokens set last_used_at = current_timestamp where token = $1")
+            .bind(token)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(user)
+    }
+
     async fn count_history(&self, user: &User) -> Result<i64> {
         let res: (i64,) = sqlx::query_as(
             "select count(1) from history
-            where user_id = $1",
+            where user_id = $1
+            and deleted_at is null",
         )
         .bind(user.id)
         .fetch_one(&self.pool)
@@ -106,6 +250,69 @@ impl Database for Postgres {
         Ok(res.0)
     }
 
+    async fn ping(&self) -> Result<()> {
+        sqlx::query("select 1").execute(&self.pool).await?;
+
+        Ok(())
+    }
+
+    async fn close(&self) {
+        self.pool.close().await;
+    }
+
+    async fn migrate(&self) -> Result<()> {
+        sqlx::migrate!("./migrations/postgres")
+            .run(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    // the migrations table doesn't exist until the first migration runs
+    async fn pending_migrations(&self) -> Result<Vec<String>> {
+        let (exists,): (bool,) =
+            sqlx::query_as("select to_regclass('_sqlx_migrations') is not null")
+                .fetch_one(&self.pool)
+                .await?;
+
+        let applied: Vec<(i64,)> = if exists {
+            sqlx::query_as("select version from _sqlx_migrations where success")
+                .fetch_all(&self.pool)
+                .await?
+        } else {
+            Vec::new()
+        };
+
+        let applied: Vec<i64> = applied.into_iter().map(|r| r.0).collect();
+
+        Ok(pending(&sqlx::migrate!("./migrations/postgres"), &applied))
+    }
+
+    async fn totals(&self) -> Result<(i64, i64)> {
+        let res: (i64, i64) = sqlx::query_as(
+            "select
+                (select count(1) from users),
+                (select count(1) from history where deleted_at is null)",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(res)
+    }
+
+    async fn list_hosts(&self, user: &User) -> Result<Vec<String>> {
+        let res: Vec<(String,)> = sqlx::query_as(
+            "select distinct hostname from history
+            where user_id = $1
+            order by hostname",
+        )
+        .bind(user.id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(res.into_iter().map(|r| r.0).collect())
+    }
+
     async fn list_history(
         &self,
         user: &User,
@@ -117,6 +324,7 @@ impl Database for Postgres {
             "select * from history 
             where user_id = $1
             and hostname != $2
+            and deleted_at is null
             and created_at >= $3
             and timestamp >= $4
             order by timestamp asc
@@ -133,8 +341,35 @@ impl Database for Postgres {
         Ok(res)
     }
 
+    // the id is used as the sync cursor, as it only ever increases
+    async fn list_history_after(
+        &self,
+        user: &User,
+        cursor: i64,
+        host: String,
+    ) -> Result<Vec<History>> {
+        let res = sqlx::query_as::<_, History>(
+            "select * from history
+            where user_id = $1
+            and hostname != $2
+            and deleted_at is null
+            and id > $3
+            order by id asc
+            limit $4",
+        )
+        .bind(user.id)
+        .bind(host)
+        .bind(cursor)
+        .bind(HISTORY_PAGE_SIZE)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(res)
+    }
+
     async fn add_history(&self, history: &[NewHistory]) -> Result<()> {
         let mut tx = self.pool.begin().await?;
+        lock_history(&mut tx, history).await?;
 
         for i in history {
             sqlx::query(
@@ -158,36 +393,494 @@ impl Database for Postgres {
         Ok(())
     }
 
-    async fn add_user(&self, user: NewUser) -> Result<i64> {
+    // like add_history, but existing history has its data replaced. used when
+    // the client re-encrypts everything with a new key
+    async fn replace_history(&self, history: &[NewHistory]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        lock_history(&mut tx, history).await?;
+
+        for i in history {
+            sqlx::query(
+                "insert into history
+                    (client_id, user_id, hostname, timestamp, data)
+                values ($1, $2, $3, $4, $5)
+                on conflict (client_id) do update
+                set data = excluded.data
+                where history.user_id = excluded.user_id
+                and history.deleted_at is null
+                ",
+            )
+            .bind(i.client_id)
+            .bind(i.user_id)
+            .bind(i.hostname)
+            .bind(i.timestamp)
+            .bind(i.data)
+            .execute(&mut tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    // the encrypted data is dropped, but the row is kept around so that other
+    // clients can find out about the deletion
+    async fn delete_history(&self, user: &User, client_ids: &[String]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        lock_user(&mut tx, user.id).await?;
+
+        for i in client_ids {
+            sqlx::query(
+                "update history
+                set data = '', deleted_at = current_timestamp,
+                    deleted_seq = nextval('history_deleted_seq')
+                where user_id = $1
+                and client_id = $2
+                and deleted_at is null",
+            )
+            .bind(user.id)
+            .bind(i)
+            .execute(&mut tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn deleted_history(
+        &self,
+        user: &User,
+        since: chrono::NaiveDateTime,
+    ) -> Result<Vec<String>> {
+        let res: Vec<(String,)> = sqlx::query_as(
+            "select client_id from history
+            where user_id = $1
+            and deleted_at >= $2",
+        )
+        .bind(user.id)
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(res.into_iter().map(|r| r.0).collect())
+    }
+
+    async fn deleted_history_after(&self, user: &User, cursor: i64) -> Result<Vec<(String, i64)>> {
+        let res = sqlx::query_as(
+            "select client_id, deleted_seq from history
+            where user_id = $1
+            and deleted_seq > $2
+            order by deleted_seq asc
+            limit $3",
+        )
+        .bind(user.id)
+        .bind(cursor)
+        .bind(HISTORY_PAGE_SIZE)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(res)
+    }
+
+    async fn add_user(&self, user: NewUser) -> Result<Option<i64>> {
+        let verified_at = match user.verification_token {
+            Some(_) => None,
+            None => Some(chrono::Utc::now().naive_utc()),
+        };
+
+        let mut tx = self.pool.begin().await?;
+
+        // claimed first, so that two registrations can't both use the invite.
+        // the transaction is rolled back if the user can't be added
+        if let Some(invite) = &user.invite {
+            let res = sqlx::query(
+                "update invites
+                set used_at = current_timestamp
+                where token = $1
+                and used_at is null",
+            )
+            .bind(invite.as_str())
+            .execute(&mut tx)
+            .await?;
+
+            if res.rows_affected() == 0 {
+                return Ok(None);
+            }
+        }
+
         let res: (i64,) = sqlx::query_as(
             "insert into users
-                (username, email, password)
-            values($1, $2, $3)
+                (username, email, password, verification_token, verified_at)
+            values($1, $2, $3, $4, $5)
             returning id",
         )
         .bind(user.username.as_str())
         .bind(user.email.as_str())
         .bind(user.password)
+        .bind(user.verification_token)
+        .bind(verified_at)
+        .fetch_one(&mut tx)
+        .await?;
+
+        if let Some(invite) = &user.invite {
+            sqlx::query("update invites set user_id = $1 where token = $2")
+                .bind(res.0)
+                .bind(invite.as_str())
+                .execute(&mut tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(Some(res.0))
+    }
+
+    async fn verify_user(&self, token: &str) -> Result<bool> {
+        let res = sqlx::query(
+            "update users
+            set verified_at = current_timestamp, verification_token = null
+            where verification_token = $1",
+        )
+        .bind(token)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn set_user_verified(&self, user: &User) -> Result<()> {
+        sqlx::query(
+            "update users
+            set verified_at = current_timestamp, verification_token = null
+            where id = $1",
+        )
+        .bind(user.id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn set_user_totp(&self, user: &User, secret: Option<&str>, enabled: bool) -> Result<()> {
+        sqlx::query("update users set totp_secret = $1, totp_enabled = $2 where id = $3")
+            .bind(secret)
+            .bind(enabled)
+            .bind(user.id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn list_users(&self) -> Result<Vec<User>> {
+        let res = sqlx::query_as::<_, User>("select * from users order by id asc")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(res)
+    }
+
+    // remove the user, and everything that belongs to them
+    async fn delete_user(&self, user: &User) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("delete from history where user_id = $1")
+            .bind(user.id)
+            .execute(&mut tx)
+            .await?;
+
+        sqlx::query("delete from sessions where user_id = $1")
+            .bind(user.id)
+            .execute(&mut tx)
+            .await?;
+
+        sqlx::query("delete from api_tokens where user_id = $1")
+            .bind(user.id)
+            .execute(&mut tx)
+            .await?;
+
+        sqlx::query("delete from devices where user_id = $1")
+            .bind(user.id)
+            .execute(&mut tx)
+            .await?;
+
+        sqlx::query("delete from records where user_id = $1")
+            .bind(user.id)
+            .execute(&mut tx)
+            .await?;
+
+        sqlx::query("delete from users where id = $1")
+            .bind(user.id)
+            .execute(&mut tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    // expects the password to already be hashed
+    async fn update_user_password(&self, user: &User, password: &str) -> Result<()> {
+        sqlx::query("update users set password = $1 where id = $2")
+            .bind(password)
+            .bind(user.id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_user_sessions(&self, user: &User) -> Result<()> {
+        sqlx::query("delete from sessions where user_id = $1")
+            .bind(user.id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    // log out everywhere, apart from the session given
+    async fn delete_other_sessions(&self, user: &User, token: &str) -> Result<()> {
+        sqlx::query("delete from sessions where user_id = $1 and token != $2")
+            .bind(user.id)
+            .bind(token)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn add_api_token(
+        &self,
+        user: &User,
+        name: &str,
+        token: &str,
+        device_id: Option<i64>,
+    ) -> Result<()> {
+        sqlx::query(
+            "insert into api_tokens (user_id, name, token, device_id) values($1, $2, $3, $4)",
+        )
+        .bind(user.id)
+        .bind(name)
+        .bind(token)
+        .bind(device_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn list_api_tokens(&self, user: &User) -> Result<Vec<ApiToken>> {
+        let res = sqlx::query_as::<_, ApiToken>(
+            "select id, user_id, name, created_at, last_used_at from api_tokens
+            where user_id = $1
+            order by name asc",
+        )
+        .bind(user.id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(res)
+    }
+
+    async fn delete_api_token(&self, user: &User, name: &str) -> Result<bool> {
+        let res = sqlx::query("delete from api_tokens where user_id = $1 and name = $2")
+            .bind(user.id)
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn add_device(&self, user_id: i64, host: &str, name: &str) -> Result<Device> {
+        sqlx::query(
+            "insert into devices (user_id, host, name) values($1, $2, $3)
+            on conflict(user_id, host) do update
+            set name = excluded.name, last_login_at = current_timestamp
+            where devices.revoked_at is null",
+        )
+        .bind(user_id)
+        .bind(host)
+        .bind(name)
+        .execute(&self.pool)
+        .await?;
+
+        let res = sqlx::query_as::<_, Device>(
+            "select * from devices
+            where user_id = $1
+            and host = $2",
+        )
+        .bind(user_id)
+        .bind(host)
         .fetch_one(&self.pool)
         .await?;
 
-        Ok(res.0)
+        Ok(res)
+    }
+
+    async fn has_devices(&self, user_id: i64) -> Result<bool> {
+        let res: (i64,) = sqlx::query_as("select count(1) from devices where user_id = $1")
+            .bind(user_id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(res.0 > 0)
+    }
+
+    async fn list_devices(&self, user: &User) -> Result<Vec<Device>> {
+        let res = sqlx::query_as::<_, Device>(
+            "select * from devices
+            where user_id = $1
+            order by last_login_at desc",
+        )
+        .bind(user.id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(res)
+    }
+
+    async fn revoke_device(&self, user: &User, host: &str) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let device = sqlx::query_as::<_, Device>(
+            "select * from devices
+            where user_id = $1
+            and host = $2",
+        )
+        .bind(user.id)
+        .bind(host)
+        .fetch_optional(&mut tx)
+        .await?;
+
+        let device = match device {
+            Some(d) => d,
+            None => return Ok(false),
+        };
+
+        sqlx::query(
+            "update devices
+            set revoked_at = coalesce(revoked_at, current_timestamp)
+            where id = $1",
+        )
+        .bind(device.id)
+        .execute(&mut tx)
+        .await?;
+
+        sqlx::query("delete from sessions where device_id = $1")
+            .bind(device.id)
+            .execute(&mut tx)
+            .await?;
+
+        sqlx::query("delete from api_tokens where device_id = $1")
+            .bind(device.id)
+            .execute(&mut tx)
+            .await?;
+
+        // sessions and tokens from before devices were tracked could belong to
+        // any device, this one included
+        sqlx::query("delete from sessions where user_id = $1 and device_id is null")
+            .bind(user.id)
+            .execute(&mut tx)
+            .await?;
+
+        sqlx::query("delete from api_tokens where user_id = $1 and device_id is null")
+            .bind(user.id)
+            .execute(&mut tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn add_records(&self, records: &[NewRecord]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        for i in records {
+            sqlx::query(
+                "insert into records (user_id, key, data, updated_at)
+                values ($1, $2, $3, $4)
+                on conflict (user_id, key) do update
+                set data = excluded.data, updated_at = excluded.updated_at
+                where records.updated_at <= excluded.updated_at",
+            )
+            .bind(i.user_id)
+            .bind(i.key)
+            .bind(i.data)
+            .bind(i.updated_at)
+            .execute(&mut tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn list_records(&self, user: &User) -> Result<Vec<Record>> {
+        let res = sqlx::query_as::<_, Record>(
+            "select * from records
+            where user_id = $1
+            order by updated_at asc",
+        )
+        .bind(user.id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(res)
+    }
+
+    async fn add_invite(&self, token: &str) -> Result<()> {
+        sqlx::query("insert into invites (token) values($1)")
+            .bind(token)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn valid_invite(&self, token: &str) -> Result<bool> {
+        let res: (i64,) = sqlx::query_as(
+            "select count(1) from invites
+            where token = $1
+            and used_at is null",
+        )
+        .bind(token)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(res.0 > 0)
     }
 
     async fn add_session(&self, session: &NewSession) -> Result<()> {
         sqlx::query(
             "insert into sessions
-                (user_id, token)
-            values($1, $2)",
+                (user_id, token, device_id)
+            values($1, $2, $3)",
         )
         .bind(session.user_id)
         .bind(session.token)
+        .bind(session.device_id)
         .execute(&self.pool)
         .await?;
 
         Ok(())
     }
 
+    async fn delete_session(&self, token: &str) -> Result<()> {
+        sqlx::query("delete from sessions where token = $1")
+            .bind(token)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
     async fn get_user_session(&self, u: &User) -> Result<Session> {
         let res: Option<Session> =
             sqlx::query_as::<_, Session>("select * from sessions where user_id = $1")
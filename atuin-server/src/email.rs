@@ -0,0 +1,42 @@
+use eyre::Result;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+use crate::settings::Settings;
+
+// Send the link that verifies a new user's email address
+pub async fn send_verification(
+    settings: &Settings,
+    username: &str,
+    email: &str,
+    token: &str,
+) -> Result<()> {
+    let link = format!(
+        "{}/verify/{}",
+        settings.public_url.trim_end_matches('/'),
+        token
+    );
+
+    let message = Message::builder()
+        .from(settings.smtp.from.parse()?)
+        .to(email.parse()?)
+        .subject("Verify your email address for atuin")
+        .body(format!(
+            "Hi {},\n\nOpen this link to verify your email address, and start syncing your history\n\n{}\n",
+            username, link
+        ))?;
+
+    let mut mailer = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.smtp.host)?
+        .port(settings.smtp.port);
+
+    if !settings.smtp.username.is_empty() {
+        mailer = mailer.credentials(Credentials::new(
+            settings.smtp.username.clone(),
+            settings.smtp.password.clone(),
+        ));
+    }
+
+    mailer.build().send(message).await?;
+
+    Ok(())
+}
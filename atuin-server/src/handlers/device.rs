@@ -0,0 +1,74 @@
+use std::convert::Infallible;
+
+use chrono::{DateTime, Utc};
+use warp::{http::StatusCode, reply::json};
+
+use crate::database::Database;
+use crate::models::User;
+use atuin_common::api::{DeviceResponse, ErrorResponse, ListDevicesResponse};
+
+const MAX_NAME_LENGTH: usize = 64;
+
+// The name the device gave, trimmed and cut down to size. An empty name is
+// fine, the client shows the host instead
+pub fn device_name(name: Option<&str>) -> String {
+    name.unwrap_or_default()
+        .trim()
+        .chars()
+        .take(MAX_NAME_LENGTH)
+        .collect()
+}
+
+pub async fn list(
+    user: User,
+    db: impl Database + Clone + Send + Sync,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    let devices = match db.list_devices(&user).await {
+        Ok(d) => d,
+        Err(e) => {
+            error!("failed to list devices for {}: {}", user.id, e);
+
+            return Ok(Box::new(ErrorResponse::reply(
+                "failed to list devices",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )));
+        }
+    };
+
+    let devices = devices
+        .into_iter()
+        .map(|d| DeviceResponse {
+            host: d.host,
+            name: d.name,
+            created_at: DateTime::from_utc(d.created_at, Utc),
+            last_login_at: DateTime::from_utc(d.last_login_at, Utc),
+            revoked_at: d.revoked_at.map(|t| DateTime::from_utc(t, Utc)),
+        })
+        .collect();
+
+    Ok(Box::new(json(&ListDevicesResponse { devices })))
+}
+
+// Log the device out, and stop it from logging back in or uploading any more
+// history. There's no undoing this, short of a new hostname
+pub async fn revoke(
+    host: String,
+    user: User,
+    db: impl Database + Clone + Send + Sync,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    match db.revoke_device(&user, host.as_str()).await {
+        Ok(true) => Ok(Box::new(warp::reply())),
+        Ok(false) => Ok(Box::new(ErrorResponse::reply(
+            "device not found",
+            StatusCode::NOT_FOUND,
+        ))),
+        Err(e) => {
+            error!("failed to revoke device for {}: {}", user.id, e);
+
+            Ok(Box::new(ErrorResponse::reply(
+                "failed to revoke device",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )))
+        }
+    }
+}
@@ -2,10 +2,15 @@ use std::convert::Infallible;
 
 use warp::{http::StatusCode, reply::json};
 
+use super::is_rejected;
 use crate::database::Database;
 use crate::models::{NewHistory, User};
+use crate::settings::Settings;
 use atuin_common::api::{
-    AddHistoryRequest, CountResponse, ErrorResponse, SyncHistoryRequest, SyncHistoryResponse,
+    AddHistoryRequest, AddHistoryResponse, CountResponse, DeleteHistoryRequest, ErrorResponse,
+    StatusResponse, SyncCursorRequest, SyncCursorResponse, SyncDeletedCursorRequest,
+    SyncDeletedCursorResponse, SyncDeletedRequest, SyncDeletedResponse, SyncHistoryRequest,
+    SyncHistoryResponse,
 };
 
 pub async fn count(
@@ -21,6 +26,30 @@ pub async fn count(
     )
 }
 
+pub async fn status(
+    user: User,
+    db: impl Database + Clone + Send + Sync,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    let count = db.count_history(&user).await;
+    let hosts = db.list_hosts(&user).await;
+
+    match (count, hosts) {
+        (Ok(count), Ok(hosts)) => Ok(Box::new(json(&StatusResponse {
+            username: user.username,
+            count,
+            hosts,
+        }))),
+        (Err(e), _) | (_, Err(e)) => {
+            error!("failed to load status: {}", e);
+
+            Ok(Box::new(ErrorResponse::reply(
+                "failed to load status",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )))
+        }
+    }
+}
+
 pub async fn list(
     req: SyncHistoryRequest,
     user: User,
@@ -58,14 +87,70 @@ pub async fn list(
     Ok(Box::new(json(&SyncHistoryResponse { history })))
 }
 
+pub async fn list_after(
+    req: SyncCursorRequest,
+    user: User,
+    db: impl Database + Clone + Send + Sync,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    let history = match db.list_history_after(&user, req.cursor, req.host).await {
+        Ok(history) => history,
+        Err(e) => {
+            error!("failed to load history: {}", e);
+
+            return Ok(Box::new(ErrorResponse::reply(
+                "failed to load history",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )));
+        }
+    };
+
+    // if there is nothing new, the client stays where it is
+    let cursor = history.last().map_or(req.cursor, |h| h.id);
+    let history: Vec<String> = history.into_iter().map(|h| h.data).collect();
+
+    debug!(
+        "loaded {} items of history for user {} after {}",
+        history.len(),
+        user.id,
+        req.cursor
+    );
+
+    Ok(Box::new(json(&SyncCursorResponse { history, cursor })))
+}
+
+// Anything turned away is left out, and the rest stored, so that one long
+// command can't stop everything after it from syncing. Returns what to store,
+// and the ids of what won't be
+fn split_rejected(
+    req: &[AddHistoryRequest],
+    max_history_length: usize,
+) -> (Vec<&AddHistoryRequest>, Vec<String>) {
+    let (accepted, rejected): (Vec<&AddHistoryRequest>, Vec<&AddHistoryRequest>) = req
+        .iter()
+        .partition(|h| !is_rejected(h.data.as_str(), max_history_length));
+
+    for h in &rejected {
+        debug!(
+            "rejected history {}, {} bytes, over the limit or not encrypted",
+            h.id,
+            h.data.len()
+        );
+    }
+
+    (accepted, rejected.iter().map(|h| h.id.clone()).collect())
+}
+
 pub async fn add(
     req: Vec<AddHistoryRequest>,
     user: User,
+    settings: Settings,
     db: impl Database + Clone + Send + Sync,
 ) -> Result<Box<dyn warp::Reply>, Infallible> {
     debug!("request to add {} history items", req.len());
 
-    let history: Vec<NewHistory> = req
+    let (accepted, rejected) = split_rejected(&req, settings.max_history_length);
+
+    let history: Vec<NewHistory> = accepted
         .iter()
         .map(|h| NewHistory {
             client_id: h.id.as_str(),
@@ -85,5 +170,119 @@ pub async fn add(
         )));
     };
 
+    Ok(Box::new(json(&AddHistoryResponse { rejected })))
+}
+
+pub async fn replace(
+    req: Vec<AddHistoryRequest>,
+    user: User,
+    settings: Settings,
+    db: impl Database + Clone + Send + Sync,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    debug!("request to replace {} history items", req.len());
+
+    let (accepted, rejected) = split_rejected(&req, settings.max_history_length);
+
+    let history: Vec<NewHistory> = accepted
+        .iter()
+        .map(|h| NewHistory {
+            client_id: h.id.as_str(),
+            user_id: user.id,
+            hostname: h.hostname.as_str(),
+            timestamp: h.timestamp.naive_utc(),
+            data: h.data.as_str(),
+        })
+        .collect();
+
+    if let Err(e) = db.replace_history(&history).await {
+        error!("failed to replace history: {}", e);
+
+        return Ok(Box::new(ErrorResponse::reply(
+            "failed to replace history",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )));
+    };
+
+    Ok(Box::new(json(&AddHistoryResponse { rejected })))
+}
+
+pub async fn delete(
+    req: Vec<DeleteHistoryRequest>,
+    user: User,
+    db: impl Database + Clone + Send + Sync,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    debug!("request to delete {} history items", req.len());
+
+    let client_ids: Vec<String> = req.into_iter().map(|h| h.client_id).collect();
+
+    if let Err(e) = db.delete_history(&user, &client_ids).await {
+        error!("failed to delete history: {}", e);
+
+        return Ok(Box::new(ErrorResponse::reply(
+            "failed to delete history",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )));
+    };
+
     Ok(Box::new(warp::reply()))
 }
+
+pub async fn deleted(
+    req: SyncDeletedRequest,
+    user: User,
+    db: impl Database + Clone + Send + Sync,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    let deleted = match db.deleted_history(&user, req.since.naive_utc()).await {
+        Ok(deleted) => deleted,
+        Err(e) => {
+            error!("failed to load deleted history: {}", e);
+
+            return Ok(Box::new(ErrorResponse::reply(
+                "failed to load deleted history",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )));
+        }
+    };
+
+    debug!(
+        "loaded {} deleted history items for user {}",
+        deleted.len(),
+        user.id
+    );
+
+    Ok(Box::new(json(&SyncDeletedResponse { deleted })))
+}
+
+pub async fn deleted_after(
+    req: SyncDeletedCursorRequest,
+    user: User,
+    db: impl Database + Clone + Send + Sync,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    let deleted = match db.deleted_history_after(&user, req.cursor).await {
+        Ok(deleted) => deleted,
+        Err(e) => {
+            error!("failed to load deleted history: {}", e);
+
+            return Ok(Box::new(ErrorResponse::reply(
+                "failed to load deleted history",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )));
+        }
+    };
+
+    // if there is nothing new, the client stays where it is
+    let cursor = deleted.last().map_or(req.cursor, |d| d.1);
+    let deleted: Vec<String> = deleted.into_iter().map(|d| d.0).collect();
+
+    debug!(
+        "loaded {} deleted history items for user {} after {}",
+        deleted.len(),
+        user.id,
+        req.cursor
+    );
+
+    Ok(Box::new(json(&SyncDeletedCursorResponse {
+        deleted,
+        cursor,
+    })))
+}
@@ -0,0 +1,34 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use warp::http::StatusCode;
+
+use crate::database::Database;
+use crate::metrics::Metrics;
+use atuin_common::api::ErrorResponse;
+
+pub async fn get(
+    metrics: Arc<Metrics>,
+    db: impl Database + Clone + Send + Sync,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    let body = match db.totals().await {
+        Ok((users, history)) => metrics.render(users, history),
+        Err(e) => Err(e),
+    };
+
+    match body {
+        Ok(body) => Ok(Box::new(warp::reply::with_header(
+            body,
+            "content-type",
+            "text/plain; version=0.0.4",
+        ))),
+        Err(e) => {
+            error!("failed to render metrics: {}", e);
+
+            Ok(Box::new(ErrorResponse::reply(
+                "failed to render metrics",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )))
+        }
+    }
+}
@@ -1,6 +1,59 @@
+use std::convert::Infallible;
+
+use warp::http::StatusCode;
+use warp::reply::json;
+
+use atuin_common::api::{ErrorResponse, HealthResponse, IndexResponse};
+use atuin_common::utils::looks_encrypted;
+
+use crate::database::Database;
+
+pub mod device;
 pub mod history;
+pub mod metrics;
+pub mod record;
+pub mod token;
 pub mod user;
 
-pub const fn index() -> &'static str {
-    "\"Through the fathomless deeps of space swims the star turtle Great A\u{2019}Tuin, bearing on its back the four giant elephants who carry on their shoulders the mass of the Discworld.\"\n\t-- Sir Terry Pratchett"
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// The oldest client that can sync with this server. Raise it when a change to
+// the api breaks older clients.
+pub const MIN_CLIENT_VERSION: &str = "0.6.0";
+
+const HOMAGE: &str = "\"Through the fathomless deeps of space swims the star turtle Great A\u{2019}Tuin, bearing on its back the four giant elephants who carry on their shoulders the mass of the Discworld.\"\n\t-- Sir Terry Pratchett";
+
+// Whether synced data, history or a record, is turned away. Anything over the
+// limit is, and so is anything that plainly isn't encrypted, from a broken or
+// misconfigured client. 0 is no limit
+fn is_rejected(data: &str, max_length: usize) -> bool {
+    (max_length > 0 && data.len() > max_length) || !looks_encrypted(data)
+}
+
+pub fn index() -> impl warp::Reply {
+    json(&IndexResponse {
+        homage: HOMAGE.to_string(),
+        version: VERSION.to_string(),
+        min_client_version: MIN_CLIENT_VERSION.to_string(),
+    })
+}
+
+// For load balancers and container healthchecks. Only healthy if the
+// database can be reached
+pub async fn health(
+    db: impl Database + Clone + Send + Sync,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    match db.ping().await {
+        Ok(_) => Ok(Box::new(json(&HealthResponse {
+            status: String::from("ok"),
+        }))),
+        Err(e) => {
+            error!("healthcheck failed: {}", e);
+
+            Ok(Box::new(ErrorResponse::reply(
+                "database unavailable",
+                StatusCode::SERVICE_UNAVAILABLE,
+            )))
+        }
+    }
 }
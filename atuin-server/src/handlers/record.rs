@@ -0,0 +1,89 @@
+use std::convert::Infallible;
+
+use chrono::{DateTime, Utc};
+use warp::{http::StatusCode, reply::json};
+
+use super::is_rejected;
+use crate::database::Database;
+use crate::models::{NewRecord, User};
+use crate::settings::Settings;
+use atuin_common::api::{
+    AddRecordsResponse, ErrorResponse, ListRecordsResponse, RecordRequest, RecordResponse,
+};
+
+// Every record the user has, including those deleted. The client works out
+// which are newer than its own copy
+pub async fn list(
+    user: User,
+    db: impl Database + Clone + Send + Sync,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    let records = match db.list_records(&user).await {
+        Ok(r) => r,
+        Err(e) => {
+            error!("failed to list records for {}: {}", user.id, e);
+
+            return Ok(Box::new(ErrorResponse::reply(
+                "failed to list records",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )));
+        }
+    };
+
+    let records = records
+        .into_iter()
+        .map(|r| RecordResponse {
+            key: r.key,
+            data: r.data,
+            updated_at: DateTime::from_utc(r.updated_at, Utc),
+        })
+        .collect();
+
+    Ok(Box::new(json(&ListRecordsResponse { records })))
+}
+
+pub async fn add(
+    req: Vec<RecordRequest>,
+    user: User,
+    settings: Settings,
+    db: impl Database + Clone + Send + Sync,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    debug!("request to add {} records", req.len());
+
+    // records are held to the same limit as history. anything turned away is
+    // left out, and the rest stored, so that one bad record can't stop the
+    // others from syncing
+    let (accepted, rejected): (Vec<&RecordRequest>, Vec<&RecordRequest>) = req
+        .iter()
+        .partition(|r| !is_rejected(r.data.as_str(), settings.max_history_length));
+
+    for r in &rejected {
+        debug!(
+            "rejected record {}, {} bytes, over the limit or not encrypted",
+            r.key,
+            r.data.len()
+        );
+    }
+
+    let records: Vec<NewRecord> = accepted
+        .iter()
+        .map(|r| NewRecord {
+            user_id: user.id,
+            key: r.key.as_str(),
+            data: r.data.as_str(),
+            updated_at: r.updated_at.naive_utc(),
+        })
+        .collect();
+
+    if let Err(e) = db.add_records(&records).await {
+        error!("failed to add records: {}", e);
+
+        return Ok(Box::new(ErrorResponse::reply(
+            "failed to add records",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )));
+    };
+
+    let rejected = rejected.iter().map(|r| r.key.clone()).collect();
+
+    Ok(Box::new(json(&AddRecordsResponse { rejected })))
+}
@@ -0,0 +1,140 @@
+use std::convert::Infallible;
+
+use chrono::{DateTime, Utc};
+use warp::{http::StatusCode, reply::json};
+
+use crate::database::Database;
+use crate::models::User;
+use atuin_common::api::{
+    CreateTokenRequest, CreateTokenResponse, ErrorResponse, ListTokensResponse, TokenResponse,
+};
+use atuin_common::utils::{hash_str, uuid_v4};
+
+const MAX_NAME_LENGTH: usize = 64;
+
+pub async fn list(
+    user: User,
+    db: impl Database + Clone + Send + Sync,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    let tokens = match db.list_api_tokens(&user).await {
+        Ok(t) => t,
+        Err(e) => {
+            error!("failed to list api tokens for {}: {}", user.id, e);
+
+            return Ok(Box::new(ErrorResponse::reply(
+                "failed to list tokens",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )));
+        }
+    };
+
+    let tokens = tokens
+        .into_iter()
+        .map(|t| TokenResponse {
+            name: t.name,
+            created_at: DateTime::from_utc(t.created_at, Utc),
+            last_used_at: t.last_used_at.map(|t| DateTime::from_utc(t, Utc)),
+        })
+        .collect();
+
+    Ok(Box::new(json(&ListTokensResponse { tokens })))
+}
+
+// The token is only ever returned here. The server keeps a hash of it, like a
+// password, so a lost token has to be revoked and created again
+pub async fn create(
+    req: CreateTokenRequest,
+    session: String,
+    user: User,
+    db: impl Database + Clone + Send + Sync,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    let name = req.name.trim();
+
+    if name.is_empty() || name.len() > MAX_NAME_LENGTH {
+        return Ok(Box::new(ErrorResponse::reply(
+            "token names must be between 1 and 64 characters",
+            StatusCode::BAD_REQUEST,
+        )));
+    }
+
+    match db.list_api_tokens(&user).await {
+        Ok(tokens) if tokens.iter().any(|t| t.name == name) => {
+            return Ok(Box::new(ErrorResponse::reply(
+                "a token with this name already exists",
+                StatusCode::CONFLICT,
+            )));
+        }
+        Ok(_) => {}
+        Err(e) => {
+            error!("failed to list api tokens for {}: {}", user.id, e);
+
+            return Ok(Box::new(ErrorResponse::reply(
+                "failed to create token",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )));
+        }
+    }
+
+    // the token belongs to the device it was created from, and is revoked
+    // along with it
+    let device_id = match db.get_session(session.as_str()).await {
+        Ok(s) => s.device_id,
+        Err(e) => {
+            error!("failed to get session for {}: {}", user.id, e);
+
+            return Ok(Box::new(ErrorResponse::reply(
+                "failed to create token",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )));
+        }
+    };
+
+    let token = uuid_v4();
+
+    if let Err(e) = db
+        .add_api_token(&user, name, hash_str(token.as_str()).as_str(), device_id)
+        .await
+    {
+        error!("failed to add api token for {}: {}", user.id, e);
+
+        return Ok(Box::new(ErrorResponse::reply(
+            "failed to create token",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )));
+    }
+
+    Ok(Box::new(json(&CreateTokenResponse { token })))
+}
+
+pub async fn delete(
+    name: String,
+    user: User,
+    db: impl Database + Clone + Send + Sync,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    // the client encodes the name, as it can have any character in it
+    let name = match urlencoding::decode(name.as_str()) {
+        Ok(name) => name,
+        Err(_) => {
+            return Ok(Box::new(ErrorResponse::reply(
+                "invalid token name",
+                StatusCode::BAD_REQUEST,
+            )))
+        }
+    };
+
+    match db.delete_api_token(&user, name.as_str()).await {
+        Ok(true) => Ok(Box::new(warp::reply())),
+        Ok(false) => Ok(Box::new(ErrorResponse::reply(
+            "token not found",
+            StatusCode::NOT_FOUND,
+        ))),
+        Err(e) => {
+            error!("failed to delete api token for {}: {}", user.id, e);
+
+            Ok(Box::new(ErrorResponse::reply(
+                "failed to revoke token",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )))
+        }
+    }
+}
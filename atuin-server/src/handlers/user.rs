@@ -1,30 +1,73 @@
 use std::convert::Infallible;
+use std::net::IpAddr;
+use std::sync::Arc;
 
-use sodiumoxide::crypto::pwhash::argon2id13;
 use uuid::Uuid;
 use warp::http::StatusCode;
 use warp::reply::json;
 
 use atuin_common::api::{
-    ErrorResponse, LoginRequest, LoginResponse, RegisterRequest, RegisterResponse, UserResponse,
+    ChangePasswordRequest, ErrorResponse, LoginRequest, LoginResponse, RegisterRequest,
+    RegisterResponse, TotpCodeRequest, TotpEnableResponse, UserResponse,
 };
-use atuin_common::utils::hash_secret;
 
 use crate::database::Database;
-use crate::models::{NewSession, NewUser};
+use crate::email::send_verification;
+use crate::handlers::device::device_name;
+use crate::models::{NewSession, NewUser, User};
+use crate::password;
+use crate::ratelimit::RateLimits;
 use crate::settings::Settings;
+use crate::totp;
 
-pub fn verify_str(secret: &str, verify: &str) -> bool {
-    sodiumoxide::init().unwrap();
+// Register the device a session is for. A revoked device is turned away.
+// Older clients don't say which device they are, which is only allowed until
+// the user has a device registered, or a revoked device could log back in by
+// leaving its host out
+async fn session_device(
+    db: &(impl Database + Clone + Send + Sync),
+    user_id: i64,
+    host: Option<&str>,
+    name: Option<&str>,
+) -> Result<Option<i64>, Box<dyn warp::Reply>> {
+    let host = match host {
+        Some(h) => h,
+        None => {
+            return match db.has_devices(user_id).await {
+                Ok(false) => Ok(None),
+                Ok(true) => Err(Box::new(ErrorResponse::reply(
+                    "this client is too old to login to this account, please upgrade atuin",
+                    StatusCode::BAD_REQUEST,
+                ))),
+                Err(e) => {
+                    error!("failed to check devices for {}: {}", user_id, e);
 
-    let mut padded = [0_u8; 128];
-    secret.as_bytes().iter().enumerate().for_each(|(i, val)| {
-        padded[i] = *val;
-    });
+                    Err(Box::new(ErrorResponse::reply(
+                        "failed to register device",
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )))
+                }
+            };
+        }
+    };
+
+    match db
+        .add_device(user_id, host, device_name(name).as_str())
+        .await
+    {
+        Ok(d) if d.revoked_at.is_some() => Err(Box::new(ErrorResponse::reply(
+            "this device has been revoked",
+            StatusCode::FORBIDDEN,
+        ))),
+        Ok(d) => Ok(Some(d.id)),
+        Err(e) => {
+            error!("failed to add device for {}: {}", user_id, e);
 
-    match argon2id13::HashedPassword::from_slice(&padded) {
-        Some(hp) => argon2id13::pwhash_verify(&hp, verify.as_bytes()),
-        None => false,
+            Err(Box::new(ErrorResponse::reply(
+                "failed to register device",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )))
+        }
     }
 }
 
@@ -48,28 +91,79 @@ pub async fn get(
     })))
 }
 
+fn too_many_attempts() -> Box<dyn warp::Reply> {
+    Box::new(ErrorResponse::reply(
+        "too many attempts, try again later",
+        StatusCode::TOO_MANY_REQUESTS,
+    ))
+}
+
 pub async fn register(
     register: RegisterRequest,
     settings: Settings,
+    limits: Arc<RateLimits>,
+    ip: Option<IpAddr>,
     db: impl Database + Clone + Send + Sync,
 ) -> Result<Box<dyn warp::Reply>, Infallible> {
+    if !limits.allow(ip, register.username.as_str()) {
+        return Ok(too_many_attempts());
+    }
+
+    // without open registration, an unused invite is required. this turns
+    // away used ones before the password is hashed, but the invite is only
+    // claimed when the user is added
     if !settings.open_registration {
-        return Ok(Box::new(ErrorResponse::reply(
-            "this server is not open for registrations",
-            StatusCode::BAD_REQUEST,
-        )));
+        let invited = match &register.invite {
+            Some(invite) => db.valid_invite(invite.as_str()).await.unwrap_or(false),
+            None => false,
+        };
+
+        if !invited {
+            return Ok(Box::new(ErrorResponse::reply(
+                "this server is not open for registrations",
+                StatusCode::FORBIDDEN,
+            )));
+        }
     }
 
-    let hashed = hash_secret(register.password.as_str());
+    let hashed = match password::hash(&settings.password_hashing, register.password.as_str()).await
+    {
+        Ok(h) => h,
+        Err(e) => {
+            error!("failed to hash password: {}", e);
+            return Ok(Box::new(ErrorResponse::reply(
+                "failed to add user",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )));
+        }
+    };
+
+    let verification_token = if settings.smtp.enable {
+        Some(Uuid::new_v4().to_simple().to_string())
+    } else {
+        None
+    };
 
     let new_user = NewUser {
-        email: register.email,
-        username: register.username,
+        email: register.email.clone(),
+        username: register.username.clone(),
         password: hashed,
+        verification_token: verification_token.clone(),
+        invite: if settings.open_registration {
+            None
+        } else {
+            register.invite.clone()
+        },
     };
 
     let user_id = match db.add_user(new_user).await {
-        Ok(id) => id,
+        Ok(Some(id)) => id,
+        Ok(None) => {
+            return Ok(Box::new(ErrorResponse::reply(
+                "this server is not open for registrations",
+                StatusCode::FORBIDDEN,
+            )));
+        }
         Err(e) => {
             error!("failed to add user: {}", e);
             return Ok(Box::new(ErrorResponse::reply(
@@ -79,11 +173,42 @@ pub async fn register(
         }
     };
 
+    // registering still works if the email can't be sent. the user can be
+    // verified by hand with atuin server users verify
+    if let Some(verification_token) = verification_token {
+        if let Err(e) = send_verification(
+            &settings,
+            register.username.as_str(),
+            register.email.as_str(),
+            verification_token.as_str(),
+        )
+        .await
+        {
+            error!(
+                "failed to send verification email to {}: {}",
+                register.username, e
+            );
+        }
+    }
+
+    let device_id = match session_device(
+        &db,
+        user_id,
+        register.host.as_deref(),
+        register.device.as_deref(),
+    )
+    .await
+    {
+        Ok(id) => id,
+        Err(reply) => return Ok(reply),
+    };
+
     let token = Uuid::new_v4().to_simple().to_string();
 
     let new_session = NewSession {
         user_id,
         token: token.as_str(),
+        device_id,
     };
 
     match db.add_session(&new_session).await {
@@ -100,8 +225,15 @@ pub async fn register(
 
 pub async fn login(
     login: LoginRequest,
+    settings: Settings,
+    limits: Arc<RateLimits>,
+    ip: Option<IpAddr>,
     db: impl Database + Clone + Send + Sync,
 ) -> Result<Box<dyn warp::Reply>, Infallible> {
+    if !limits.allow(ip, login.username.as_str()) {
+        return Ok(too_many_attempts());
+    }
+
     let user = match db.get_user(login.username.clone()).await {
         Ok(u) => u,
         Err(e) => {
@@ -114,28 +246,278 @@ pub async fn login(
         }
     };
 
-    let session = match db.get_user_session(&user).await {
-        Ok(u) => u,
-        Err(e) => {
-            error!("failed to get session for {}: {}", login.username, e);
+    let verified = password::verify(user.password.as_str(), login.password.as_str()).await;
 
+    if !verified {
+        return Ok(Box::new(ErrorResponse::reply(
+            "user not found",
+            StatusCode::NOT_FOUND,
+        )));
+    }
+
+    if user.totp_enabled && !valid_code(&user, login.totp.as_deref()) {
+        let reason = match login.totp {
+            Some(_) => "invalid 2fa code",
+            None => "a 2fa code is required",
+        };
+
+        return Ok(Box::new(ErrorResponse::reply(
+            reason,
+            StatusCode::UNAUTHORIZED,
+        )));
+    }
+
+    // this is the only time we have the password, so take the chance to
+    // bring an old hash up to date. logging in still works if it fails
+    if password::needs_rehash(&settings.password_hashing, user.password.as_str()) {
+        let rehashed = password::hash(&settings.password_hashing, login.password.as_str()).await;
+
+        let res = match rehashed {
+            Ok(h) => db.update_user_password(&user, h.as_str()).await,
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = res {
+            error!("failed to rehash password for {}: {}", user.id, e);
+        }
+    }
+
+    let device_id =
+        match session_device(&db, user.id, login.host.as_deref(), login.device.as_deref()).await {
+            Ok(id) => id,
+            Err(reply) => return Ok(reply),
+        };
+
+    // every login gets its own session, so that they can be revoked
+    // individually
+    let token = Uuid::new_v4().to_simple().to_string();
+
+    let new_session = NewSession {
+        user_id: user.id,
+        token: token.as_str(),
+        device_id,
+    };
+
+    if let Err(e) = db.add_session(&new_session).await {
+        error!("failed to add session for {}: {}", login.username, e);
+
+        return Ok(Box::new(ErrorResponse::reply(
+            "failed to login",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )));
+    }
+
+    Ok(Box::new(warp::reply::json(&LoginResponse {
+        session: token,
+    })))
+}
+
+// remove the user's account, along with all of their history and sessions
+pub async fn delete(
+    user: User,
+    db: impl Database + Clone + Send + Sync,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    if let Err(e) = db.delete_user(&user).await {
+        error!("failed to delete user {}: {}", user.id, e);
+
+        return Ok(Box::new(ErrorResponse::reply(
+            "failed to delete account",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )));
+    }
+
+    Ok(Box::new(warp::reply()))
+}
+
+// change the user's password. every other session is logged out, so that
+// anyone else with access loses it
+pub async fn change_password(
+    req: ChangePasswordRequest,
+    token: String,
+    user: User,
+    settings: Settings,
+    db: impl Database + Clone + Send + Sync,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    if !password::verify(user.password.as_str(), req.current_password.as_str()).await {
+        return Ok(Box::new(ErrorResponse::reply(
+            "current password is incorrect",
+            StatusCode::UNAUTHORIZED,
+        )));
+    }
+
+    let hashed = match password::hash(&settings.password_hashing, req.new_password.as_str()).await {
+        Ok(h) => h,
+        Err(e) => {
+            error!("failed to hash password: {}", e);
             return Ok(Box::new(ErrorResponse::reply(
-                "user not found",
-                StatusCode::NOT_FOUND,
+                "failed to change password",
+                StatusCode::INTERNAL_SERVER_ERROR,
             )));
         }
     };
 
-    let verified = verify_str(user.password.as_str(), login.password.as_str());
+    if let Err(e) = db.update_user_password(&user, hashed.as_str()).await {
+        error!("failed to update password for {}: {}", user.id, e);
 
-    if !verified {
         return Ok(Box::new(ErrorResponse::reply(
-            "user not found",
-            StatusCode::NOT_FOUND,
+            "failed to change password",
+            StatusCode::INTERNAL_SERVER_ERROR,
         )));
     }
 
-    Ok(Box::new(warp::reply::json(&LoginResponse {
-        session: session.token,
+    if let Err(e) = db.delete_other_sessions(&user, token.as_str()).await {
+        error!("failed to remove sessions for {}: {}", user.id, e);
+
+        return Ok(Box::new(ErrorResponse::reply(
+            "password changed, but failed to log out other sessions",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )));
+    }
+
+    Ok(Box::new(warp::reply()))
+}
+
+// revoke the session making the request
+pub async fn logout(
+    token: String,
+    user: User,
+    db: impl Database + Clone + Send + Sync,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    if let Err(e) = db.delete_session(token.as_str()).await {
+        error!("failed to delete session for {}: {}", user.id, e);
+
+        return Ok(Box::new(ErrorResponse::reply(
+            "failed to logout",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )));
+    }
+
+    Ok(Box::new(warp::reply()))
+}
+
+fn valid_code(user: &User, code: Option<&str>) -> bool {
+    match (&user.totp_secret, code) {
+        (Some(secret), Some(code)) => {
+            totp::verify(secret.as_str(), code, chrono::Utc::now().timestamp() as u64)
+        }
+        _ => false,
+    }
+}
+
+// start setting up 2fa. nothing changes for the user until they confirm it
+// with a code from their authenticator app
+pub async fn enable_totp(
+    user: User,
+    db: impl Database + Clone + Send + Sync,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    if user.totp_enabled {
+        return Ok(Box::new(ErrorResponse::reply(
+            "2fa is already enabled",
+            StatusCode::CONFLICT,
+        )));
+    }
+
+    let secret = totp::generate_secret();
+
+    if let Err(e) = db.set_user_totp(&user, Some(secret.as_str()), false).await {
+        error!("failed to store 2fa secret for {}: {}", user.id, e);
+
+        return Ok(Box::new(ErrorResponse::reply(
+            "failed to enable 2fa",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )));
+    }
+
+    Ok(Box::new(json(&TotpEnableResponse {
+        url: totp::url(user.username.as_str(), secret.as_str()),
+        secret,
     })))
 }
+
+pub async fn confirm_totp(
+    req: TotpCodeRequest,
+    user: User,
+    db: impl Database + Clone + Send + Sync,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    if user.totp_enabled {
+        return Ok(Box::new(ErrorResponse::reply(
+            "2fa is already enabled",
+            StatusCode::CONFLICT,
+        )));
+    }
+
+    if !valid_code(&user, Some(req.code.as_str())) {
+        return Ok(Box::new(ErrorResponse::reply(
+            "invalid 2fa code",
+            StatusCode::UNAUTHORIZED,
+        )));
+    }
+
+    if let Err(e) = db
+        .set_user_totp(&user, user.totp_secret.as_deref(), true)
+        .await
+    {
+        error!("failed to enable 2fa for {}: {}", user.id, e);
+
+        return Ok(Box::new(ErrorResponse::reply(
+            "failed to enable 2fa",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )));
+    }
+
+    Ok(Box::new(warp::reply()))
+}
+
+pub async fn disable_totp(
+    req: TotpCodeRequest,
+    user: User,
+    db: impl Database + Clone + Send + Sync,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    if !user.totp_enabled {
+        return Ok(Box::new(ErrorResponse::reply(
+            "2fa is not enabled",
+            StatusCode::CONFLICT,
+        )));
+    }
+
+    if !valid_code(&user, Some(req.code.as_str())) {
+        return Ok(Box::new(ErrorResponse::reply(
+            "invalid 2fa code",
+            StatusCode::UNAUTHORIZED,
+        )));
+    }
+
+    if let Err(e) = db.set_user_totp(&user, None, false).await {
+        error!("failed to disable 2fa for {}: {}", user.id, e);
+
+        return Ok(Box::new(ErrorResponse::reply(
+            "failed to disable 2fa",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )));
+    }
+
+    Ok(Box::new(warp::reply()))
+}
+
+pub async fn verify(
+    token: String,
+    db: impl Database + Clone + Send + Sync,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    match db.verify_user(token.as_str()).await {
+        Ok(true) => Ok(Box::new(
+            "Your email address is verified, you can now sync your history",
+        )),
+        Ok(false) => Ok(Box::new(ErrorResponse::reply(
+            "invalid or already used verification link",
+            StatusCode::NOT_FOUND,
+        ))),
+        Err(e) => {
+            error!("failed to verify user: {}", e);
+
+            Ok(Box::new(ErrorResponse::reply(
+                "failed to verify user",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )))
+        }
+    }
+}
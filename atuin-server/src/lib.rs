@@ -1,30 +1,198 @@
 use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
 
-use eyre::Result;
+use eyre::{eyre, Result};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio_stream::wrappers::{TcpListenerStream, UnixListenerStream};
+use tracing_subscriber::EnvFilter;
 
-use crate::settings::Settings;
+use crate::database::{Database, Postgres, Sqlite};
+use crate::listen::{Listen, Listener};
+use crate::metrics::Metrics;
+use crate::settings::{LogFormat, Settings};
 
 #[macro_use]
-extern crate log;
+extern crate tracing;
 
 #[macro_use]
 extern crate serde_derive;
 
 pub mod auth;
 pub mod database;
+pub mod email;
 pub mod handlers;
+pub mod listen;
+pub mod metrics;
 pub mod models;
+pub mod password;
+pub mod ratelimit;
 pub mod router;
 pub mod settings;
+pub mod totp;
+
+// Resolves on SIGINT or SIGTERM. The server then stops accepting connections,
+// and finishes the requests it already has, so that a restart doesn't cut off
+// an upload halfway through
+async fn shutdown_signal() {
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(s) => s,
+        Err(e) => {
+            error!("failed to listen for SIGTERM: {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        }
+    };
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {},
+        _ = terminate.recv() => {},
+    }
+
+    info!("shutting down, waiting for requests in progress to finish");
+}
+
+async fn serve<D: Database + Clone + Send + Sync + 'static>(
+    settings: &Settings,
+    db: D,
+    host: IpAddr,
+    port: u16,
+) -> Result<()> {
+    let metrics = if settings.metrics {
+        Some(Arc::new(Metrics::new()?))
+    } else {
+        None
+    };
+
+    // the router takes its own copy of the pool, this one is for closing it
+    let pool = db.clone();
 
-pub async fn launch(settings: &Settings, host: String, port: u16) -> Result<()> {
     // routes to run:
     // index, register, add_history, login, get_user, sync_count, sync_list
-    let host = host.parse::<IpAddr>()?;
+    let r = router::router(settings, db, metrics);
+
+    let listen = listen::parse(settings.listen.as_str())?;
+
+    if settings.tls.enable && listen != Listen::Tcp {
+        return Err(eyre!(
+            "tls is only supported when listening on host and port"
+        ));
+    }
+
+    match listen {
+        Listen::Tcp if settings.tls.enable => {
+            // warp panics if it can't read them, so check first for a clearer
+            // error
+            for path in &[&settings.tls.cert_path, &settings.tls.key_path] {
+                if !Path::new(path).is_file() {
+                    return Err(eyre!("tls is enabled, but {:?} does not exist", path));
+                }
+            }
+
+            let (_, server) = warp::serve(r)
+                .tls()
+                .cert_path(&settings.tls.cert_path)
+                .key_path(&settings.tls.key_path)
+                .bind_with_graceful_shutdown((host, port), shutdown_signal());
+
+            server.await;
+        }
+        Listen::Tcp => {
+            let (_, server) =
+                warp::serve(r).try_bind_with_graceful_shutdown((host, port), shutdown_signal())?;
+
+            server.await;
+        }
+        Listen::Unix(path) => {
+            let incoming = UnixListenerStream::new(listen::unix(&path)?);
+
+            warp::serve(r)
+                .serve_incoming_with_graceful_shutdown(incoming, shutdown_signal())
+                .await;
+        }
+        Listen::Systemd => match listen::systemd()? {
+            Listener::Tcp(l) => {
+                warp::serve(r)
+                    .serve_incoming_with_graceful_shutdown(
+                        TcpListenerStream::new(l),
+                        shutdown_signal(),
+                    )
+                    .await
+            }
+            Listener::Unix(l) => {
+                warp::serve(r)
+                    .serve_incoming_with_graceful_shutdown(
+                        UnixListenerStream::new(l),
+                        shutdown_signal(),
+                    )
+                    .await
+            }
+        },
+    }
+
+    pool.close().await;
+    info!("shut down");
 
-    let r = router::router(settings).await?;
+    Ok(())
+}
+
+// Log each request as it finishes. RUST_LOG still picks what is logged, but
+// defaults to info so that requests show up
+fn init_tracing(settings: &Settings) -> Result<()> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+
+    // set directly rather than with init, which also tries to take over the
+    // log crate, and the client has already set up a logger for that
+    let res = match settings.log_format {
+        LogFormat::Pretty => tracing::subscriber::set_global_default(builder.pretty().finish()),
+        LogFormat::Json => {
+            tracing::subscriber::set_global_default(builder.json().with_current_span(true).finish())
+        }
+    };
+
+    res.map_err(|e| eyre!("failed to set up logging: {}", e))
+}
+
+// Bring the database up to date, if the settings allow it. Otherwise only warn,
+// as queries against an old schema may still work
+pub async fn auto_migrate(settings: &Settings, db: &(impl Database + Sync)) -> Result<()> {
+    if settings.auto_migrate {
+        return db.migrate().await;
+    }
+
+    let pending = db.pending_migrations().await?;
 
-    warp::serve(r).run((host, port)).await;
+    if !pending.is_empty() {
+        warn!(
+            "{} database migrations are pending, run atuin server migrate",
+            pending.len()
+        );
+    }
 
     Ok(())
 }
+
+pub async fn launch(settings: &Settings, host: String, port: u16) -> Result<()> {
+    let host = host.parse::<IpAddr>()?;
+
+    init_tracing(settings)?;
+
+    password::check(&settings.password_hashing)?;
+
+    if settings.smtp.enable && settings.public_url.is_empty() {
+        return Err(eyre!(
+            "public_url is needed for the links in verification emails"
+        ));
+    }
+
+    if settings.is_sqlite() {
+        let db = Sqlite::new(settings.db_uri.as_str()).await?;
+        auto_migrate(settings, &db).await?;
+        serve(settings, db, host, port).await
+    } else {
+        let db = Postgres::new(settings.db_uri.as_str()).await?;
+        auto_migrate(settings, &db).await?;
+        serve(settings, db, host, port).await
+    }
+}
@@ -0,0 +1,105 @@
+use std::fs;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::io::{FromRawFd, IntoRawFd};
+use std::path::{Path, PathBuf};
+
+use eyre::{eyre, Result};
+use tokio::net::{TcpListener, UnixListener};
+
+// The first socket passed by systemd, see sd_listen_fds(3)
+const SD_LISTEN_FDS_START: i32 = 3;
+
+// Where the server listens, from the listen setting
+#[derive(Debug, PartialEq)]
+pub enum Listen {
+    // host and port
+    Tcp,
+    Unix(PathBuf),
+    Systemd,
+}
+
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+pub fn parse(listen: &str) -> Result<Listen> {
+    match listen {
+        "" => Ok(Listen::Tcp),
+        "systemd" => Ok(Listen::Systemd),
+        _ => match listen.strip_prefix("unix:") {
+            Some(path) if !path.is_empty() => Ok(Listen::Unix(PathBuf::from(path))),
+            _ => Err(eyre!(
+                "invalid listen {:?}, expected unix:<path> or systemd",
+                listen
+            )),
+        },
+    }
+}
+
+// A socket left behind by a server that didn't shut down cleanly would stop
+// us binding, so remove it. Anything else at the path is left alone.
+pub fn unix(path: &Path) -> Result<UnixListener> {
+    if let Ok(meta) = fs::symlink_metadata(path) {
+        if meta.file_type().is_socket() {
+            fs::remove_file(path)?;
+        }
+    }
+
+    UnixListener::bind(path).map_err(|e| eyre!("failed to bind {:?}: {}", path, e))
+}
+
+// The socket systemd opened for us, with socket activation. It could be tcp or
+// unix, depending on the .socket unit
+pub fn systemd() -> Result<Listener> {
+    let ours = std::env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        .map_or(false, |pid| pid == std::process::id());
+
+    let fds = std::env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|fds| fds.parse::<i32>().ok())
+        .unwrap_or(0);
+
+    if !ours || fds < 1 {
+        return Err(eyre!(
+            "listen is set to systemd, but no socket was passed by systemd"
+        ));
+    }
+
+    // safe, as systemd hands the descriptor over to us and nothing else in
+    // the process uses it. getsockname fails for anything but a unix socket,
+    // and then the descriptor is handed on to be treated as tcp
+    let unix = unsafe { std::os::unix::net::UnixListener::from_raw_fd(SD_LISTEN_FDS_START) };
+
+    if unix.local_addr().is_ok() {
+        unix.set_nonblocking(true)?;
+        return Ok(Listener::Unix(UnixListener::from_std(unix)?));
+    }
+
+    let tcp = unsafe { std::net::TcpListener::from_raw_fd(unix.into_raw_fd()) };
+    tcp.set_nonblocking(true)?;
+
+    Ok(Listener::Tcp(TcpListener::from_std(tcp)?))
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::{parse, Listen};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("").unwrap(), Listen::Tcp);
+        assert_eq!(parse("systemd").unwrap(), Listen::Systemd);
+        assert_eq!(
+            parse("unix:/run/atuin.sock").unwrap(),
+            Listen::Unix(PathBuf::from("/run/atuin.sock"))
+        );
+
+        assert!(parse("unix:").is_err());
+        assert!(parse("tcp:8888").is_err());
+    }
+}
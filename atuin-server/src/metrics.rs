@@ -0,0 +1,131 @@
+use std::time::Duration;
+
+use eyre::Result;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+
+// Routes that requests are counted under. Anything else, such as scanners
+// probing for admin pages, is counted as "other" so that the number of
+// series stays fixed.
+const ROUTES: &[&str] = &[
+    "/",
+    "/sync/count",
+    "/sync/status",
+    "/sync/history",
+    "/sync/cursor",
+    "/sync/deleted",
+    "/sync/deleted/cursor",
+    "/history",
+    "/records",
+    "/register",
+    "/login",
+    "/logout",
+    "/account",
+    "/account/password",
+    "/account/2fa",
+    "/account/2fa/confirm",
+    "/account/tokens",
+    "/metrics",
+    "/healthz",
+];
+
+// Prometheus metrics for the server, served from /metrics when enabled
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    latency: HistogramVec,
+    users: IntGauge,
+    history: IntGauge,
+}
+
+// the route a request path is counted under
+pub fn route(path: &str) -> &'static str {
+    if path.starts_with("/user/") {
+        return "/user/:username";
+    }
+
+    if path.starts_with("/account/tokens/") {
+        return "/account/tokens/:name";
+    }
+
+    if path.starts_with("/verify/") {
+        return "/verify/:token";
+    }
+
+    ROUTES
+        .iter()
+        .find(|r| **r == path)
+        .copied()
+        .unwrap_or("other")
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self> {
+        let registry = Registry::new();
+
+        let requests = IntCounterVec::new(
+            Opts::new("atuin_http_requests_total", "HTTP requests handled"),
+            &["method", "route", "status"],
+        )?;
+
+        let latency = HistogramVec::new(
+            HistogramOpts::new(
+                "atuin_http_request_duration_seconds",
+                "Time taken to handle HTTP requests",
+            ),
+            &["method", "route"],
+        )?;
+
+        let users = IntGauge::new("atuin_users", "Registered users")?;
+        let history = IntGauge::new("atuin_history_rows", "Stored history, across all users")?;
+
+        registry.register(Box::new(requests.clone()))?;
+        registry.register(Box::new(latency.clone()))?;
+        registry.register(Box::new(users.clone()))?;
+        registry.register(Box::new(history.clone()))?;
+
+        Ok(Self {
+            registry,
+            requests,
+            latency,
+            users,
+            history,
+        })
+    }
+
+    pub fn observe(&self, method: &str, path: &str, status: u16, elapsed: Duration) {
+        let route = route(path);
+
+        self.requests
+            .with_label_values(&[method, route, status.to_string().as_str()])
+            .inc();
+
+        self.latency
+            .with_label_values(&[method, route])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    // the text exposition format, with the totals as of now
+    pub fn render(&self, users: i64, history: i64) -> Result<String> {
+        self.users.set(users);
+        self.history.set(history);
+
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::route;
+
+    #[test]
+    fn test_route() {
+        assert_eq!(route("/sync/history"), "/sync/history");
+        assert_eq!(route("/user/ellie"), "/user/:username");
+        assert_eq!(route("/wp-admin.php"), "other");
+    }
+}
@@ -28,6 +28,13 @@ pub struct User {
     pub username: String,
     pub email: String,
     pub password: String,
+
+    // users can't sync until this is set
+    pub verified_at: Option<NaiveDateTime>,
+
+    // set while 2fa is being set up, but only checked once enabled
+    pub totp_secret: Option<String>,
+    pub totp_enabled: bool,
 }
 
 #[derive(sqlx::FromRow)]
@@ -35,15 +42,66 @@ pub struct Session {
     pub id: i64,
     pub user_id: i64,
     pub token: String,
+
+    // sessions from before devices were tracked don't have one
+    pub device_id: Option<i64>,
+}
+
+// The token itself is never loaded, only the hash of it is stored
+#[derive(sqlx::FromRow)]
+pub struct ApiToken {
+    pub id: i64,
+    pub user_id: i64,
+    pub name: String,
+    pub created_at: NaiveDateTime,
+    pub last_used_at: Option<NaiveDateTime>,
+}
+
+// A machine the user syncs from. Once revoked, its sessions are gone and it
+// can't log back in or upload any more history
+#[derive(sqlx::FromRow)]
+pub struct Device {
+    pub id: i64,
+    pub user_id: i64,
+    pub host: String,
+    pub name: String,
+    pub created_at: NaiveDateTime,
+    pub last_login_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+#[derive(sqlx::FromRow)]
+pub struct Record {
+    pub id: i64,
+    pub user_id: i64,
+    pub key: String,
+    pub data: String,
+    pub updated_at: NaiveDateTime,
+}
+
+pub struct NewRecord<'a> {
+    pub user_id: i64,
+    pub key: &'a str,
+    pub data: &'a str,
+    pub updated_at: NaiveDateTime,
 }
 
 pub struct NewUser {
     pub username: String,
     pub email: String,
     pub password: String,
+
+    // without one, the user is verified straight away
+    pub verification_token: Option<String>,
+
+    // claimed along with adding the user, which fails if it has been used
+    pub invite: Option<String>,
 }
 
 pub struct NewSession<'a> {
     pub user_id: i64,
     pub token: &'a str,
+
+    // older clients don't say which device they are
+    pub device_id: Option<i64>,
 }
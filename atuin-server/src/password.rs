@@ -0,0 +1,123 @@
+use std::convert::TryFrom;
+
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use eyre::{eyre, Result};
+
+use crate::settings::PasswordHashing;
+
+fn hasher(settings: &PasswordHashing) -> Result<Argon2<'static>> {
+    let params = Params::new(
+        settings.memory_kib,
+        settings.iterations,
+        settings.parallelism,
+        None,
+    )
+    .map_err(|e| eyre!("invalid password hashing parameters: {}", e))?;
+
+    Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+}
+
+// Fail at startup, rather than on the first registration
+pub fn check(settings: &PasswordHashing) -> Result<()> {
+    hasher(settings).map(|_| ())
+}
+
+// Hashing and checking passwords is meant to be slow, and on the runtime's own
+// threads would hold up every other request, so both run on the blocking pool
+pub async fn hash(settings: &PasswordHashing, password: &str) -> Result<String> {
+    let settings = settings.clone();
+    let password = String::from(password);
+
+    tokio::task::spawn_blocking(move || hash_now(&settings, password.as_str())).await?
+}
+
+pub async fn verify(hash: &str, password: &str) -> bool {
+    let hash = String::from(hash);
+    let password = String::from(password);
+
+    tokio::task::spawn_blocking(move || verify_now(hash.as_str(), password.as_str()))
+        .await
+        .unwrap_or(false)
+}
+
+fn hash_now(settings: &PasswordHashing, password: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    let hash = hasher(settings)?
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| eyre!("failed to hash password: {}", e))?;
+
+    Ok(hash.to_string())
+}
+
+// Any PHC string argon2 can check works here, whatever its parameters. That
+// includes the hashes made by libsodium before the parameters were
+// configurable
+fn verify_now(hash: &str, password: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(parsed) => Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+// Whether a hash was made with anything other than argon2id and the current
+// parameters, and should be replaced the next time we have the password
+pub fn needs_rehash(settings: &PasswordHashing, hash: &str) -> bool {
+    let parsed = match PasswordHash::new(hash) {
+        Ok(p) => p,
+        Err(_) => return true,
+    };
+
+    if parsed.algorithm != Algorithm::Argon2id.ident() {
+        return true;
+    }
+
+    match Params::try_from(&parsed) {
+        Ok(params) => {
+            params.m_cost() != settings.memory_kib
+                || params.t_cost() != settings.iterations
+                || params.p_cost() != settings.parallelism
+        }
+        Err(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use atuin_common::utils::hash_secret;
+
+    use super::*;
+
+    #[test]
+    fn test_rehash() {
+        let settings = PasswordHashing {
+            memory_kib: 8 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        };
+
+        let hashed = hash_now(&settings, "hunter2").unwrap();
+        assert!(hashed.starts_with("$argon2id$"));
+        assert!(verify_now(hashed.as_str(), "hunter2"));
+        assert!(!verify_now(hashed.as_str(), "hunter3"));
+        assert!(!needs_rehash(&settings, hashed.as_str()));
+
+        let stronger = PasswordHashing {
+            iterations: 3,
+            ..settings.clone()
+        };
+        assert!(needs_rehash(&stronger, hashed.as_str()));
+
+        // made by libsodium, as older servers did
+        let legacy = hash_secret("hunter2");
+        assert!(verify_now(legacy.as_str(), "hunter2"));
+        assert!(needs_rehash(&settings, legacy.as_str()));
+
+        assert!(!verify_now("not a hash", "hunter2"));
+        assert!(needs_rehash(&settings, "not a hash"));
+    }
+}
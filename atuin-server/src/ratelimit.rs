@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::settings::RateLimit;
+
+// Buckets are only tidied up once there are this many, so that a quiet
+// server doesn't bother
+const MAX_BUCKETS: usize = 10_000;
+
+// A token bucket for each key. Each holds up to per_minute tokens, a request
+// takes one, and they refill at per_minute a minute. A limit of 0 is no limit.
+pub struct RateLimiter {
+    per_minute: u32,
+    buckets: Mutex<HashMap<String, (f64, Instant)>>,
+    // the number of buckets at which to next tidy up
+    tidy_at: AtomicUsize,
+}
+
+impl RateLimiter {
+    pub fn new(per_minute: u32) -> Self {
+        Self {
+            per_minute,
+            buckets: Mutex::new(HashMap::new()),
+            tidy_at: AtomicUsize::new(MAX_BUCKETS),
+        }
+    }
+
+    pub fn allow(&self, key: &str) -> bool {
+        self.allow_at(key, Instant::now())
+    }
+
+    fn allow_at(&self, key: &str, now: Instant) -> bool {
+        if self.per_minute == 0 {
+            return true;
+        }
+
+        let capacity = f64::from(self.per_minute);
+        let refill = |at: Instant| now.duration_since(at).as_secs_f64() * capacity / 60.0;
+
+        let mut buckets = self.buckets.lock().unwrap();
+
+        // a full bucket is the same as no bucket. if most are still in use,
+        // the next tidy waits until there are twice as many, rather than
+        // going through them all again on every request
+        if buckets.len() >= self.tidy_at.load(Ordering::Relaxed) {
+            buckets.retain(|_, (tokens, at)| *tokens + refill(*at) < capacity);
+
+            self.tidy_at.store(
+                std::cmp::max(MAX_BUCKETS, buckets.len() * 2),
+                Ordering::Relaxed,
+            );
+        }
+
+        let (tokens, at) = buckets.entry(key.to_string()).or_insert((capacity, now));
+
+        *tokens = (*tokens + refill(*at)).min(capacity);
+        *at = now;
+
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+// The limits on logging in and registering, to slow down anyone guessing
+// passwords
+pub struct RateLimits {
+    ip: RateLimiter,
+    username: RateLimiter,
+}
+
+impl RateLimits {
+    pub fn new(settings: &RateLimit) -> Self {
+        Self {
+            ip: RateLimiter::new(settings.ip_per_minute),
+            username: RateLimiter::new(settings.username_per_minute),
+        }
+    }
+
+    // the address isn't known when listening on a unix socket, so only the
+    // username is limited then
+    pub fn allow(&self, ip: Option<IpAddr>, username: &str) -> bool {
+        let ip = ip.map_or(true, |ip| self.ip.allow(ip.to_string().as_str()));
+
+        ip && self.username.allow(username)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::RateLimiter;
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(3);
+        let now = Instant::now();
+
+        assert!(limiter.allow_at("ellie", now));
+        assert!(limiter.allow_at("ellie", now));
+        assert!(limiter.allow_at("ellie", now));
+        assert!(!limiter.allow_at("ellie", now));
+
+        // each key has its own bucket
+        assert!(limiter.allow_at("frank", now));
+
+        // one token comes back every 20 seconds
+        let later = now + Duration::from_secs(20);
+        assert!(limiter.allow_at("ellie", later));
+        assert!(!limiter.allow_at("ellie", later));
+
+        let unlimited = RateLimiter::new(0);
+        assert!((0..100).all(|_| unlimited.allow_at("ellie", now)));
+    }
+}
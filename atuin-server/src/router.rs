@@ -1,13 +1,27 @@
 use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
 
-use eyre::Result;
-use warp::{hyper::StatusCode, Filter};
+use serde::de::DeserializeOwned;
+use warp::http::header::{
+    HeaderMap, HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH,
+};
+use warp::hyper::body::{to_bytes, Bytes};
+use warp::hyper::{Body, StatusCode};
+use warp::{Filter, Reply};
 
-use atuin_common::api::SyncHistoryRequest;
+use atuin_common::api::{
+    ErrorResponse, SyncCursorRequest, SyncDeletedCursorRequest, SyncDeletedRequest,
+    SyncHistoryRequest,
+};
+use atuin_common::compression::{self, Encoding};
+use atuin_common::utils::hash_str;
 
+use super::database::Database;
 use super::handlers;
-use super::{database::Database, database::Postgres};
+use crate::metrics::{route, Metrics};
 use crate::models::User;
+use crate::ratelimit::RateLimits;
 use crate::settings::Settings;
 
 fn with_settings(
@@ -16,109 +30,579 @@ fn with_settings(
     warp::any().map(move || settings.clone())
 }
 
-fn with_db(
-    db: impl Database + Clone + Send + Sync,
-) -> impl Filter<Extract = (impl Database + Clone,), Error = Infallible> + Clone {
+// only matches if metrics are enabled, so that /metrics is otherwise treated
+// like any other unknown route
+fn with_metrics(
+    metrics: Option<Arc<Metrics>>,
+) -> impl Filter<Extract = (Arc<Metrics>,), Error = warp::Rejection> + Clone {
+    warp::any().and_then(move || {
+        let metrics = metrics.clone();
+
+        async move { metrics.ok_or_else(warp::reject) }
+    })
+}
+
+fn with_limits(
+    limits: Arc<RateLimits>,
+) -> impl Filter<Extract = (Arc<RateLimits>,), Error = Infallible> + Clone {
+    warp::any().map(move || limits.clone())
+}
+
+// the address of the client, from the proxy in front of us if it is trusted
+fn with_client_ip(
+    trust_forwarded_for: bool,
+) -> impl Filter<Extract = (Option<IpAddr>,), Error = Infallible> + Clone {
+    warp::addr::remote()
+        .and(warp::header::optional::<String>("x-forwarded-for"))
+        .map(
+            move |remote: Option<SocketAddr>, forwarded: Option<String>| {
+                // each proxy appends the address it saw, so only the last
+                // entry, added by ours, can be trusted. anything before it
+                // came from the client
+                let forwarded = forwarded
+                    .filter(|_| trust_forwarded_for)
+                    .and_then(|f| f.rsplit(',').next().and_then(|ip| ip.trim().parse().ok()));
+
+                forwarded.or_else(|| remote.map(|r| r.ip()))
+            },
+        )
+}
+
+fn with_db<D: Database + Clone + Send + Sync>(
+    db: D,
+) -> impl Filter<Extract = (D,), Error = Infallible> + Clone {
     warp::any().map(move || db.clone())
 }
 
-fn with_user(
-    postgres: Postgres,
-) -> impl Filter<Extract = (User,), Error = warp::Rejection> + Clone {
-    warp::header::<String>("authorization").and_then(move |header: String| {
-        // async closures are still buggy :(
-        let postgres = postgres.clone();
+// the session token from the authorization header
+fn with_token() -> impl Filter<Extract = (String,), Error = warp::Rejection> + Clone {
+    warp::header::<String>("authorization").and_then(|header: String| async move {
+        let header: Vec<&str> = header.split(' ').collect();
 
-        async move {
-            let header: Vec<&str> = header.split(' ').collect();
+        if header.len() == 2 && header[0] == "Token" {
+            Ok(header[1].to_string())
+        } else {
+            Err(warp::reject())
+        }
+    })
+}
+
+// A json body, which may have been compressed by the client. The limit
+// applies again while decompressing, as a small body can expand to a huge one
+fn with_json_body<T: DeserializeOwned + Send>(
+    max_body_size: u64,
+) -> impl Filter<Extract = (T,), Error = warp::Rejection> + Clone {
+    // a body without a length is turned away, and hyper won't read past the
+    // length, so no more than the limit is ever read
+    let limit = if max_body_size > 0 {
+        max_body_size
+    } else {
+        u64::MAX
+    };
 
-            let token;
+    warp::header::optional::<String>("content-encoding")
+        .and(warp::body::content_length_limit(limit))
+        .and(warp::body::bytes())
+        .and_then(move |encoding: Option<String>, body: Bytes| async move {
+            let body = match encoding.as_deref().map(str::trim) {
+                None | Some("identity") => body.to_vec(),
+                Some(name) => Encoding::from_name(name)
+                    .ok_or_else(warp::reject)?
+                    .decode_limited(&body, max_body_size)
+                    .map_err(|_| warp::reject())?,
+            };
 
-            if header.len() == 2 {
-                if header[0] != "Token" {
-                    return Err(warp::reject());
-                }
+            serde_json::from_slice::<T>(&body).map_err(|_| warp::reject())
+        })
+}
 
-                token = header[1];
+// Matches any request with a body over the limit, so that it is turned away
+// before the body is read. 0 is no limit
+fn body_too_large(
+    max_body_size: u64,
+) -> impl Filter<Extract = (Box<dyn warp::Reply>,), Error = warp::Rejection> + Clone {
+    warp::header::<u64>("content-length")
+        .and_then(move |length: u64| async move {
+            if max_body_size > 0 && length > max_body_size {
+                Ok(length)
             } else {
-                return Err(warp::reject());
+                Err(warp::reject())
             }
+        })
+        .map(move |length: u64| {
+            let reply: Box<dyn warp::Reply> = Box::new(ErrorResponse::reply(
+                format!(
+                    "request body of {} bytes is over the limit of {} bytes",
+                    length, max_body_size
+                )
+                .as_str(),
+                StatusCode::PAYLOAD_TOO_LARGE,
+            ));
+
+            reply
+        })
+}
+
+// Compress the response if the client asked for it, and let it know which
+// encodings we can take request bodies in
+async fn compress_reply(
+    headers: HeaderMap,
+    reply: impl Reply,
+) -> Result<warp::reply::Response, Infallible> {
+    let mut response = reply.into_response();
+
+    response.headers_mut().insert(
+        ACCEPT_ENCODING,
+        HeaderValue::from_static(compression::ACCEPT),
+    );
+
+    let encoding = headers
+        .get(ACCEPT_ENCODING)
+        .and_then(|h| h.to_str().ok())
+        .and_then(Encoding::negotiate);
+
+    let encoding = match encoding {
+        Some(e) if !response.headers().contains_key(CONTENT_ENCODING) => e,
+        _ => return Ok(response),
+    };
+
+    let (mut parts, body) = response.into_parts();
+
+    let body = match to_bytes(body).await {
+        Ok(b) => b,
+        Err(e) => {
+            error!("failed to read response body: {}", e);
+
+            return Ok(
+                warp::reply::with_status(warp::reply(), StatusCode::INTERNAL_SERVER_ERROR)
+                    .into_response(),
+            );
+        }
+    };
+
+    if body.len() < compression::MIN_SIZE {
+        return Ok(warp::reply::Response::from_parts(parts, Body::from(body)));
+    }
+
+    match encoding.encode(&body) {
+        Ok(compressed) => {
+            parts.headers.remove(CONTENT_LENGTH);
+            parts.headers.insert(
+                CONTENT_ENCODING,
+                HeaderValue::from_static(encoding.as_str()),
+            );
 
-            let user = postgres
-                .get_session_user(token)
+            Ok(warp::reply::Response::from_parts(
+                parts,
+                Body::from(compressed),
+            ))
+        }
+        Err(e) => {
+            error!("failed to compress response: {}", e);
+
+            Ok(warp::reply::Response::from_parts(parts, Body::from(body)))
+        }
+    }
+}
+
+// Only a login session. Managing the account needs one, so that a leaked api
+// token can't be used to take it over
+fn with_session_user<D: Database + Clone + Send + Sync + 'static>(
+    db: D,
+) -> impl Filter<Extract = (User,), Error = warp::Rejection> + Clone {
+    with_token().and_then(move |token: String| {
+        // async closures are still buggy :(
+        let db = db.clone();
+
+        async move {
+            let user = db
+                .get_session_user(token.as_str())
                 .await
                 .map_err(|_| warp::reject())?;
 
+            tracing::Span::current().record("user_id", &user.id);
+
+            Ok::<_, warp::Rejection>(user)
+        }
+    })
+}
+
+// A login session, or an api token. Both are sent as "Authorization: Token".
+// Neither is found once its device has been revoked, so a revoked device is
+// turned away here, whichever route it is after
+fn with_user<D: Database + Clone + Send + Sync + 'static>(
+    db: D,
+) -> impl Filter<Extract = (User,), Error = warp::Rejection> + Clone {
+    with_token().and_then(move |token: String| {
+        let db = db.clone();
+
+        async move {
+            let user = match db.get_session_user(token.as_str()).await {
+                Ok(user) => user,
+                Err(_) => db
+                    .get_api_token_user(hash_str(token.as_str()).as_str())
+                    .await
+                    .map_err(|_| warp::reject())?,
+            };
+
+            tracing::Span::current().record("user_id", &user.id);
+
+            Ok::<_, warp::Rejection>(user)
+        }
+    })
+}
+
+// Syncing needs a verified email address. Told apart from other rejections so
+// that the user can be told why
+#[derive(Debug)]
+struct Unverified;
+
+impl warp::reject::Reject for Unverified {}
+
+// with_user, for users that have verified their email address
+fn with_verified_user<D: Database + Clone + Send + Sync + 'static>(
+    db: D,
+) -> impl Filter<Extract = (User,), Error = warp::Rejection> + Clone {
+    with_user(db).and_then(|user: User| async move {
+        if user.verified_at.is_some() {
             Ok(user)
+        } else {
+            Err(warp::reject::custom(Unverified))
         }
     })
 }
 
-pub async fn router(
+// Turns away an unverified user with a reason. Anything else is passed on, so
+// that the other routes are still tried
+async fn unverified_reply(
+    rejection: warp::Rejection,
+) -> Result<Box<dyn warp::Reply>, warp::Rejection> {
+    if rejection.find::<Unverified>().is_none() {
+        return Err(rejection);
+    }
+
+    Ok(Box::new(ErrorResponse::reply(
+        "verify your email address to start syncing, using the link sent when you registered",
+        StatusCode::FORBIDDEN,
+    )))
+}
+
+pub fn router<D: Database + Clone + Send + Sync + 'static>(
     settings: &Settings,
-) -> Result<impl Filter<Extract = impl warp::Reply, Error = Infallible> + Clone> {
-    let postgres = Postgres::new(settings.db_uri.as_str()).await?;
+    db: D,
+    metrics: Option<Arc<Metrics>>,
+) -> impl Filter<Extract = impl warp::Reply, Error = Infallible> + Clone {
     let index = warp::get().and(warp::path::end()).map(handlers::index);
 
+    let health = warp::get()
+        .and(warp::path("healthz"))
+        .and(warp::path::end())
+        .and(with_db(db.clone()))
+        .and_then(handlers::health);
+
+    let limits = Arc::new(RateLimits::new(&settings.rate_limit));
+    let trust_forwarded_for = settings.rate_limit.trust_forwarded_for;
+
+    let metrics_route = warp::get()
+        .and(warp::path("metrics"))
+        .and(warp::path::end())
+        .and(with_metrics(metrics.clone()))
+        .and(with_db(db.clone()))
+        .and_then(handlers::metrics::get);
+
     let count = warp::get()
         .and(warp::path("sync"))
         .and(warp::path("count"))
         .and(warp::path::end())
-        .and(with_user(postgres.clone()))
-        .and(with_db(postgres.clone()))
+        .and(with_verified_user(db.clone()))
+        .and(with_db(db.clone()))
         .and_then(handlers::history::count);
 
+    let status = warp::get()
+        .and(warp::path("sync"))
+        .and(warp::path("status"))
+        .and(warp::path::end())
+        .and(with_verified_user(db.clone()))
+        .and(with_db(db.clone()))
+        .and_then(handlers::history::status);
+
     let sync = warp::get()
         .and(warp::path("sync"))
         .and(warp::path("history"))
         .and(warp::query::<SyncHistoryRequest>())
         .and(warp::path::end())
-        .and(with_user(postgres.clone()))
-        .and(with_db(postgres.clone()))
+        .and(with_verified_user(db.clone()))
+        .and(with_db(db.clone()))
         .and_then(handlers::history::list);
 
+    let sync_cursor = warp::get()
+        .and(warp::path("sync"))
+        .and(warp::path("cursor"))
+        .and(warp::query::<SyncCursorRequest>())
+        .and(warp::path::end())
+        .and(with_verified_user(db.clone()))
+        .and(with_db(db.clone()))
+        .and_then(handlers::history::list_after);
+
+    let sync_deleted = warp::get()
+        .and(warp::path("sync"))
+        .and(warp::path("deleted"))
+        .and(warp::query::<SyncDeletedRequest>())
+        .and(warp::path::end())
+        .and(with_verified_user(db.clone()))
+        .and(with_db(db.clone()))
+        .and_then(handlers::history::deleted);
+
+    let sync_deleted_cursor = warp::get()
+        .and(warp::path("sync"))
+        .and(warp::path("deleted"))
+        .and(warp::path("cursor"))
+        .and(warp::query::<SyncDeletedCursorRequest>())
+        .and(warp::path::end())
+        .and(with_verified_user(db.clone()))
+        .and(with_db(db.clone()))
+        .and_then(handlers::history::deleted_after);
+
     let add_history = warp::post()
         .and(warp::path("history"))
         .and(warp::path::end())
-        .and(warp::body::json())
-        .and(with_user(postgres.clone()))
-        .and(with_db(postgres.clone()))
+        .and(with_json_body(settings.max_body_size))
+        .and(with_verified_user(db.clone()))
+        .and(with_settings(settings.clone()))
+        .and(with_db(db.clone()))
         .and_then(handlers::history::add);
 
+    let replace_history = warp::put()
+        .and(warp::path("history"))
+        .and(warp::path::end())
+        .and(with_json_body(settings.max_body_size))
+        .and(with_verified_user(db.clone()))
+        .and(with_settings(settings.clone()))
+        .and(with_db(db.clone()))
+        .and_then(handlers::history::replace);
+
+    let delete_history = warp::delete()
+        .and(warp::path("history"))
+        .and(warp::path::end())
+        .and(with_json_body(settings.max_body_size))
+        .and(with_verified_user(db.clone()))
+        .and(with_db(db.clone()))
+        .and_then(handlers::history::delete);
+
+    let list_records = warp::get()
+        .and(warp::path("records"))
+        .and(warp::path::end())
+        .and(with_verified_user(db.clone()))
+        .and(with_db(db.clone()))
+        .and_then(handlers::record::list);
+
+    // aliases and scripts are ran on every machine, so a revoked device must
+    // not be able to upload them. with_user, under with_verified_user, sees to
+    // that
+    let add_records = warp::post()
+        .and(warp::path("records"))
+        .and(warp::path::end())
+        .and(with_json_body(settings.max_body_size))
+        .and(with_verified_user(db.clone()))
+        .and(with_settings(settings.clone()))
+        .and(with_db(db.clone()))
+        .and_then(handlers::record::add);
+
     let user = warp::get()
         .and(warp::path("user"))
         .and(warp::path::param::<String>())
         .and(warp::path::end())
-        .and(with_db(postgres.clone()))
+        .and(with_db(db.clone()))
         .and_then(handlers::user::get);
 
+    let verify = warp::get()
+        .and(warp::path("verify"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(with_db(db.clone()))
+        .and_then(handlers::user::verify);
+
     let register = warp::post()
         .and(warp::path("register"))
         .and(warp::path::end())
-        .and(warp::body::json())
+        .and(with_json_body(settings.max_body_size))
         .and(with_settings(settings.clone()))
-        .and(with_db(postgres.clone()))
+        .and(with_limits(limits.clone()))
+        .and(with_client_ip(trust_forwarded_for))
+        .and(with_db(db.clone()))
         .and_then(handlers::user::register);
 
+    let delete_account = warp::delete()
+        .and(warp::path("account"))
+        .and(warp::path::end())
+        .and(with_session_user(db.clone()))
+        .and(with_db(db.clone()))
+        .and_then(handlers::user::delete);
+
+    let change_password = warp::post()
+        .and(warp::path("account"))
+        .and(warp::path("password"))
+        .and(warp::path::end())
+        .and(with_json_body(settings.max_body_size))
+        .and(with_token())
+        .and(with_session_user(db.clone()))
+        .and(with_settings(settings.clone()))
+        .and(with_db(db.clone()))
+        .and_then(handlers::user::change_password);
+
+    let enable_totp = warp::post()
+        .and(warp::path("account"))
+        .and(warp::path("2fa"))
+        .and(warp::path::end())
+        .and(with_session_user(db.clone()))
+        .and(with_db(db.clone()))
+        .and_then(handlers::user::enable_totp);
+
+    let confirm_totp = warp::post()
+        .and(warp::path("account"))
+        .and(warp::path("2fa"))
+        .and(warp::path("confirm"))
+        .and(warp::path::end())
+        .and(with_json_body(settings.max_body_size))
+        .and(with_session_user(db.clone()))
+        .and(with_db(db.clone()))
+        .and_then(handlers::user::confirm_totp);
+
+    let disable_totp = warp::delete()
+        .and(warp::path("account"))
+        .and(warp::path("2fa"))
+        .and(warp::path::end())
+        .and(with_json_body(settings.max_body_size))
+        .and(with_session_user(db.clone()))
+        .and(with_db(db.clone()))
+        .and_then(handlers::user::disable_totp);
+
+    let list_tokens = warp::get()
+        .and(warp::path("account"))
+        .and(warp::path("tokens"))
+        .and(warp::path::end())
+        .and(with_session_user(db.clone()))
+        .and(with_db(db.clone()))
+        .and_then(handlers::token::list);
+
+    let create_token = warp::post()
+        .and(warp::path("account"))
+        .and(warp::path("tokens"))
+        .and(warp::path::end())
+        .and(with_json_body(settings.max_body_size))
+        .and(with_token())
+        .and(with_session_user(db.clone()))
+        .and(with_db(db.clone()))
+        .and_then(handlers::token::create);
+
+    let delete_token = warp::delete()
+        .and(warp::path("account"))
+        .and(warp::path("tokens"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(with_session_user(db.clone()))
+        .and(with_db(db.clone()))
+        .and_then(handlers::token::delete);
+
+    let list_devices = warp::get()
+        .and(warp::path("account"))
+        .and(warp::path("devices"))
+        .and(warp::path::end())
+        .and(with_session_user(db.clone()))
+        .and(with_db(db.clone()))
+        .and_then(handlers::device::list);
+
+    let revoke_device = warp::delete()
+        .and(warp::path("account"))
+        .and(warp::path("devices"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(with_session_user(db.clone()))
+        .and(with_db(db.clone()))
+        .and_then(handlers::device::revoke);
+
+    let logout = warp::post()
+        .and(warp::path("logout"))
+        .and(warp::path::end())
+        .and(with_token())
+        .and(with_session_user(db.clone()))
+        .and(with_db(db.clone()))
+        .and_then(handlers::user::logout);
+
     let login = warp::post()
         .and(warp::path("login"))
         .and(warp::path::end())
-        .and(warp::body::json())
-        .and(with_db(postgres))
+        .and(with_json_body(settings.max_body_size))
+        .and(with_settings(settings.clone()))
+        .and(with_limits(limits))
+        .and(with_client_ip(trust_forwarded_for))
+        .and(with_db(db.clone()))
         .and_then(handlers::user::login);
 
-    let r = warp::any()
+    // everything that needs a verified email address
+    let synced = count
+        .or(status)
+        .or(sync)
+        .or(sync_cursor)
+        .or(sync_deleted)
+        .or(sync_deleted_cursor)
+        .or(add_history)
+        .or(replace_history)
+        .or(delete_history)
+        .or(list_records)
+        .or(add_records)
+        .recover(unverified_reply);
+
+    warp::any()
+        .and(warp::header::headers_cloned())
         .and(
-            index
-                .or(count)
-                .or(sync)
-                .or(add_history)
+            body_too_large(settings.max_body_size)
+                .or(index)
+                .or(health)
+                .or(synced)
                 .or(user)
                 .or(register)
+                .or(verify)
                 .or(login)
+                .or(logout)
+                .or(delete_account)
+                .or(change_password)
+                .or(enable_totp)
+                .or(confirm_totp)
+                .or(disable_totp)
+                .or(list_tokens)
+                .or(create_token)
+                .or(delete_token)
+                .or(list_devices)
+                .or(revoke_device)
+                .or(metrics_route)
                 .or(warp::any().map(|| warp::reply::with_status("☕", StatusCode::IM_A_TEAPOT))),
         )
-        .with(warp::filters::log::log("atuin::api"));
+        .and_then(compress_reply)
+        .with(warp::filters::log::custom(move |info| {
+            info!(
+                status = info.status().as_u16(),
+                latency_ms = info.elapsed().as_secs_f64() * 1000.0,
+                "finished request"
+            );
 
-    Ok(r)
+            if let Some(metrics) = &metrics {
+                metrics.observe(
+                    info.method().as_str(),
+                    info.path(),
+                    info.status().as_u16(),
+                    info.elapsed(),
+                );
+            }
+        }))
+        // a span for each request, so that anything logged while handling it
+        // says which request it was for. the user is filled in once known
+        .with(warp::trace(|info| {
+            info_span!(
+                "request",
+                method = %info.method(),
+                route = route(info.path()),
+                user_id = tracing::field::Empty,
+            )
+        }))
 }
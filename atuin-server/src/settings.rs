@@ -7,15 +7,87 @@ use eyre::{eyre, Result};
 
 pub const HISTORY_PAGE_SIZE: i64 = 100;
 
+// How requests are logged. pretty is meant for reading, json for collecting
+// with something else
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Pretty,
+    Json,
+}
+
+// Login and registration attempts allowed a minute, from one address and for
+// one username. 0 is no limit
+#[derive(Clone, Debug, Deserialize)]
+pub struct RateLimit {
+    pub ip_per_minute: u32,
+    pub username_per_minute: u32,
+
+    // take the address from X-Forwarded-For, when behind a single reverse
+    // proxy
+    pub trust_forwarded_for: bool,
+}
+
+// Serve https directly, with a PEM encoded certificate and private key
+#[derive(Clone, Debug, Deserialize)]
+pub struct Tls {
+    pub enable: bool,
+    pub cert_path: String,
+    pub key_path: String,
+}
+
+// For sending verification emails to new users. Without it, new users can
+// sync straight away
+#[derive(Clone, Debug, Deserialize)]
+pub struct Smtp {
+    pub enable: bool,
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+    pub from: String,
+}
+
+// Argon2id parameters for new password hashes. Hashes made with anything else
+// are replaced when the user next logs in
+#[derive(Clone, Debug, Deserialize)]
+pub struct PasswordHashing {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct Settings {
     pub host: String,
     pub port: u16,
     pub db_uri: String,
     pub open_registration: bool,
+    pub auto_migrate: bool,
+    pub metrics: bool,
+    pub log_format: LogFormat,
+    pub rate_limit: RateLimit,
+    pub tls: Tls,
+    pub smtp: Smtp,
+    pub password_hashing: PasswordHashing,
+
+    // where clients reach the server, for links in emails
+    pub public_url: String,
+
+    // empty to use host and port, "unix:<path>", or "systemd"
+    pub listen: String,
+
+    // in bytes, of the encrypted history and of any request. 0 is no limit
+    pub max_history_length: usize,
+    pub max_body_size: u64,
 }
 
 impl Settings {
+    // the storage backend is picked from the scheme of the db uri
+    pub fn is_sqlite(&self) -> bool {
+        self.db_uri.starts_with("sqlite:")
+    }
+
     pub fn new() -> Result<Self> {
         let config_dir = atuin_common::utils::config_dir();
         let config_dir = config_dir.as_path();
@@ -47,7 +119,29 @@ impl Settings {
         s.set_default("host", "127.0.0.1")?;
         s.set_default("port", 8888)?;
         s.set_default("open_registration", false)?;
+        s.set_default("auto_migrate", true)?;
         s.set_default("db_uri", "default_uri")?;
+        s.set_default("metrics", false)?;
+        s.set_default("log_format", "pretty")?;
+        s.set_default("max_history_length", 32 * 1024)?;
+        s.set_default("max_body_size", 16 * 1024 * 1024)?;
+        s.set_default("listen", "")?;
+        s.set_default("public_url", "")?;
+        s.set_default("smtp.enable", false)?;
+        s.set_default("smtp.host", "")?;
+        s.set_default("smtp.port", 587)?;
+        s.set_default("smtp.username", "")?;
+        s.set_default("smtp.password", "")?;
+        s.set_default("smtp.from", "")?;
+        s.set_default("password_hashing.memory_kib", 64 * 1024)?;
+        s.set_default("password_hashing.iterations", 3)?;
+        s.set_default("password_hashing.parallelism", 1)?;
+        s.set_default("tls.enable", false)?;
+        s.set_default("tls.cert_path", "")?;
+        s.set_default("tls.key_path", "")?;
+        s.set_default("rate_limit.ip_per_minute", 20)?;
+        s.set_default("rate_limit.username_per_minute", 5)?;
+        s.set_default("rate_limit.trust_forwarded_for", false)?;
 
         s.merge(Environment::with_prefix("atuin").separator("_"))?;
 
@@ -0,0 +1,67 @@
+use rand::RngCore;
+use totp_lite::{totp_custom, Sha1, DEFAULT_STEP};
+
+const DIGITS: u32 = 6;
+
+// authenticator apps and servers are rarely in perfect sync, so also accept
+// the codes either side of the current one
+const SKEW: u64 = 1;
+
+// A new secret, base32 encoded as authenticator apps expect
+pub fn generate_secret() -> String {
+    let mut secret = [0_u8; 20];
+    rand::thread_rng().fill_bytes(&mut secret);
+
+    base32::encode(base32::Alphabet::RFC4648 { padding: false }, &secret)
+}
+
+// The link for authenticator apps to add the account with, usually shown as a
+// QR code
+pub fn url(username: &str, secret: &str) -> String {
+    format!(
+        "otpauth://totp/atuin:{}?secret={}&issuer=atuin&digits={}&period={}",
+        urlencoding::encode(username),
+        secret,
+        DIGITS,
+        DEFAULT_STEP
+    )
+}
+
+pub fn verify(secret: &str, code: &str, now: u64) -> bool {
+    let secret = match base32::decode(base32::Alphabet::RFC4648 { padding: false }, secret) {
+        Some(s) => s,
+        None => return false,
+    };
+
+    let code = code.trim();
+
+    (0..=SKEW * 2).any(|i| {
+        let time = (now + i * DEFAULT_STEP).saturating_sub(SKEW * DEFAULT_STEP);
+        totp_custom::<Sha1>(DEFAULT_STEP, DIGITS, &secret, time) == code
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        // the test secret from RFC 6238, "12345678901234567890"
+        let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+        assert!(verify(secret, "287082", 59));
+        assert!(verify(secret, " 287082\n", 59));
+
+        // a step either side is still fine, but not any further
+        assert!(verify(secret, "287082", 59 + 30));
+        assert!(!verify(secret, "287082", 59 + 60));
+
+        assert!(!verify(secret, "123456", 59));
+        assert!(!verify("not base32!", "287082", 59));
+
+        let secret = generate_secret();
+        assert_eq!(secret.len(), 32);
+        assert!(url("ellie", secret.as_str()).starts_with("otpauth://totp/atuin:ellie?secret="));
+    }
+}
@@ -0,0 +1,354 @@
+use std::io::{stdin, stdout, Write};
+use std::path::Path;
+
+use tabwriter::TabWriter;
+
+use eyre::{eyre, Result};
+use structopt::StructOpt;
+
+use atuin_client::api_client;
+use atuin_client::encryption::{decrypt, load_key, EncryptedHistory};
+use atuin_client::settings::Settings;
+use atuin_common::api::DeviceResponse;
+use atuin_common::utils::looks_encrypted;
+
+#[derive(StructOpt)]
+pub enum Cmd {
+    #[structopt(about = "delete your account, and all of your history stored on the server")]
+    Delete,
+
+    #[structopt(about = "change your password. other machines will need to login again")]
+    ChangePassword {
+        #[structopt(long, short, about = "your current password")]
+        current: String,
+
+        #[structopt(long, short, about = "the password to change to")]
+        new: String,
+    },
+
+    #[structopt(
+        name = "2fa",
+        about = "require a code from an authenticator app when logging in"
+    )]
+    TwoFactor(TwoFactorCmd),
+
+    #[structopt(about = "manage api tokens, for scripts and CI")]
+    Token(TokenCmd),
+
+    #[structopt(about = "list the machines you sync from, or revoke a lost one")]
+    Devices {
+        #[structopt(subcommand)]
+        cmd: Option<DevicesCmd>,
+    },
+
+    #[structopt(about = "check that the history stored on the server is encrypted")]
+    VerifyEncryption {
+        #[structopt(long, short, default_value = "5", about = "how many items to check")]
+        count: usize,
+    },
+}
+
+#[derive(StructOpt)]
+pub enum TokenCmd {
+    #[structopt(about = "create a new token. it is only shown once")]
+    Create {
+        #[structopt(long, short, about = "a name to recognise the token by")]
+        name: String,
+    },
+
+    #[structopt(about = "list your tokens, and when they were last used", aliases=&["l", "ls"])]
+    List,
+
+    #[structopt(about = "revoke a token, so that it can no longer be used")]
+    Revoke { name: String },
+}
+
+#[derive(StructOpt)]
+pub enum DevicesCmd {
+    #[structopt(about = "list your devices, and when they last logged in", aliases=&["l", "ls"])]
+    List,
+
+    #[structopt(about = "log a device out, and stop it from syncing again")]
+    Revoke {
+        #[structopt(about = "the name of the device, or the start of its host")]
+        device: String,
+    },
+}
+
+#[derive(StructOpt)]
+pub enum TwoFactorCmd {
+    #[structopt(about = "set up 2fa with an authenticator app")]
+    Enable,
+
+    #[structopt(about = "stop requiring a code when logging in")]
+    Disable {
+        #[structopt(long, short, about = "a code from your authenticator app")]
+        code: String,
+    },
+}
+
+fn read_line(prompt: &str) -> Result<String> {
+    print!("{}", prompt);
+    stdout().flush()?;
+
+    let mut input = String::new();
+    stdin().read_line(&mut input)?;
+
+    Ok(input.trim().to_string())
+}
+
+// Find a device by its name, or by the start of its host, which is what's
+// left to go on when it was never given a name
+fn find_device<'a>(devices: &'a [DeviceResponse], query: &str) -> Result<&'a DeviceResponse> {
+    let matches: Vec<&DeviceResponse> = devices
+        .iter()
+        .filter(|d| d.name == query || d.host.starts_with(query))
+        .collect();
+
+    match matches.as_slice() {
+        [device] => Ok(device),
+        [] => Err(eyre!("no device matches {}", query)),
+        _ => Err(eyre!(
+            "{} matches more than one device, give more of its host",
+            query
+        )),
+    }
+}
+
+// Ask before doing anything that can't be undone
+fn confirm(prompt: &str) -> Result<bool> {
+    let input = read_line(format!("{} [y/N] ", prompt).as_str())?;
+
+    Ok(matches!(input.to_lowercase().as_str(), "y" | "yes"))
+}
+
+impl Cmd {
+    pub async fn run(&self, settings: &Settings) -> Result<()> {
+        if !Path::new(settings.session_path.as_str()).exists() {
+            return Err(eyre!("you are not logged in"));
+        }
+
+        let client = api_client::Client::new(settings)?;
+
+        match self {
+            Self::Delete => {
+                if !confirm(
+                    "This deletes your account, and all of your history on the server. Local history is kept. Continue?",
+                )? {
+                    println!("Not deleting account");
+                    return Ok(());
+                }
+
+                client.delete_account().await?;
+                std::fs::remove_file(settings.session_path.as_str())?;
+
+                println!("Your account has been deleted");
+
+                Ok(())
+            }
+
+            Self::ChangePassword { current, new } => {
+                client
+                    .change_password(current.as_str(), new.as_str())
+                    .await?;
+
+                println!("Your password has been changed");
+
+                Ok(())
+            }
+
+            Self::TwoFactor(TwoFactorCmd::Enable) => {
+                let totp = client.enable_totp().await?;
+
+                println!("Add this account to your authenticator app, with the link");
+                println!();
+                println!("    {}", totp.url);
+                println!();
+                println!("or by entering the secret {}", totp.secret);
+                println!();
+
+                let code = read_line("Code from the app: ")?;
+                client.confirm_totp(code.as_str()).await?;
+
+                println!("2fa is enabled. Logging in now needs a code, given with --code");
+
+                Ok(())
+            }
+
+            Self::TwoFactor(TwoFactorCmd::Disable { code }) => {
+                client.disable_totp(code.as_str()).await?;
+
+                println!("2fa is disabled");
+
+                Ok(())
+            }
+
+            Self::Token(TokenCmd::Create { name }) => {
+                let token = client.create_token(name.as_str()).await?;
+
+                println!("{}", token);
+                eprintln!();
+                eprintln!(
+                    "This is the only time the token is shown. Give it to atuin as ATUIN_API_TOKEN"
+                );
+
+                Ok(())
+            }
+
+            Self::Token(TokenCmd::List) => {
+                let mut writer = TabWriter::new(stdout()).padding(2);
+                writeln!(writer, "NAME\tCREATED\tLAST USED")?;
+
+                for token in client.list_tokens().await? {
+                    let last_used = token.last_used_at.map_or_else(
+                        || String::from("never"),
+                        |t| t.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    );
+
+                    writeln!(
+                        writer,
+                        "{}\t{}\t{}",
+                        token.name,
+                        token.created_at.format("%Y-%m-%d %H:%M:%S"),
+                        last_used
+                    )?;
+                }
+
+                writer.flush()?;
+
+                Ok(())
+            }
+
+            Self::Devices { cmd: None }
+            | Self::Devices {
+                cmd: Some(DevicesCmd::List),
+            } => {
+                let this_host = api_client::this_host();
+
+                let mut writer = TabWriter::new(stdout()).padding(2);
+                writeln!(writer, "NAME\tHOST\tLAST LOGIN\tSTATUS")?;
+
+                for device in client.list_devices().await? {
+                    let status = if device.revoked_at.is_some() {
+                        "revoked"
+                    } else if device.host == this_host {
+                        "this device"
+                    } else {
+                        ""
+                    };
+
+                    let name = if device.name.is_empty() {
+                        "-"
+                    } else {
+                        device.name.as_str()
+                    };
+
+                    writeln!(
+                        writer,
+                        "{}\t{}\t{}\t{}",
+                        name,
+                        &device.host[..std::cmp::min(12, device.host.len())],
+                        device.last_login_at.format("%Y-%m-%d %H:%M:%S"),
+                        status
+                    )?;
+                }
+
+                writer.flush()?;
+
+                Ok(())
+            }
+
+            Self::Devices {
+                cmd: Some(DevicesCmd::Revoke { device }),
+            } => {
+                let devices = client.list_devices().await?;
+                let device = find_device(&devices, device.as_str())?;
+                let this_device = device.host == api_client::this_host();
+
+                let prompt = if this_device {
+                    "This is the device you are using. It will be logged out, and can't sync again. Continue?"
+                } else {
+                    "The device will be logged out, and can't sync again. Continue?"
+                };
+
+                if !confirm(prompt)? {
+                    println!("Not revoking device");
+                    return Ok(());
+                }
+
+                client.revoke_device(device.host.as_str()).await?;
+
+                // the session went with the device
+                if this_device {
+                    std::fs::remove_file(settings.session_path.as_str())?;
+                }
+
+                println!("Revoked {}", device.host);
+
+                Ok(())
+            }
+
+            Self::VerifyEncryption { count } => verify_encryption(&client, settings, *count).await,
+
+            Self::Token(TokenCmd::Revoke { name }) => {
+                client.delete_token(name.as_str()).await?;
+
+                println!("Revoked {}", name);
+
+                Ok(())
+            }
+        }
+    }
+}
+
+// Look at history as the server has it. Each item should be encrypted with
+// one of our algorithms, by a key we have, and nothing else
+async fn verify_encryption(
+    client: &api_client::Client<'_>,
+    settings: &Settings,
+    count: usize,
+) -> Result<()> {
+    let key = load_key(settings)?;
+
+    // the server leaves out history from the host given, so give one that
+    // matches nothing to see everything
+    let (history, _) = client.get_raw_history_after(0, Some(String::new())).await?;
+
+    if history.is_empty() {
+        println!("There is no history on the server to check yet");
+        return Ok(());
+    }
+
+    let mut failed = 0;
+
+    for (i, data) in history.iter().take(count).enumerate() {
+        let problem = if looks_encrypted(data.as_str()) {
+            serde_json::from_str::<EncryptedHistory>(data.as_str())
+                .map_err(eyre::Report::from)
+                .and_then(|h| decrypt(&h, &key))
+                .err()
+                .map(|_| "it does not decrypt with your key")
+        } else {
+            Some("it is not encrypted")
+        };
+
+        match problem {
+            None => println!("[ok]   item {}", i + 1),
+            Some(p) => {
+                failed += 1;
+                println!("[fail] item {}: {}", i + 1, p);
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(eyre!(
+            "{} items on the server are not encrypted as expected",
+            failed
+        ));
+    }
+
+    println!("Your history on the server is encrypted");
+
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use std::io::{stdout, Write};
+
+use eyre::{eyre, Result};
+use structopt::StructOpt;
+use tabwriter::TabWriter;
+
+use atuin_client::alias::{self, NAMESPACE};
+use atuin_client::database::Database;
+use atuin_client::record::Record;
+
+#[derive(StructOpt)]
+pub enum Cmd {
+    #[structopt(about = "set an alias, replacing any with the same name")]
+    Set {
+        name: String,
+
+        #[structopt(required = true, about = "the command the alias runs")]
+        value: Vec<String>,
+    },
+
+    #[structopt(about = "list your aliases", aliases=&["l", "ls"])]
+    List,
+
+    #[structopt(about = "delete an alias", aliases=&["rm"])]
+    Delete { name: String },
+}
+
+impl Cmd {
+    pub async fn run(&self, db: &(impl Database + Send + Sync)) -> Result<()> {
+        match self {
+            Self::Set { name, value } => {
+                let value = value.join(" ");
+                alias::check(name.as_str(), value.as_str())?;
+
+                db.save_record(&Record::new(NAMESPACE, name.as_str(), Some(value)))
+                    .await?;
+
+                println!("alias {} set, it will be in new shells", name);
+
+                Ok(())
+            }
+
+            Self::List => {
+                let mut writer = TabWriter::new(stdout()).padding(2);
+
+                for r in db.records(NAMESPACE).await? {
+                    writeln!(writer, "{}\t{}", r.key, r.value.unwrap_or_default())?;
+                }
+
+                writer.flush()?;
+
+                Ok(())
+            }
+
+            Self::Delete { name } => {
+                let existing = db.record(NAMESPACE, name.as_str()).await?;
+
+                if existing.map_or(true, |r| r.value.is_none()) {
+                    return Err(eyre!("there is no alias named {}", name));
+                }
+
+                db.save_record(&Record::new(NAMESPACE, name.as_str(), None))
+                    .await?;
+
+                println!("alias {} deleted, it will be gone from new shells", name);
+
+                Ok(())
+            }
+        }
+    }
+}
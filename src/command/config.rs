@@ -0,0 +1,125 @@
+use std::fs;
+
+use eyre::{eyre, Result};
+use structopt::StructOpt;
+use toml_edit::{Document, Value};
+
+use atuin_client::settings::Settings;
+
+#[derive(StructOpt)]
+pub enum Cmd {
+    #[structopt(about = "print a config file listing every setting, with its default")]
+    Default {
+        #[structopt(long, about = "write it to the config file, rather than printing it")]
+        write: bool,
+
+        #[structopt(long, about = "replace the config file if there already is one")]
+        force: bool,
+    },
+
+    #[structopt(about = "print the value of a setting, such as sync_address or keys.accept")]
+    Get { key: String },
+
+    #[structopt(about = "change a setting in the config file")]
+    Set { key: String, value: String },
+}
+
+// Settings are read with the defaults, config file and environment variables
+// all merged, so this is the value atuin will actually use
+fn get(key: &str) -> Result<String> {
+    let settings = Settings::builder()?;
+
+    if let Ok(value) = settings.get_str(key) {
+        return Ok(value);
+    }
+
+    settings
+        .get_array(key)
+        .map(|values| {
+            values
+                .into_iter()
+                .filter_map(|v| v.into_str().ok())
+                .collect::<Vec<String>>()
+                .join("\n")
+        })
+        .map_err(|_| eyre!("unknown setting {}", key))
+}
+
+// Edit the config file in place, so that comments and formatting are kept
+fn set(key: &str, value: &str) -> Result<()> {
+    // anything that isn't already a valid toml value is taken as a string, so
+    // that `set sync_address https://...` works without quoting
+    let value = value
+        .parse::<Value>()
+        .unwrap_or_else(|_| Value::from(value));
+
+    get(key)?;
+
+    let path = Settings::config_file();
+    let original = if path.exists() {
+        fs::read_to_string(&path)?
+    } else {
+        String::from(Settings::example_config())
+    };
+
+    let mut doc = original.parse::<Document>()?;
+
+    let mut parts: Vec<&str> = key.split('.').collect();
+    let last = parts.pop().unwrap_or(key);
+
+    let mut table = doc.as_table_mut();
+    for part in parts {
+        table = table
+            .entry(part)
+            .or_insert(toml_edit::table())
+            .as_table_mut()
+            .ok_or_else(|| eyre!("{} is not a table in the config file", part))?;
+    }
+
+    table[last] = toml_edit::value(value);
+
+    fs::write(&path, doc.to_string())?;
+
+    // put it back if atuin can no longer read its settings, such as when the
+    // value has the wrong type
+    if let Err(e) = Settings::new() {
+        fs::write(&path, original)?;
+        return Err(eyre!("could not set {}: {}", key, e));
+    }
+
+    Ok(())
+}
+
+impl Cmd {
+    pub fn run(&self) -> Result<()> {
+        match self {
+            Self::Default { write, force } => {
+                if !*write {
+                    print!("{}", Settings::example_config());
+                    return Ok(());
+                }
+
+                let path = Settings::config_file();
+
+                if path.exists() && !*force {
+                    return Err(eyre!(
+                        "{} already exists, pass --force to replace it",
+                        path.display()
+                    ));
+                }
+
+                fs::write(&path, Settings::example_config())?;
+                println!("Wrote {}", path.display());
+
+                Ok(())
+            }
+
+            Self::Get { key } => {
+                println!("{}", get(key)?);
+                Ok(())
+            }
+
+            Self::Set { key, value } => set(key, value),
+        }
+    }
+}
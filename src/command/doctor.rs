@@ -0,0 +1,97 @@
+use std::env;
+use std::path::Path;
+use std::time::Instant;
+
+use eyre::Result;
+
+use atuin_client::api_client;
+use atuin_client::database::Database;
+use atuin_client::settings::Settings;
+
+use super::init::check_hooks;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+fn exists(path: &str) -> &'static str {
+    if Path::new(path).exists() {
+        "found"
+    } else {
+        "missing"
+    }
+}
+
+// Everything that usually gets asked for in a bug report, printed as markdown
+// so that it can be pasted straight into an issue
+pub async fn run(settings: &Settings, db: &(impl Database + Send + Sync)) -> Result<()> {
+    println!("## atuin doctor");
+    println!();
+    println!("- version: {}", VERSION);
+    println!("- os: {} {}", env::consts::OS, env::consts::ARCH);
+    println!(
+        "- shell: {}",
+        env::var("SHELL").unwrap_or_else(|_| String::from("unknown"))
+    );
+
+    println!();
+    println!("### Shell hooks");
+    println!();
+
+    for c in check_hooks(db).await? {
+        if c.ok {
+            println!("- [x] {}", c.name);
+        } else {
+            println!("- [ ] {} ({})", c.name, c.hint);
+        }
+    }
+
+    println!();
+    println!("### Database");
+    println!();
+    println!("- path: {}", settings.db_path);
+
+    match db.history_count().await {
+        Ok(count) => println!("- history: {}", count),
+        Err(e) => println!("- history: failed to count, {}", e),
+    }
+
+    println!();
+    println!("### Settings");
+    println!();
+
+    println!(
+        "- profile: {}",
+        atuin_common::utils::profile().unwrap_or_else(|| String::from("default"))
+    );
+
+    let config = Settings::config_file();
+    println!(
+        "- config: {} ({})",
+        config.display(),
+        exists(&config.to_string_lossy())
+    );
+
+    // the token would let anyone reading the issue into the account
+    let mut parsed = settings.clone();
+    parsed.session_token = String::from("[redacted]");
+
+    println!();
+    println!("```");
+    println!("{:#?}", parsed);
+    println!("```");
+
+    println!();
+    println!("### Sync");
+    println!();
+    println!("- server: {}", settings.sync_address);
+
+    let start = Instant::now();
+    match api_client::ping(settings).await {
+        Ok(_) => println!("- reachable: yes, in {}ms", start.elapsed().as_millis()),
+        Err(e) => println!("- reachable: no, {}", e),
+    }
+
+    println!("- session: {}", exists(settings.session_path.as_str()));
+    println!("- key: {}", exists(settings.key_path.as_str()));
+
+    Ok(())
+}
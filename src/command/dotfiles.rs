@@ -0,0 +1,75 @@
+use std::io::{stdout, Write};
+
+use eyre::{eyre, Result};
+use structopt::StructOpt;
+use tabwriter::TabWriter;
+
+use atuin_client::database::Database;
+use atuin_client::dotfiles::{self, VAR_NAMESPACE};
+use atuin_client::record::Record;
+
+#[derive(StructOpt)]
+pub enum Cmd {
+    #[structopt(about = "manage environment variables, which are exported by atuin init")]
+    Var(VarCmd),
+}
+
+#[derive(StructOpt)]
+pub enum VarCmd {
+    #[structopt(about = "set a variable, replacing any with the same name")]
+    Set { name: String, value: String },
+
+    #[structopt(about = "list your variables", aliases=&["l", "ls"])]
+    List,
+
+    #[structopt(about = "delete a variable", aliases=&["rm"])]
+    Delete { name: String },
+}
+
+impl Cmd {
+    pub async fn run(&self, db: &(impl Database + Send + Sync)) -> Result<()> {
+        match self {
+            Self::Var(VarCmd::Set { name, value }) => {
+                dotfiles::check_var(name.as_str(), value.as_str())?;
+
+                db.save_record(&Record::new(
+                    VAR_NAMESPACE,
+                    name.as_str(),
+                    Some(value.clone()),
+                ))
+                .await?;
+
+                println!("{} set, it will be in new shells", name);
+
+                Ok(())
+            }
+
+            Self::Var(VarCmd::List) => {
+                let mut writer = TabWriter::new(stdout()).padding(2);
+
+                for r in db.records(VAR_NAMESPACE).await? {
+                    writeln!(writer, "{}\t{}", r.key, r.value.unwrap_or_default())?;
+                }
+
+                writer.flush()?;
+
+                Ok(())
+            }
+
+            Self::Var(VarCmd::Delete { name }) => {
+                let existing = db.record(VAR_NAMESPACE, name.as_str()).await?;
+
+                if existing.map_or(true, |r| r.value.is_none()) {
+                    return Err(eyre!("there is no variable named {}", name));
+                }
+
+                db.save_record(&Record::new(VAR_NAMESPACE, name.as_str(), None))
+                    .await?;
+
+                println!("{} deleted, it will be gone from new shells", name);
+
+                Ok(())
+            }
+        }
+    }
+}
@@ -1,15 +1,25 @@
+use std::collections::HashSet;
+use std::convert::TryFrom;
 use std::env;
+use std::fs;
 use std::io::Write;
+use std::process::{Command, Stdio};
 use std::time::Duration;
 
-use eyre::Result;
+use eyre::{eyre, Result};
+use regex::Regex;
+use serde_derive::Serialize;
 use structopt::StructOpt;
 use tabwriter::TabWriter;
 
-use atuin_client::database::Database;
-use atuin_client::history::History;
-use atuin_client::settings::Settings;
-use atuin_client::sync;
+use atuin_client::daemon::{self, Request};
+use atuin_client::database::{current_context, Database};
+use atuin_client::history::{History, HistoryFilter};
+use atuin_client::retention;
+use atuin_client::settings::{FilterMode, SearchMode, Settings};
+use atuin_client::tags::{self, TagRules};
+
+use super::search::Filters;
 
 #[derive(StructOpt)]
 pub enum Cmd {
@@ -27,6 +37,13 @@ pub enum Cmd {
         id: String,
         #[structopt(long, short)]
         exit: i64,
+
+        #[structopt(
+            long,
+            short,
+            about = "how long the command took in nanoseconds, if the shell measured it"
+        )]
+        duration: Option<i64>,
     },
 
     #[structopt(
@@ -34,14 +51,27 @@ pub enum Cmd {
         aliases=&["l", "li", "lis"],
     )]
     List {
-        #[structopt(long, short)]
+        #[structopt(long, short, about = "only list history from the current directory")]
         cwd: bool,
 
-        #[structopt(long, short)]
+        #[structopt(long, short, about = "only list history from the current session")]
         session: bool,
 
+        #[structopt(long, short, about = "only list this many of the most recent items")]
+        limit: Option<usize>,
+
         #[structopt(long, short)]
         human: bool,
+
+        #[structopt(long, about = "terminate each item with a NUL, rather than a newline")]
+        print0: bool,
+
+        #[structopt(
+            long,
+            short,
+            about = "print each item with this format, eg '{time} {command}', or json"
+        )]
+        format: Option<String>,
     },
 
     #[structopt(
@@ -51,48 +81,419 @@ pub enum Cmd {
     Last {
         #[structopt(long, short)]
         human: bool,
+
+        #[structopt(long = "cmd-only", about = "only print the command")]
+        cmd_only: bool,
+
+        #[structopt(
+            long = "exclude-atuin",
+            about = "skip over any atuin commands, to get the last command before them"
+        )]
+        exclude_atuin: bool,
+
+        #[structopt(long, about = "terminate the item with a NUL, rather than a newline")]
+        print0: bool,
+    },
+
+    #[structopt(
+        about="delete history matching the given filters",
+        aliases=&["d", "de", "del"],
+    )]
+    Delete {
+        #[structopt(flatten)]
+        filters: Filters,
+
+        #[structopt(long, short, about = "only delete commands beginning with this")]
+        prefix: Option<String>,
+
+        #[structopt(long, about = "only delete commands matching this regex")]
+        regex: Option<String>,
+
+        #[structopt(
+            long = "dry-run",
+            about = "list what would be deleted, but do not delete it"
+        )]
+        dry_run: bool,
+
+        #[structopt(long, short)]
+        human: bool,
+    },
+
+    #[structopt(
+        about = "collapse repeated runs of the same command, in the same directory, into one"
+    )]
+    Dedup {
+        #[structopt(
+            long = "older-than",
+            about = "only collapse history older than this, eg 30d (default: all history)"
+        )]
+        older_than: Option<String>,
+
+        #[structopt(
+            long = "dry-run",
+            about = "report what would be removed, but do not remove it"
+        )]
+        dry_run: bool,
+    },
+
+    #[structopt(about = "delete history past the limits in the retention settings")]
+    Expire {
+        #[structopt(
+            long = "dry-run",
+            about = "list what would be deleted, but do not delete it"
+        )]
+        dry_run: bool,
+
+        #[structopt(long, short)]
+        human: bool,
+    },
+
+    #[structopt(
+        about="delete history matching the secrets filter and history_filter",
+        aliases=&["p", "pr", "pru", "prun"],
+    )]
+    Prune {
+        #[structopt(
+            long = "dry-run",
+            about = "list what would be deleted, but do not delete it"
+        )]
+        dry_run: bool,
+
+        #[structopt(long, short)]
+        human: bool,
+    },
+
+    #[structopt(about = "tag an item of history, or list its tags when none are given")]
+    Tag {
+        #[structopt(about = "the id of the history, as printed with --format json")]
+        id: String,
+
+        tags: Vec<String>,
+
+        #[structopt(long, short, about = "remove the tags, rather than adding them")]
+        remove: bool,
+    },
+
+    #[structopt(about = "pin a command to the top of the interactive search")]
+    Pin {
+        #[structopt(about = "the id of any run of the command, as printed with --format json")]
+        id: String,
+    },
+
+    #[structopt(about = "unpin a command")]
+    Unpin {
+        #[structopt(about = "the id of any run of the command, as printed with --format json")]
+        id: String,
+    },
+
+    #[structopt(about = "list the pinned commands")]
+    Pinned {
+        #[structopt(long, short)]
+        human: bool,
+
+        #[structopt(long = "cmd-only", about = "only print the command")]
+        cmd_only: bool,
+
+        #[structopt(
+            long,
+            short,
+            about = "print each command with this format, eg '{time} {command}', or json"
+        )]
+        format: Option<String>,
     },
 }
 
+// How each item is written out when listing history
+#[derive(Clone, Copy)]
+pub enum ListMode {
+    Regular,
+    Human,
+    CmdOnly,
+}
+
+impl ListMode {
+    pub const fn from_flags(human: bool, cmd_only: bool) -> Self {
+        if cmd_only {
+            Self::CmdOnly
+        } else if human {
+            Self::Human
+        } else {
+            Self::Regular
+        }
+    }
+}
+
+// imported history often has no duration, and running commands don't have one
+// yet
 #[allow(clippy::clippy::cast_sign_loss)]
-pub fn print_list(h: &[History], human: bool) {
-    let mut writer = TabWriter::new(std::io::stdout()).padding(2);
-
-    let lines = h.iter().map(|h| {
-        if human {
-            let duration = humantime::format_duration(Duration::from_nanos(std::cmp::max(
-                h.duration, 0,
-            ) as u64))
+fn format_duration(h: &History) -> String {
+    if h.duration < 0 {
+        return String::from("-");
+    }
+
+    let duration =
+        humantime::format_duration(Duration::from_nanos(std::cmp::max(h.duration, 0) as u64))
             .to_string();
-            let duration: Vec<&str> = duration.split(' ').collect();
-            let duration = duration[0];
-
-            format!(
-                "{}\t{}\t{}\n",
-                h.timestamp.format("%Y-%m-%d %H:%M:%S"),
-                h.command.trim(),
-                duration,
-            )
-        } else {
-            format!(
-                "{}\t{}\t{}\n",
-                h.timestamp.timestamp_nanos(),
-                h.command.trim(),
-                h.duration
-            )
+
+    duration.split(' ').next().unwrap_or_default().to_string()
+}
+
+// Fill in the {field} placeholders of a user supplied format. Unknown
+// placeholders are left as they are.
+pub fn format_line(h: &History, format: &str, human: bool) -> String {
+    let mut out = String::with_capacity(format.len() + h.command.len());
+    let mut rest = format;
+
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = match rest.find('}') {
+            Some(end) => end,
+            None => break,
+        };
+
+        match &rest[1..end] {
+            "time" if human => out.push_str(&h.timestamp.format("%Y-%m-%d %H:%M:%S").to_string()),
+            "time" => out.push_str(&h.timestamp.timestamp_nanos().to_string()),
+            "duration" if human => out.push_str(&format_duration(h)),
+            "duration" => out.push_str(&h.duration.to_string()),
+            "exit" => out.push_str(&h.exit.to_string()),
+            "directory" => out.push_str(&h.cwd),
+            "host" => out.push_str(&h.hostname),
+            "session" => out.push_str(&h.session),
+            "command" => out.push_str(h.command.trim()),
+            _ => out.push_str(&rest[..=end]),
         }
+
+        rest = &rest[end + 1..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+// History is given most recent first, and printed like the shell's own
+// history, with the most recent last
+pub fn print_order(h: &[History]) -> impl Iterator<Item = &History> {
+    h.iter().rev()
+}
+
+// With print0, each item ends with a NUL rather than a newline, so that
+// commands spanning several lines can be told apart. The reader going away,
+// as with head or fzf, is not an error.
+pub fn print_list(h: &[History], mode: ListMode, format: Option<&str>, print0: bool) -> Result<()> {
+    let end = if print0 { '\0' } else { '\n' };
+
+    let mut lines = print_order(h).map(|h| match (format, mode) {
+        (Some(format), _) => format!(
+            "{}{}",
+            format_line(h, format, matches!(mode, ListMode::Human)),
+            end
+        ),
+        (None, ListMode::CmdOnly) => format!("{}{}", h.command.trim(), end),
+        (None, ListMode::Human) => format!(
+            "{}\t{}\t{}{}",
+            h.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            h.command.trim(),
+            format_duration(h),
+            end
+        ),
+        (None, ListMode::Regular) => format!(
+            "{}\t{}\t{}{}",
+            h.timestamp.timestamp_nanos(),
+            h.command.trim(),
+            h.duration,
+            end
+        ),
     });
 
-    for i in lines.rev() {
-        writer
-            .write_all(i.as_bytes())
-            .expect("failed to write to tab writer");
+    // only the default columns are aligned, anything else is written as is so
+    // that it can be piped elsewhere
+    let stdout = std::io::stdout();
+    let mut writer: Box<dyn Write> = match (format, mode) {
+        (None, ListMode::Regular) | (None, ListMode::Human) if !print0 => {
+            Box::new(TabWriter::new(stdout.lock()).padding(2))
+        }
+        _ => Box::new(stdout.lock()),
+    };
+
+    let written = lines
+        .try_for_each(|i| writer.write_all(i.as_bytes()))
+        .and_then(|_| writer.flush());
+
+    match written {
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        res => Ok(res?),
     }
+}
+
+// A history item as printed by --format json. Other tools build on this, so
+// fields may be added but never renamed or removed.
+#[derive(Serialize)]
+struct JsonHistory<'a> {
+    id: &'a str,
+    timestamp: String,
+    duration: i64,
+    exit: i64,
+    command: &'a str,
+    cwd: &'a str,
+    session: &'a str,
+    hostname: &'a str,
+}
 
-    writer.flush().expect("failed to flush tab writer");
+impl<'a> From<&'a History> for JsonHistory<'a> {
+    fn from(h: &'a History) -> Self {
+        Self {
+            id: &h.id,
+            timestamp: h.timestamp.to_rfc3339(),
+            duration: h.duration,
+            exit: h.exit,
+            command: &h.command,
+            cwd: &h.cwd,
+            session: &h.session,
+            hostname: &h.hostname,
+        }
+    }
+}
+
+// Items are in the same order as print_list would print them, with the most
+// recent last
+fn print_json(h: &[History]) -> Result<()> {
+    let items: Vec<JsonHistory> = print_order(h).map(JsonHistory::from).collect();
+    println!("{}", serde_json::to_string_pretty(&items)?);
+
+    Ok(())
+}
+
+// --format is json, like atuin stats takes, or a format for each line
+pub fn print_formatted(
+    h: &[History],
+    mode: ListMode,
+    format: Option<&str>,
+    print0: bool,
+) -> Result<()> {
+    if format == Some("json") {
+        return print_json(h);
+    }
+
+    print_list(h, mode, format, print0)
+}
+
+// The ids of every run of a command in a directory, apart from the most recent,
+// out of history that is already most recent first
+fn duplicates(history: &[History]) -> Vec<String> {
+    let mut seen = HashSet::new();
+
+    history
+        .iter()
+        .filter(|h| !seen.insert((h.command.as_str(), h.cwd.as_str())))
+        .map(|h| h.id.clone())
+        .collect()
+}
+
+#[allow(clippy::clippy::cast_precision_loss)]
+fn format_size(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", size, units[unit])
+}
+
+// the database, along with its write ahead log
+fn db_size(settings: &Settings) -> u64 {
+    ["", "-wal"]
+        .iter()
+        .filter_map(|suffix| fs::metadata(format!("{}{}", settings.db_path, suffix)).ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+fn is_atuin(command: &str) -> bool {
+    command.split_whitespace().next() == Some("atuin")
+}
+
+// The most recent command to have finished. Running `atuin history last` in a
+// script after other atuin commands would otherwise just find those.
+async fn last_command(db: &(impl Database + Send + Sync), exclude_atuin: bool) -> Result<History> {
+    if !exclude_atuin {
+        return db.last().await;
+    }
+
+    let mut timestamp = chrono::Utc::now();
+
+    loop {
+        let page = db.before(timestamp, 100).await?;
+
+        timestamp = match page.last() {
+            Some(h) => h.timestamp,
+            None => return Err(eyre!("no history found")),
+        };
+
+        if let Some(h) = page
+            .into_iter()
+            .find(|h| h.duration >= 0 && !is_atuin(&h.command))
+        {
+            return Ok(h);
+        }
+    }
 }
 
 impl Cmd {
+    // The shell hooks hand off to the daemon if it is running, so that they
+    // don't need to open the database themselves. Returns false if the daemon
+    // is not running, or this isn't a hook
+    pub async fn run_daemon(&self, settings: &Settings) -> Result<bool> {
+        let request = match self {
+            Self::Start { command: words } => {
+                let command = words.join(" ");
+                let cwd = env::current_dir()?.display().to_string();
+
+                let h = History::new(chrono::Utc::now(), command, cwd, -1, -1, None, None);
+
+                if !h.should_save(settings)? {
+                    debug!("not saving history - filtered");
+                    return Ok(true);
+                }
+
+                Request::Start { history: h }
+            }
+
+            Self::End { id, exit, duration } => {
+                if id.trim() == "" {
+                    return Ok(true);
+                }
+
+                Request::End {
+                    id: id.clone(),
+                    exit: *exit,
+                    duration: *duration,
+                }
+            }
+
+            _ => return Ok(false),
+        };
+
+        let mut client = match daemon::Client::connect().await {
+            Some(c) => c,
+            None => return Ok(false),
+        };
+
+        client.request(&request).await?;
+
+        if let Request::Start { history } = request {
+            println!("{}", history.id);
+        }
+
+        Ok(true)
+    }
+
     pub async fn run(
         &self,
         settings: &Settings,
@@ -105,14 +506,30 @@ impl Cmd {
 
                 let h = History::new(chrono::Utc::now(), command, cwd, -1, -1, None, None);
 
+                // print nothing, so that the shell has no ID to end
+                if !h.should_save(settings)? {
+                    debug!("not saving history - filtered");
+                    return Ok(());
+                }
+
                 // print the ID
                 // we use this as the key for calling end
                 println!("{}", h.id);
                 db.save(&h).await?;
+
+                // the history is saved, and the shell has its id, so failing
+                // to tag it is no reason to fail the hook
+                let tags = TagRules::new(settings).tags(h.command.as_str());
+                if !tags.is_empty() {
+                    if let Err(e) = db.add_tags(h.id.as_str(), &tags).await {
+                        warn!("failed to tag history {}: {}", h.id, e);
+                    }
+                }
+
                 Ok(())
             }
 
-            Self::End { id, exit } => {
+            Self::End { id, exit, duration } => {
                 if id.trim() == "" {
                     return Ok(());
                 }
@@ -126,14 +543,24 @@ impl Cmd {
                     return Ok(());
                 }
 
-                h.exit = *exit;
-                h.duration = chrono::Utc::now().timestamp_nanos() - h.timestamp.timestamp_nanos();
+                h.end(*exit, *duration);
 
                 db.update(&h).await?;
 
                 if settings.should_sync()? {
                     debug!("running periodic background sync");
-                    sync::sync(settings, false, db).await?;
+
+                    // sync in another process, so that the shell is not kept
+                    // waiting on the network. note the attempt, so that more
+                    // commands ending while it runs don't start more syncs
+                    Settings::save_sync_attempt()?;
+
+                    Command::new(env::current_exe()?)
+                        .arg("sync")
+                        .stdin(Stdio::null())
+                        .stdout(Stdio::null())
+                        .stderr(Stdio::null())
+                        .spawn()?;
                 } else {
                     debug!("sync disabled! not syncing");
                 }
@@ -144,38 +571,314 @@ impl Cmd {
             Self::List {
                 session,
                 cwd,
+                limit,
                 human,
+                print0,
+                format,
             } => {
-                let params = (session, cwd);
-                let cwd = env::current_dir()?.display().to_string();
-                let session = env::var("ATUIN_SESSION")?;
+                // most recent first, so that the limit keeps the latest
+                let history = if *session || *cwd {
+                    let context = current_context();
+                    let mode = if *session {
+                        FilterMode::Session
+                    } else {
+                        FilterMode::Directory
+                    };
+
+                    // the database filters by one of these at a time. with
+                    // both, the directory is checked here, and so the limit too
+                    let both = *session && *cwd;
+                    let db_limit = if both {
+                        None
+                    } else {
+                        limit.map(i64::try_from).transpose()?
+                    };
+
+                    db.search(db_limit, SearchMode::Prefix, mode, &context, "", false)
+                        .await?
+                        .into_iter()
+                        .filter(|h| !both || h.cwd == context.cwd)
+                        .take(limit.unwrap_or(usize::MAX))
+                        .collect()
+                } else {
+                    db.list(*limit, false).await?
+                };
 
-                let query_session = format!("select * from history where session = {};", session);
+                print_formatted(
+                    &history,
+                    ListMode::from_flags(*human, false),
+                    format.as_deref(),
+                    *print0,
+                )
+            }
 
-                let query_dir = format!("select * from history where cwd = {};", cwd);
-                let query_session_dir = format!(
-                    "select * from history where cwd = {} and session = {};",
-                    cwd, session
-                );
+            Self::Last {
+                human,
+                cmd_only,
+                exclude_atuin,
+                print0,
+            } => {
+                let last = last_command(db, *exclude_atuin).await?;
+                print_list(
+                    &[last],
+                    ListMode::from_flags(*human, *cmd_only),
+                    None,
+                    *print0,
+                )
+            }
+
+            Self::Delete {
+                filters,
+                prefix,
+                regex,
+                dry_run,
+                human,
+            } => {
+                if filters.is_empty() && prefix.is_none() && regex.is_none() {
+                    return Err(eyre!(
+                        "refusing to delete all history, please specify at least one filter"
+                    ));
+                }
 
-                let history = match params {
-                    (false, false) => db.list(None, false).await?,
-                    (true, false) => db.query_history(query_session.as_str()).await?,
-                    (false, true) => db.query_history(query_dir.as_str()).await?,
-                    (true, true) => db.query_history(query_session_dir.as_str()).await?,
+                let regex = regex.as_deref().map(Regex::new).transpose()?;
+
+                let history = db.list(None, false).await?;
+                let history: Vec<History> = filters
+                    .apply(&history, settings, &*db)
+                    .await?
+                    .into_iter()
+                    .filter(|h| prefix.as_ref().map_or(true, |p| h.command.starts_with(p)))
+                    .filter(|h| regex.as_ref().map_or(true, |r| r.is_match(&h.command)))
+                    .collect();
+
+                if *dry_run {
+                    print_list(&history, ListMode::from_flags(*human, false), None, false)?;
+                    println!("Would delete {} items from history", history.len());
+
+                    return Ok(());
+                }
+
+                let ids: Vec<String> = history.iter().map(|h| h.id.clone()).collect();
+                db.delete(&ids).await?;
+                println!("Deleted {} items from history", history.len());
+
+                Ok(())
+            }
+
+            Self::Dedup {
+                older_than,
+                dry_run,
+            } => {
+                let cutoff = match older_than {
+                    Some(d) => {
+                        let d = humantime::parse_duration(d)
+                            .map_err(|e| eyre!("invalid duration {:?}: {}", d, e))?;
+                        chrono::Utc::now() - chrono::Duration::from_std(d)?
+                    }
+                    None => chrono::Utc::now(),
                 };
 
-                print_list(&history, *human);
+                let history: Vec<History> = db
+                    .list(None, false)
+                    .await?
+                    .into_iter()
+                    .filter(|h| h.timestamp < cutoff)
+                    .collect();
+
+                let ids = duplicates(&history);
+
+                if *dry_run {
+                    println!(
+                        "Would remove {} duplicates, out of {} items of history",
+                        ids.len(),
+                        history.len()
+                    );
+
+                    return Ok(());
+                }
+
+                let before = db_size(settings);
+
+                db.delete(&ids).await?;
+                db.compact().await?;
+
+                let after = db_size(settings);
+
+                println!(
+                    "Removed {} duplicates, and reclaimed {}",
+                    ids.len(),
+                    format_size(before.saturating_sub(after))
+                );
+
+                Ok(())
+            }
+
+            Self::Expire { dry_run, human } => {
+                if !retention::enabled(&settings.retention) {
+                    return Err(eyre!(
+                        "no retention limits are set, see retention in the config"
+                    ));
+                }
+
+                if *dry_run {
+                    let history = retention::expired(&settings.retention, &*db).await?;
+
+                    print_list(&history, ListMode::from_flags(*human, false), None, false)?;
+                    println!("Would expire {} items from history", history.len());
+
+                    return Ok(());
+                }
+
+                let expired = retention::expire(settings, db).await?;
+                println!("Expired {} items from history", expired);
 
                 Ok(())
             }
 
-            Self::Last { human } => {
-                let last = db.last().await?;
-                print_list(&[last], *human);
+            Self::Prune { dry_run, human } => {
+                let filter = HistoryFilter::new(settings)?;
+
+                let history = db.list(None, false).await?;
+                let history: Vec<History> =
+                    history.into_iter().filter(|h| filter.is_match(h)).collect();
+
+                if *dry_run {
+                    print_list(&history, ListMode::from_flags(*human, false), None, false)?;
+                    println!("Would prune {} items from history", history.len());
+
+                    return Ok(());
+                }
+
+                let ids: Vec<String> = history.iter().map(|h| h.id.clone()).collect();
+                db.delete(&ids).await?;
+                println!("Pruned {} items from history", history.len());
 
                 Ok(())
             }
+
+            Self::Tag { id, tags, remove } => {
+                db.load(id.as_str())
+                    .await
+                    .map_err(|_| eyre!("no history with the id {}", id))?;
+
+                if tags.is_empty() {
+                    for tag in db.tags(id.as_str()).await? {
+                        println!("{}", tag);
+                    }
+
+                    return Ok(());
+                }
+
+                tags::check(tags)?;
+
+                if *remove {
+                    db.remove_tags(id.as_str(), tags).await?;
+                } else {
+                    db.add_tags(id.as_str(), tags).await?;
+                }
+
+                Ok(())
+            }
+
+            Self::Pin { id } | Self::Unpin { id } => {
+                let h = db
+                    .load(id.as_str())
+                    .await
+                    .map_err(|_| eyre!("no history with the id {}", id))?;
+
+                if let Self::Pin { .. } = self {
+                    db.pin(h.command.as_str()).await?;
+                } else {
+                    db.unpin(h.command.as_str()).await?;
+                }
+
+                Ok(())
+            }
+
+            Self::Pinned {
+                human,
+                cmd_only,
+                format,
+            } => {
+                let pinned = db.pinned().await?;
+
+                print_formatted(
+                    &pinned,
+                    ListMode::from_flags(*human, *cmd_only),
+                    format.as_deref(),
+                    false,
+                )
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use chrono::TimeZone;
+
+    use atuin_client::history::History;
+
+    use super::{duplicates, format_line, format_size};
+
+    #[test]
+    fn test_format_line() {
+        let h = History::new(
+            chrono::Utc.ymd(2021, 4, 1).and_hms(12, 0, 0),
+            "cargo build\n".into(),
+            "/home/{command}".into(),
+            0,
+            1_500_000_000,
+            Some("session".into()),
+            Some("host:user".into()),
+        );
+
+        assert_eq!(
+            format_line(&h, "{time} {duration} {exit} {command}", true),
+            "2021-04-01 12:00:00 1s 0 cargo build"
+        );
+        assert_eq!(
+            format_line(&h, "{exit}\t{duration}", false),
+            "0\t1500000000"
+        );
+        assert_eq!(
+            format_line(&h, "{directory} {host} {nope} {", false),
+            "/home/{command} host:user {nope} {"
+        );
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let h = |command: &str, cwd: &str| {
+            History::new(
+                chrono::Utc::now(),
+                command.into(),
+                cwd.into(),
+                0,
+                0,
+                None,
+                None,
+            )
+        };
+
+        let history = vec![
+            h("ls", "/home"),
+            h("ls", "/tmp"),
+            h("ls", "/home"),
+            h("cargo build", "/home"),
+            h("ls", "/home"),
+        ];
+
+        assert_eq!(
+            duplicates(&history),
+            vec![history[2].id.clone(), history[4].id.clone()]
+        );
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512.0 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(10 * 1024 * 1024), "10.0 MiB");
+    }
+}
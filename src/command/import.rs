@@ -6,8 +6,9 @@ use eyre::{eyre, Result};
 use structopt::StructOpt;
 
 use atuin_client::database::Database;
-use atuin_client::history::History;
-use atuin_client::import::{bash::Bash, zsh::Zsh};
+use atuin_client::history::{History, HistoryFilter};
+use atuin_client::import::{bash::Bash, fish::Fish, resh::Resh, zsh::Zsh, zsh_histdb::ZshHistDb};
+use atuin_client::settings::Settings;
 use indicatif::ProgressBar;
 
 #[derive(StructOpt)]
@@ -24,15 +25,37 @@ pub enum Cmd {
     )]
     Zsh,
 
+    #[structopt(
+        about = "import history from the zsh-histdb sqlite database",
+        name = "zsh-histdb"
+    )]
+    ZshHistDb,
+
     #[structopt(
         about="import history from the bash history file",
         aliases=&["b", "ba", "bas"],
     )]
     Bash,
+
+    #[structopt(
+        about="import history from the fish history file",
+        aliases=&["f", "fi", "fis"],
+    )]
+    Fish,
+
+    #[structopt(
+        about="import history from the resh history file",
+        aliases=&["r", "re", "res"],
+    )]
+    Resh,
 }
 
 impl Cmd {
-    pub async fn run(&self, db: &mut (impl Database + Send + Sync)) -> Result<()> {
+    pub async fn run(
+        &self,
+        settings: &Settings,
+        db: &mut (impl Database + Send + Sync),
+    ) -> Result<()> {
         println!("        Atuin         ");
         println!("======================");
         println!("          \u{1f30d}          ");
@@ -47,20 +70,46 @@ impl Cmd {
 
                 if shell.ends_with("/zsh") {
                     println!("Detected ZSH");
-                    import_zsh(db).await
+                    import_zsh(settings, db).await
+                } else if shell.ends_with("/bash") {
+                    println!("Detected Bash");
+                    import_bash(settings, db).await
+                } else if shell.ends_with("/fish") {
+                    println!("Detected Fish");
+                    import_fish(settings, db).await
                 } else {
-                    println!("cannot import {} history", shell);
-                    Ok(())
+                    // we don't know this shell, but there may still be a
+                    // history file we know how to read lying around
+                    let user_dirs = UserDirs::new().unwrap();
+                    let home_dir = user_dirs.home_dir();
+
+                    if home_dir.join(".zsh_history").exists() || home_dir.join(".zhistory").exists()
+                    {
+                        println!("Could not detect {}, but found a zsh history file", shell);
+                        import_zsh(settings, db).await
+                    } else if home_dir.join(".bash_history").exists() {
+                        println!("Could not detect {}, but found a bash history file", shell);
+                        import_bash(settings, db).await
+                    } else if fish_histpath().exists() {
+                        println!("Could not detect {}, but found a fish history file", shell);
+                        import_fish(settings, db).await
+                    } else {
+                        println!("cannot import {} history", shell);
+                        Ok(())
+                    }
                 }
             }
 
-            Self::Zsh => import_zsh(db).await,
-            Self::Bash => import_bash(db).await,
+            Self::Zsh => import_zsh(settings, db).await,
+            Self::ZshHistDb => import_zsh_histdb(settings, db).await,
+            Self::Bash => import_bash(settings, db).await,
+            Self::Fish => import_fish(settings, db).await,
+            Self::Resh => import_resh(settings, db).await,
         }
     }
 }
 
-async fn import_zsh(db: &mut (impl Database + Send + Sync)) -> Result<()> {
+async fn import_zsh(settings: &Settings, db: &mut (impl Database + Send + Sync)) -> Result<()> {
     // oh-my-zsh sets HISTFILE=~/.zhistory
     // zsh has no default value for this var, but uses ~/.zhistory.
     // we could maybe be smarter about this in the future :)
@@ -97,43 +146,39 @@ async fn import_zsh(db: &mut (impl Database + Send + Sync)) -> Result<()> {
     };
 
     let zsh = Zsh::new(histpath)?;
+    let loc = zsh.loc;
 
-    let progress = ProgressBar::new(zsh.loc);
-
-    let buf_size = 100;
-    let mut buf = Vec::<History>::with_capacity(buf_size);
-
-    for i in zsh
-        .filter_map(Result::ok)
-        .filter(|x| !x.command.trim().is_empty())
-    {
-        buf.push(i);
-
-        if buf.len() == buf_size {
-            db.save_bulk(&buf).await?;
-            progress.inc(buf.len() as u64);
+    import(settings, db, zsh, loc).await
+}
 
-            buf.clear();
-        }
+async fn import_zsh_histdb(
+    settings: &Settings,
+    db: &mut (impl Database + Send + Sync),
+) -> Result<()> {
+    // histdb stores its database in ~/.histdb by default, unless HISTDB_FILE
+    // is set
+    let histpath = env::var("HISTDB_FILE").map_or_else(
+        |_| {
+            let user_dirs = UserDirs::new().unwrap();
+            user_dirs.home_dir().join(".histdb").join("zsh-history.db")
+        },
+        PathBuf::from,
+    );
+
+    if !histpath.exists() {
+        return Err(eyre!(
+            "Could not find histdb database {:?}. try setting $HISTDB_FILE",
+            histpath
+        ));
     }
 
-    if !buf.is_empty() {
-        db.save_bulk(&buf).await?;
-        progress.inc(buf.len() as u64);
-    }
+    let histdb = ZshHistDb::new(histpath).await?;
+    let loc = histdb.loc;
 
-    progress.finish();
-    println!("Import complete!");
-
-    Ok(())
+    import(settings, db, histdb, loc).await
 }
 
-// TODO: don't just copy paste this lol
-async fn import_bash(db: &mut (impl Database + Send + Sync)) -> Result<()> {
-    // oh-my-zsh sets HISTFILE=~/.zhistory
-    // zsh has no default value for this var, but uses ~/.zhistory.
-    // we could maybe be smarter about this in the future :)
-
+async fn import_bash(settings: &Settings, db: &mut (impl Database + Send + Sync)) -> Result<()> {
     let histpath = env::var("HISTFILE");
 
     let histpath = if let Ok(p) = histpath {
@@ -155,16 +200,76 @@ async fn import_bash(db: &mut (impl Database + Send + Sync)) -> Result<()> {
     };
 
     let bash = Bash::new(histpath)?;
+    let loc = bash.loc;
 
-    let progress = ProgressBar::new(bash.loc);
+    import(settings, db, bash, loc).await
+}
+
+fn fish_histpath() -> PathBuf {
+    // fish keeps history in $XDG_DATA_HOME/fish, and names the file after the
+    // $fish_history session, which is "fish" by default
+    let data_dir = env::var("XDG_DATA_HOME").map_or_else(
+        |_| {
+            let user_dirs = UserDirs::new().unwrap();
+            user_dirs.home_dir().join(".local").join("share")
+        },
+        PathBuf::from,
+    );
+
+    let session = env::var("fish_history").unwrap_or_else(|_| String::from("fish"));
+    data_dir.join("fish").join(format!("{}_history", session))
+}
+
+async fn import_fish(settings: &Settings, db: &mut (impl Database + Send + Sync)) -> Result<()> {
+    let histpath = fish_histpath();
+
+    if !histpath.exists() {
+        return Err(eyre!("Could not find history file {:?}", histpath));
+    }
+
+    let fish = Fish::new(histpath)?;
+    let loc = fish.loc;
+
+    import(settings, db, fish, loc).await
+}
+
+async fn import_resh(settings: &Settings, db: &mut (impl Database + Send + Sync)) -> Result<()> {
+    let user_dirs = UserDirs::new().unwrap();
+    let histpath = user_dirs.home_dir().join(".resh_history.json");
+
+    if !histpath.exists() {
+        return Err(eyre!("Could not find history file {:?}", histpath));
+    }
+
+    let resh = Resh::new(histpath)?;
+    let loc = resh.loc;
+
+    import(settings, db, resh, loc).await
+}
+
+async fn import(
+    settings: &Settings,
+    db: &mut (impl Database + Send + Sync),
+    history: impl Iterator<Item = Result<History>>,
+    loc: u64,
+) -> Result<()> {
+    let progress = ProgressBar::new(loc);
 
     let buf_size = 100;
     let mut buf = Vec::<History>::with_capacity(buf_size);
 
-    for i in bash
+    let filter = HistoryFilter::new(settings)?;
+    let mut filtered = 0;
+
+    for i in history
         .filter_map(Result::ok)
         .filter(|x| !x.command.trim().is_empty())
     {
+        if filter.is_match(&i) {
+            filtered += 1;
+            continue;
+        }
+
         buf.push(i);
 
         if buf.len() == buf_size {
@@ -181,6 +286,11 @@ async fn import_bash(db: &mut (impl Database + Send + Sync)) -> Result<()> {
     }
 
     progress.finish();
+
+    if filtered > 0 {
+        println!("Skipped {} commands matching the history filters", filtered);
+    }
+
     println!("Import complete!");
 
     Ok(())
@@ -1,30 +1,421 @@
-use eyre::Result;
+use std::env;
+use std::str::FromStr;
+
+use eyre::{eyre, Result};
 use structopt::StructOpt;
 
+use atuin_client::alias;
+use atuin_client::database::Database;
+use atuin_client::dotfiles;
+
 #[derive(StructOpt)]
-pub enum Cmd {
-    #[structopt(about = "zsh setup")]
+pub struct Cmd {
+    #[structopt(about = "the shell to set up: zsh, bash, fish, nu or powershell")]
+    shell: Shell,
+
+    #[structopt(
+        long = "disable-up-arrow",
+        about = "do not bind the up arrow to search"
+    )]
+    disable_up_arrow: bool,
+
+    #[structopt(long = "disable-ctrl-r", about = "do not bind ctrl-r to search")]
+    disable_ctrl_r: bool,
+
+    #[structopt(
+        long,
+        about = "check that the hooks are installed and running in this shell, rather than printing them"
+    )]
+    check: bool,
+}
+
+// The result of checking one part of the shell setup
+pub struct Check {
+    pub name: &'static str,
+    pub ok: bool,
+    pub hint: String,
+}
+
+// Whether the hooks are running in the shell that started us. They pass
+// everything along in the environment, and the preexec hook has already
+// recorded this very command by the time it runs.
+pub async fn check_hooks(db: &(impl Database + Send + Sync)) -> Result<Vec<Check>> {
+    let session = env::var("ATUIN_SESSION").ok();
+
+    let started = match env::var("ATUIN_HISTORY_ID") {
+        Ok(id) => db.load(id.as_str()).await.is_ok(),
+        Err(_) => false,
+    };
+
+    let ended = match &session {
+        Some(session) => db
+            .before(chrono::Utc::now(), 100)
+            .await?
+            .iter()
+            .any(|h| h.session == *session && h.duration >= 0),
+        None => false,
+    };
+
+    Ok(vec![
+        Check {
+            name: "ATUIN_SESSION is set",
+            ok: session.is_some(),
+            hint: String::from("the init script has not been loaded by this shell"),
+        },
+        Check {
+            name: "commands are being recorded",
+            ok: started,
+            hint: String::from("the preexec hook did not run for this command"),
+        },
+        Check {
+            name: "commands are being finished",
+            ok: ended,
+            hint: String::from(
+                "no command in this session has an exit code, run another command and check again",
+            ),
+        },
+    ])
+}
+
+pub enum Shell {
     Zsh,
-    #[structopt(about = "bash setup")]
     Bash,
+    Fish,
+    Nu,
+    Powershell,
+}
+
+impl FromStr for Shell {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "zsh" => Ok(Self::Zsh),
+            "bash" => Ok(Self::Bash),
+            "fish" => Ok(Self::Fish),
+            "nu" | "nushell" => Ok(Self::Nu),
+            "powershell" | "pwsh" => Ok(Self::Powershell),
+            _ => Err(eyre!(
+                "unsupported shell {}, expected zsh, bash, fish, nu or powershell",
+                s
+            )),
+        }
+    }
+}
+
+const ZSH_CTRL_R: &str = "bindkey '^r' _atuin_search_widget";
+
+// depends on terminal mode
+const ZSH_UP_ARROW: &str = "bindkey '^[[A' _atuin_search_widget
+bindkey '^[OA' _atuin_search_widget";
+
+const BASH_CTRL_R: &str = r#"bind -x '"\C-r": __atuin_history'"#;
+
+const BASH_UP_ARROW: &str = r#"bind -x '"\e[A": __atuin_history'
+bind -x '"\eOA": __atuin_history'"#;
+
+const FISH_CTRL_R: &str = r"bind \cr _atuin_search
+
+if bind -M insert > /dev/null 2>&1
+	bind -M insert \cr _atuin_search
+end";
+
+const FISH_UP_ARROW: &str = r"bind -k up _atuin_search
+bind \eOA _atuin_search
+bind \e\[A _atuin_search
+
+if bind -M insert > /dev/null 2>&1
+	bind -M insert -k up _atuin_search
+	bind -M insert \eOA _atuin_search
+	bind -M insert \e\[A _atuin_search
+end";
+
+const NU_CTRL_R: &str = "$env.config = (
+    $env.config | upsert keybindings (
+        $env.config.keybindings
+        | append {
+            name: atuin
+            modifier: control
+            keycode: char_r
+            mode: [emacs, vi_normal, vi_insert]
+            event: { send: executehostcommand cmd: (_atuin_search_cmd) }
+        }
+    )
+)";
+
+const NU_UP_ARROW: &str = "$env.config = (
+    $env.config | upsert keybindings (
+        $env.config.keybindings
+        | append {
+            name: atuin
+            modifier: none
+            keycode: up
+            mode: [emacs, vi_normal, vi_insert]
+            event: {
+                until: [
+                    { send: menuup }
+                    { send: executehostcommand cmd: (_atuin_search_cmd) }
+                ]
+            }
+        }
+    )
+)";
+
+const POWERSHELL_CTRL_R: &str = r#"Set-PSReadLineKeyHandler -Chord "Ctrl+r" -BriefDescription "Search history with atuin" -ScriptBlock { Invoke-AtuinSearch }"#;
+
+const POWERSHELL_UP_ARROW: &str = r#"Set-PSReadLineKeyHandler -Chord "UpArrow" -BriefDescription "Search history with atuin" -ScriptBlock { Invoke-AtuinSearch }"#;
+
+// Everything inside single quotes is literal to zsh and bash, apart from the
+// quote itself
+fn posix_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+// fish takes \' and \\ as escapes inside single quotes
+fn fish_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\\', r"\\").replace('\'', r"\'"))
 }
 
-fn init_zsh() {
-    let full = include_str!("../shell/atuin.zsh");
-    println!("{}", full);
+// powershell takes the typographic single quotes as quotes too, and any of
+// them is escaped by doubling it
+fn powershell_quote(s: &str) -> String {
+    let mut quoted = String::from("'");
+
+    for c in s.chars() {
+        if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}') {
+            quoted.push(c);
+        }
+        quoted.push(c);
+    }
+
+    quoted.push('\'');
+    quoted
 }
 
-fn init_bash() {
-    let full = include_str!("../shell/atuin.bash");
-    println!("{}", full);
+// nu reads an alias as code, and has no way to run a string as code, so only
+// plain words are safe to write out
+fn nu_plain(s: &str) -> bool {
+    s.chars().all(|c| {
+        c.is_alphanumeric()
+            || matches!(
+                c,
+                ' ' | '-' | '_' | '.' | '/' | '=' | ':' | ',' | '+' | '@' | '%' | '~'
+            )
+    })
+}
+
+// None when the alias can't be written out safely for the shell. powershell
+// aliases can't take arguments, so it's a function there, that runs the
+// quoted command as a script block
+fn alias_line(shell: &Shell, name: &str, value: &str) -> Option<String> {
+    match shell {
+        Shell::Zsh | Shell::Bash => Some(format!("alias {}={}", name, posix_quote(value))),
+        Shell::Fish => Some(format!("alias {} {}", name, fish_quote(value))),
+        Shell::Nu if nu_plain(value) => Some(format!("alias {} = {}", name, value)),
+        Shell::Nu => None,
+        Shell::Powershell => Some(format!(
+            "function {} {{ & ([scriptblock]::Create({})) @args }}",
+            name,
+            powershell_quote(format!("{} @args", value).as_str())
+        )),
+    }
+}
+
+fn var_line(shell: &Shell, name: &str, value: &str) -> String {
+    match shell {
+        Shell::Zsh | Shell::Bash => format!("export {}={}", name, posix_quote(value)),
+        Shell::Fish => format!("set -gx {} {}", name, fish_quote(value)),
+        Shell::Nu => format!(
+            "$env.{} = \"{}\"",
+            name,
+            value.replace('\\', r"\\").replace('"', "\\\"")
+        ),
+        Shell::Powershell => format!("$env:{} = {}", name, powershell_quote(value)),
+    }
 }
 
 impl Cmd {
-    pub fn run(&self) -> Result<()> {
-        match self {
-            Self::Zsh => init_zsh(),
-            Self::Bash => init_bash(),
+    pub async fn run(&self, db: &(impl Database + Send + Sync)) -> Result<()> {
+        if self.check {
+            return self.check(db).await;
+        }
+
+        // the scripts only set up the hooks and search widget, the key
+        // bindings are added here so that they can be left out
+        let (script, ctrl_r, up_arrow) = match self.shell {
+            Shell::Zsh => (include_str!("../shell/atuin.zsh"), ZSH_CTRL_R, ZSH_UP_ARROW),
+            Shell::Bash => (
+                include_str!("../shell/atuin.bash"),
+                BASH_CTRL_R,
+                BASH_UP_ARROW,
+            ),
+            Shell::Fish => (
+                include_str!("../shell/atuin.fish"),
+                FISH_CTRL_R,
+                FISH_UP_ARROW,
+            ),
+            Shell::Nu => (include_str!("../shell/atuin.nu"), NU_CTRL_R, NU_UP_ARROW),
+            Shell::Powershell => (
+                include_str!("../shell/atuin.ps1"),
+                POWERSHELL_CTRL_R,
+                POWERSHELL_UP_ARROW,
+            ),
+        };
+
+        // the hooks run atuin again, and need to use the same profile
+        if let Some(profile) = atuin_common::utils::profile() {
+            match self.shell {
+                Shell::Zsh | Shell::Bash => println!("export ATUIN_PROFILE={}\n", profile),
+                Shell::Fish => println!("set -gx ATUIN_PROFILE {}\n", profile),
+                Shell::Nu => println!("$env.ATUIN_PROFILE = \"{}\"\n", profile),
+                Shell::Powershell => println!("$env:ATUIN_PROFILE = \"{}\"\n", profile),
+            }
+        }
+
+        println!("{}", script);
+
+        // before the aliases, which might use them
+        let vars = db.records(dotfiles::VAR_NAMESPACE).await?;
+
+        for v in &vars {
+            if let Some(value) = &v.value {
+                if let Err(e) = dotfiles::check_var(v.key.as_str(), value.as_str()) {
+                    warn!("skipping variable: {}", e);
+                    continue;
+                }
+
+                println!("{}", var_line(&self.shell, v.key.as_str(), value.as_str()));
+            }
+        }
+
+        let aliases = db.records(alias::NAMESPACE).await?;
+
+        for a in &aliases {
+            if let Some(value) = &a.value {
+                // synced aliases were only checked on the machine that set
+                // them, and the name is written out unquoted
+                if let Err(e) = alias::check(a.key.as_str(), value.as_str()) {
+                    warn!("skipping alias: {}", e);
+                    continue;
+                }
+
+                match alias_line(&self.shell, a.key.as_str(), value.as_str()) {
+                    Some(line) => println!("{}", line),
+                    None => warn!(
+                        "skipping alias {}: nu aliases can only be plain words",
+                        a.key
+                    ),
+                }
+            }
+        }
+
+        if !vars.is_empty() || !aliases.is_empty() {
+            println!();
+        }
+
+        if matches!(self.shell, Shell::Nu) && !(self.disable_ctrl_r && self.disable_up_arrow) {
+            println!("$env.config = ($env.config | default [] keybindings)\n");
         }
+
+        if !self.disable_ctrl_r {
+            println!("{}\n", ctrl_r);
+        }
+
+        if !self.disable_up_arrow {
+            println!("{}\n", up_arrow);
+        }
+
         Ok(())
     }
+
+    async fn check(&self, db: &(impl Database + Send + Sync)) -> Result<()> {
+        let checks = check_hooks(db).await?;
+
+        for c in &checks {
+            if c.ok {
+                println!("[ok]   {}", c.name);
+            } else {
+                println!("[fail] {}: {}", c.name, c.hint);
+            }
+        }
+
+        if checks.iter().all(|c| c.ok) {
+            return Ok(());
+        }
+
+        let setup = match self.shell {
+            Shell::Zsh => r#"eval "$(atuin init zsh)" in ~/.zshrc"#,
+            Shell::Bash => {
+                r#"bash-preexec to be loaded, then eval "$(atuin init bash)" in ~/.bashrc"#
+            }
+            Shell::Fish => "atuin init fish | source in ~/.config/fish/config.fish",
+            Shell::Nu => "the output of atuin init nu to be sourced from config.nu",
+            Shell::Powershell => {
+                "atuin init powershell | Out-String | Invoke-Expression in $PROFILE"
+            }
+        };
+
+        println!();
+        println!("The hooks need {}", setup);
+
+        Err(eyre!("the shell hooks are not working"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{alias_line, var_line, Shell};
+
+    #[test]
+    fn test_alias_line() {
+        assert_eq!(
+            alias_line(&Shell::Zsh, "ll", "ls -l").unwrap(),
+            "alias ll='ls -l'"
+        );
+        assert_eq!(
+            alias_line(&Shell::Bash, "hi", "echo 'hi there'").unwrap(),
+            r"alias hi='echo '\''hi there'\'''"
+        );
+        assert_eq!(
+            alias_line(&Shell::Fish, "hi", r"echo 'hi\there'").unwrap(),
+            r"alias hi 'echo \'hi\\there\''"
+        );
+        assert_eq!(
+            alias_line(&Shell::Nu, "ll", "ls -l").unwrap(),
+            "alias ll = ls -l"
+        );
+        assert_eq!(alias_line(&Shell::Nu, "x", "ls; rm -r ~"), None);
+        assert_eq!(
+            alias_line(&Shell::Powershell, "gs", "git status").unwrap(),
+            "function gs { & ([scriptblock]::Create('git status @args')) @args }"
+        );
+        assert_eq!(
+            alias_line(&Shell::Powershell, "x", "x }; Remove-Item ~ #").unwrap(),
+            "function x { & ([scriptblock]::Create('x }; Remove-Item ~ # @args')) @args }"
+        );
+        assert_eq!(
+            alias_line(&Shell::Powershell, "x", "echo \u{2019}; Remove-Item ~").unwrap(),
+            "function x { & ([scriptblock]::Create('echo \u{2019}\u{2019}; Remove-Item ~ @args')) @args }"
+        );
+    }
+
+    #[test]
+    fn test_var_line() {
+        assert_eq!(
+            var_line(&Shell::Zsh, "EDITOR", "nvim"),
+            "export EDITOR='nvim'"
+        );
+        assert_eq!(
+            var_line(&Shell::Fish, "EDITOR", "nvim"),
+            "set -gx EDITOR 'nvim'"
+        );
+        assert_eq!(
+            var_line(&Shell::Nu, "GREETING", r#"say "hi" \o/"#),
+            r#"$env.GREETING = "say \"hi\" \\o/""#
+        );
+        assert_eq!(
+            var_line(&Shell::Powershell, "GREETING", "it's"),
+            "$env:GREETING = 'it''s'"
+        );
+    }
 }
@@ -0,0 +1,167 @@
+use std::io::{stdin, stdout, Read, Write};
+use std::path::Path;
+
+use chrono::Utc;
+use eyre::{eyre, Result};
+use structopt::StructOpt;
+use termion::input::TermRead;
+
+use atuin_client::database::Database;
+use atuin_client::encryption::{
+    derive_key, key_to_mnemonic, load_key, load_pending_key, parse_exported_key, save_key,
+};
+use atuin_client::settings::Settings;
+use atuin_client::sync;
+
+#[derive(StructOpt)]
+pub struct Cmd {
+    #[structopt(
+        long = "set-passphrase",
+        about = "derive your key from a passphrase, so it can be restored by typing it in"
+    )]
+    set_passphrase: bool,
+
+    #[structopt(long, short, about = "your username, needed with --set-passphrase")]
+    username: Option<String>,
+
+    #[structopt(long, about = "print the key as a BIP39 word list, rather than base64")]
+    mnemonic: bool,
+
+    #[structopt(subcommand)]
+    cmd: Option<KeyCmd>,
+}
+
+#[derive(StructOpt)]
+pub enum KeyCmd {
+    #[structopt(about = "generate a new key, and re-encrypt all of your synced history with it")]
+    Rotate,
+
+    #[structopt(about = "install a key from another machine, as base64 or a BIP39 word list")]
+    Import {
+        #[structopt(about = "the key to import. read from stdin if not given")]
+        key: Option<String>,
+    },
+}
+
+// Prompt for a passphrase without echoing it
+pub fn read_passphrase(prompt: &str) -> Result<String> {
+    let mut stdout = stdout();
+    write!(stdout, "{}: ", prompt)?;
+    stdout.flush()?;
+
+    let passphrase = stdin().read_passwd(&mut stdout)?;
+    writeln!(stdout)?;
+
+    match passphrase {
+        Some(p) if !p.is_empty() => Ok(p),
+        _ => Err(eyre!("no passphrase given")),
+    }
+}
+
+impl KeyCmd {
+    async fn run(&self, settings: &Settings, db: &mut (impl Database + Send + Sync)) -> Result<()> {
+        match self {
+            Self::Import { key } => {
+                let key = if let Some(key) = key {
+                    key.clone()
+                } else {
+                    let mut key = String::new();
+                    stdin().read_to_string(&mut key)?;
+                    key
+                };
+
+                let key = parse_exported_key(key.as_str())?;
+
+                // never throw away the old key, it may be the only way to read
+                // history that was synced with it
+                let key_path = settings.key_path.as_str();
+
+                if Path::new(key_path).exists() {
+                    let backup = format!("{}.{}.bak", key_path, Utc::now().timestamp());
+                    std::fs::copy(key_path, backup.as_str())?;
+
+                    println!("Backed up your previous key to {}", backup);
+                }
+
+                save_key(settings, key)?;
+
+                println!("Key imported");
+
+                Ok(())
+            }
+
+            Self::Rotate => {
+                if load_pending_key(settings)?.is_some() {
+                    println!("Finishing the key rotation that was interrupted...");
+                }
+
+                // make sure we have everything the server does first, or it
+                // would be left encrypted with the old key
+                sync::sync(settings, false, db).await?;
+
+                let key = sync::rotate_key(settings, db, None).await?;
+
+                println!("Your key has been rotated, and all of your history re-encrypted.");
+                println!("Other machines will need to login again, with your new key:");
+                println!("{}", base64::encode(key));
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Cmd {
+    pub async fn run(
+        &self,
+        settings: &Settings,
+        db: &mut (impl Database + Send + Sync),
+    ) -> Result<()> {
+        if let Some(cmd) = &self.cmd {
+            return cmd.run(settings, db).await;
+        }
+
+        if self.set_passphrase {
+            let username = self
+                .username
+                .as_ref()
+                .ok_or_else(|| eyre!("--username is required to set a passphrase"))?;
+
+            let passphrase = read_passphrase("Passphrase")?;
+
+            if passphrase != read_passphrase("Confirm passphrase")? {
+                return Err(eyre!("passphrases do not match"));
+            }
+
+            let key = derive_key(username, passphrase.as_str())?;
+
+            if !settings.can_sync() {
+                save_key(settings, key)?;
+
+                println!("Your key is now derived from your passphrase.");
+                return Ok(());
+            }
+
+            // anything already synced would otherwise be left encrypted with
+            // the old key, which a machine logging in with the passphrase
+            // can't read
+            sync::sync(settings, false, db).await?;
+            sync::rotate_key(settings, db, Some(key)).await?;
+
+            println!("Your key is now derived from your passphrase, and all of your history re-encrypted.");
+            println!("Other machines will need to login again, with --passphrase.");
+
+            return Ok(());
+        }
+
+        if self.mnemonic {
+            println!("{}", key_to_mnemonic(&load_key(settings)?)?);
+            return Ok(());
+        }
+
+        let key = std::fs::read(settings.key_path.as_str())?;
+        println!("{}", base64::encode(key));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use eyre::{eyre, Result};
+use termion::event::Key;
+
+use atuin_client::settings::Keys as KeySettings;
+
+// The key bound to each action in the interactive search, parsed from the
+// [keys] section of the config
+pub struct Keys {
+    pub accept: Key,
+    pub edit: Key,
+    pub mark: Key,
+    pub copy: Key,
+    pub inspect: Key,
+    pub delete: Key,
+    pub pin: Key,
+    pub exit: Key,
+    pub cycle_filter: Key,
+    pub toggle_session: Key,
+    pub up: Key,
+    pub down: Key,
+}
+
+impl Keys {
+    pub fn new(settings: &KeySettings) -> Result<Self> {
+        Ok(Self {
+            accept: parse_key(settings.accept.as_str())?,
+            edit: parse_key(settings.edit.as_str())?,
+            mark: parse_key(settings.mark.as_str())?,
+            copy: parse_key(settings.copy.as_str())?,
+            inspect: parse_key(settings.inspect.as_str())?,
+            delete: parse_key(settings.delete.as_str())?,
+            pin: parse_key(settings.pin.as_str())?,
+            exit: parse_key(settings.exit.as_str())?,
+            cycle_filter: parse_key(settings.cycle_filter.as_str())?,
+            toggle_session: parse_key(settings.toggle_session.as_str())?,
+            up: parse_key(settings.up.as_str())?,
+            down: parse_key(settings.down.as_str())?,
+        })
+    }
+}
+
+// Parse a key chord, such as "enter", "ctrl-f", "alt-x" or "k"
+pub fn parse_key(chord: &str) -> Result<Key> {
+    let lower = chord.trim().to_lowercase();
+
+    let single = |s: &str| {
+        let mut chars = s.chars();
+
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+
+    if let Some(c) = lower.strip_prefix("ctrl-").and_then(single) {
+        return Ok(Key::Ctrl(c));
+    }
+
+    if let Some(c) = lower.strip_prefix("alt-").and_then(single) {
+        return Ok(Key::Alt(c));
+    }
+
+    if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+        return Ok(Key::F(n));
+    }
+
+    let key = match lower.as_str() {
+        "enter" | "return" => Key::Char('\n'),
+        "tab" => Key::Char('\t'),
+        "backtab" | "shift-tab" => Key::BackTab,
+        "esc" | "escape" => Key::Esc,
+        "backspace" => Key::Backspace,
+        "delete" | "del" => Key::Delete,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "space" => Key::Char(' '),
+        _ => match single(chord.trim()) {
+            // keep the case, so that "G" and "g" are different keys
+            Some(c) => Key::Char(c),
+            None => return Err(eyre!("invalid key: {}", chord)),
+        },
+    };
+
+    Ok(key)
+}
+
+// How to show a key in the UI
+pub fn key_name(key: Key) -> String {
+    match key {
+        Key::Char('\n') => String::from("Enter"),
+        Key::Char('\t') => String::from("Tab"),
+        Key::Char(' ') => String::from("Space"),
+        Key::Char(c) => c.to_string(),
+        Key::Ctrl(c) => format!("Ctrl-{}", c.to_ascii_uppercase()),
+        Key::Alt(c) => format!("Alt-{}", c.to_ascii_uppercase()),
+        Key::F(n) => format!("F{}", n),
+        Key::Esc => String::from("Esc"),
+        k => format!("{:?}", k),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use termion::event::Key;
+
+    use super::parse_key;
+
+    #[test]
+    fn test_parse_key() {
+        assert_eq!(parse_key("enter").unwrap(), Key::Char('\n'));
+        assert_eq!(parse_key("Esc").unwrap(), Key::Esc);
+        assert_eq!(parse_key("ctrl-f").unwrap(), Key::Ctrl('f'));
+        assert_eq!(parse_key("alt-x").unwrap(), Key::Alt('x'));
+        assert_eq!(parse_key("f5").unwrap(), Key::F(5));
+        assert_eq!(parse_key("G").unwrap(), Key::Char('G'));
+        assert_eq!(parse_key("up").unwrap(), Key::Up);
+
+        assert!(parse_key("ctrl-").is_err());
+        assert!(parse_key("hyper-x").is_err());
+    }
+}
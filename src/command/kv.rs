@@ -0,0 +1,127 @@
+use eyre::{eyre, Result};
+use structopt::StructOpt;
+
+use atuin_client::database::Database;
+use atuin_client::kv;
+use atuin_client::record::Record;
+
+#[derive(StructOpt)]
+pub enum Cmd {
+    #[structopt(about = "set a value, replacing any already under the key")]
+    Set {
+        #[structopt(
+            long,
+            short,
+            default_value = "default",
+            about = "the namespace to set the key in"
+        )]
+        namespace: String,
+
+        key: String,
+        value: String,
+    },
+
+    #[structopt(about = "print the value under a key")]
+    Get {
+        #[structopt(
+            long,
+            short,
+            default_value = "default",
+            about = "the namespace to look in"
+        )]
+        namespace: String,
+
+        key: String,
+    },
+
+    #[structopt(about = "list the keys in a namespace", aliases=&["l", "ls"])]
+    List {
+        #[structopt(
+            long,
+            short,
+            default_value = "default",
+            about = "the namespace to list"
+        )]
+        namespace: String,
+
+        #[structopt(long, short, about = "print each value after its key")]
+        values: bool,
+    },
+
+    #[structopt(about = "delete a key", aliases=&["rm"])]
+    Delete {
+        #[structopt(
+            long,
+            short,
+            default_value = "default",
+            about = "the namespace to delete the key from"
+        )]
+        namespace: String,
+
+        key: String,
+    },
+}
+
+impl Cmd {
+    pub async fn run(&self, db: &(impl Database + Send + Sync)) -> Result<()> {
+        match self {
+            Self::Set {
+                namespace,
+                key,
+                value,
+            } => {
+                let ns = kv::namespace(namespace.as_str())?;
+                kv::check_key(key.as_str())?;
+                kv::check_value(value.as_str())?;
+
+                db.save_record(&Record::new(ns.as_str(), key.as_str(), Some(value.clone())))
+                    .await?;
+
+                Ok(())
+            }
+
+            Self::Get { namespace, key } => {
+                let ns = kv::namespace(namespace.as_str())?;
+
+                match db.record(ns.as_str(), key.as_str()).await? {
+                    Some(Record {
+                        value: Some(value), ..
+                    }) => {
+                        println!("{}", value);
+                        Ok(())
+                    }
+                    _ => Err(eyre!("no value for {} in namespace {}", key, namespace)),
+                }
+            }
+
+            Self::List { namespace, values } => {
+                let ns = kv::namespace(namespace.as_str())?;
+
+                for r in db.records(ns.as_str()).await? {
+                    if *values {
+                        println!("{}\t{}", r.key, r.value.unwrap_or_default());
+                    } else {
+                        println!("{}", r.key);
+                    }
+                }
+
+                Ok(())
+            }
+
+            Self::Delete { namespace, key } => {
+                let ns = kv::namespace(namespace.as_str())?;
+
+                let existing = db.record(ns.as_str(), key.as_str()).await?;
+
+                if existing.map_or(true, |r| r.value.is_none()) {
+                    return Err(eyre!("no value for {} in namespace {}", key, namespace));
+                }
+
+                db.save_record(&Record::new(ns.as_str(), key.as_str(), None))
+                    .await?;
+
+                Ok(())
+            }
+        }
+    }
+}
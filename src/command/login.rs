@@ -1,12 +1,15 @@
 use std::fs::File;
 use std::io::prelude::*;
 
-use eyre::Result;
+use eyre::{eyre, Result};
 use structopt::StructOpt;
 
 use atuin_client::api_client;
+use atuin_client::encryption::{derive_key, parse_exported_key, save_key};
 use atuin_client::settings::Settings;
 
+use super::key::read_passphrase;
+
 #[derive(StructOpt)]
 #[structopt(setting(structopt::clap::AppSettings::DeriveDisplayOrder))]
 pub struct Cmd {
@@ -16,25 +19,58 @@ pub struct Cmd {
     #[structopt(long, short)]
     pub password: String,
 
-    #[structopt(long, short, about = "the encryption key for your account")]
-    pub key: String,
+    #[structopt(
+        long,
+        short,
+        about = "the encryption key for your account, as base64 or a BIP39 word list"
+    )]
+    pub key: Option<String>,
+
+    #[structopt(
+        long,
+        about = "derive the encryption key from your passphrase, instead of giving --key"
+    )]
+    pub passphrase: bool,
+
+    #[structopt(
+        long,
+        short,
+        about = "a code from your authenticator app, if 2fa is enabled"
+    )]
+    pub code: Option<String>,
+
+    #[structopt(
+        long,
+        about = "a name to recognise this machine by in your device list"
+    )]
+    pub device: Option<String>,
 }
 
 impl Cmd {
     pub fn run(&self, settings: &Settings) -> Result<()> {
+        // check for the key before logging in, so we don't end up half done
+        let key = match (&self.key, self.passphrase) {
+            (Some(key), false) => parse_exported_key(key.as_str())?,
+            (None, true) => {
+                let passphrase = read_passphrase("Passphrase")?;
+                derive_key(self.username.as_str(), passphrase.as_str())?
+            }
+            _ => return Err(eyre!("please give one of --key or --passphrase")),
+        };
+
         let session = api_client::login(
-            settings.sync_address.as_str(),
+            settings,
             self.username.as_str(),
             self.password.as_str(),
+            self.code.as_deref(),
+            self.device.as_deref(),
         )?;
 
         let session_path = settings.session_path.as_str();
         let mut file = File::create(session_path)?;
         file.write_all(session.session.as_bytes())?;
 
-        let key_path = settings.key_path.as_str();
-        let mut file = File::create(key_path)?;
-        file.write_all(&base64::decode(self.key.clone())?)?;
+        save_key(settings, key)?;
 
         println!("Logged in!");
 
@@ -0,0 +1,33 @@
+use std::path::Path;
+
+use eyre::{eyre, Result};
+
+use atuin_client::api_client;
+use atuin_client::settings::Settings;
+
+pub async fn run(settings: &Settings) -> Result<()> {
+    let session_path = settings.session_path.as_str();
+
+    if !Path::new(session_path).exists() {
+        return Err(eyre!("you are not logged in"));
+    }
+
+    let client = api_client::Client::new(settings)?;
+
+    // still forget the session locally if the server can't be reached, but
+    // make sure the user knows it may still be valid
+    if settings.offline {
+        eprintln!("Offline, so the session was not revoked on the server, and may still be valid");
+    } else if let Err(e) = client.logout().await {
+        eprintln!(
+            "Failed to revoke the session on the server, it may still be valid: {}",
+            e
+        );
+    }
+
+    std::fs::remove_file(session_path)?;
+
+    println!("Logged out!");
+
+    Ok(())
+}
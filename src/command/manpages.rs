@@ -0,0 +1,151 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use eyre::{eyre, Result};
+use structopt::clap::{AppSettings, ErrorKind};
+use structopt::StructOpt;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(StructOpt)]
+pub struct Cmd {
+    #[structopt(
+        long,
+        short,
+        default_value = ".",
+        about = "the directory to write the man pages to"
+    )]
+    out_dir: String,
+}
+
+// Text that roff could take for a request, or an escape, is made literal
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\e")
+        .lines()
+        .map(|l| {
+            if l.starts_with('.') || l.starts_with('\'') {
+                format!("\\&{}", l)
+            } else {
+                l.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// The --help for the command at path. clap 2 has no public way to look inside
+// an App, but asking for help is an "error" that carries the text
+fn help(path: &[String]) -> Result<String> {
+    let args = path
+        .iter()
+        .cloned()
+        .chain(std::iter::once(String::from("--help")));
+
+    match crate::Atuin::clap()
+        .global_setting(AppSettings::ColorNever)
+        .get_matches_from_safe(args)
+    {
+        Err(e) if e.kind == ErrorKind::HelpDisplayed => Ok(e.message),
+        Err(e) => Err(eyre!("failed to get help for {}: {}", path.join(" "), e)),
+        Ok(_) => Err(eyre!("failed to get help for {}", path.join(" "))),
+    }
+}
+
+// The about line is the last before the first blank one, after the name and
+// version, and the author on the top level command
+fn about(help: &str) -> &str {
+    help.lines()
+        .skip(1)
+        .take_while(|l| !l.trim().is_empty())
+        .last()
+        .unwrap_or_default()
+}
+
+// The names listed under SUBCOMMANDS, without clap's own help
+fn subcommands(help: &str) -> Vec<String> {
+    help.lines()
+        .skip_while(|l| l.trim() != "SUBCOMMANDS:")
+        .skip(1)
+        .take_while(|l| !l.trim().is_empty())
+        .filter(|l| l.starts_with("    ") && !l.starts_with("     "))
+        .filter_map(|l| l.split_whitespace().next())
+        .filter(|name| *name != "help")
+        .map(String::from)
+        .collect()
+}
+
+// One page for a command, with the same text as its --help. Every subcommand
+// gets a page of its own, named like git's: atuin-history-list
+fn write_page(path: &[String], dir: &Path) -> Result<()> {
+    let name = path.join("-");
+    let help = help(path)?;
+    let subcommands = subcommands(help.as_str());
+
+    let mut page = File::create(dir.join(format!("{}.1", name)))?;
+
+    writeln!(
+        page,
+        ".TH {} 1 \"\" \"atuin {}\" \"Atuin Manual\"",
+        name.to_uppercase(),
+        VERSION
+    )?;
+    writeln!(page, ".SH NAME")?;
+    writeln!(page, "{} \\- {}", name, escape(about(help.as_str()).trim()))?;
+    writeln!(page, ".SH DESCRIPTION")?;
+    writeln!(page, ".nf")?;
+    writeln!(page, "{}", escape(help.as_str()))?;
+    writeln!(page, ".fi")?;
+
+    if !subcommands.is_empty() {
+        writeln!(page, ".SH SEE ALSO")?;
+
+        let see_also = subcommands
+            .iter()
+            .map(|s| format!("{}-{}(1)", name, s))
+            .collect::<Vec<_>>();
+
+        writeln!(page, "{}", see_also.join(", "))?;
+    }
+
+    for sub in subcommands {
+        let mut path = path.to_vec();
+        path.push(sub);
+
+        write_page(&path, dir)?;
+    }
+
+    Ok(())
+}
+
+impl Cmd {
+    pub fn run(&self) -> Result<()> {
+        let dir = Path::new(self.out_dir.as_str());
+        std::fs::create_dir_all(dir)?;
+
+        write_page(&[String::from("atuin")], dir)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{about, escape, subcommands};
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("search history"), "search history");
+        assert_eq!(escape(r"a \n b"), r"a \en b");
+        assert_eq!(
+            escape(".hidden\n'quoted\nfine"),
+            "\\&.hidden\n\\&'quoted\nfine"
+        );
+    }
+
+    #[test]
+    fn test_parse_help() {
+        let help = "atuin-history 0.6.4\nmanipulate shell history\n\nUSAGE:\n    atuin history <SUBCOMMAND>\n\nSUBCOMMANDS:\n    start    begins a new command in the history\n    list     \n            list all items in history\n    help     Prints this message or the help of the given subcommand(s)\n";
+
+        assert_eq!(about(help), "manipulate shell history");
+        assert_eq!(subcommands(help), vec!["start", "list"]);
+    }
+}
@@ -1,19 +1,33 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use eyre::Result;
+use eyre::{eyre, Result};
+use structopt::clap::Shell;
 use structopt::StructOpt;
 
-use atuin_client::database::Sqlite;
+use atuin_client::daemon;
+use atuin_client::database::{Database, Sqlite};
 use atuin_client::settings::Settings as ClientSettings;
 use atuin_common::utils::uuid_v4;
 use atuin_server::settings::Settings as ServerSettings;
 
+mod account;
+mod alias;
+mod config;
+mod doctor;
+mod dotfiles;
 mod event;
 mod history;
 mod import;
 mod init;
+mod key;
+mod keybindings;
+mod kv;
 mod login;
+mod logout;
+mod manpages;
+mod predict;
 mod register;
+mod scripts;
 mod search;
 mod server;
 mod stats;
@@ -43,104 +57,166 @@ pub enum AtuinCmd {
     Uuid,
 
     #[structopt(about = "interactive history search")]
-    Search {
-        #[structopt(long, short, about = "filter search result by directory")]
-        cwd: Option<String>,
+    Search(search::Cmd),
 
-        #[structopt(long = "exclude-cwd", about = "exclude directory from results")]
-        exclude_cwd: Option<String>,
+    #[structopt(about = "sync with the configured server")]
+    Sync(sync::Cmd),
 
-        #[structopt(long, short, about = "filter search result by exit code")]
-        exit: Option<i64>,
+    #[structopt(about = "login to the configured server")]
+    Login(login::Cmd),
 
-        #[structopt(long = "exclude-exit", about = "exclude results with this exit code")]
-        exclude_exit: Option<i64>,
+    #[structopt(about = "log out of the configured server")]
+    Logout,
 
-        #[structopt(long, short, about = "only include results added before this date")]
-        before: Option<String>,
+    #[structopt(about = "register with the configured server")]
+    Register(register::Cmd),
 
-        #[structopt(long, about = "only include results after this date")]
-        after: Option<String>,
+    #[structopt(about = "manage your account on the configured server")]
+    Account(account::Cmd),
 
-        #[structopt(long, short, about = "open interactive search UI")]
-        interactive: bool,
+    #[structopt(about = "manage shell aliases, which are synced to your other machines")]
+    Alias(alias::Cmd),
 
-        #[structopt(long, short, about = "use human-readable formatting for time")]
-        human: bool,
+    #[structopt(about = "manage environment variables that are synced to your other machines")]
+    Dotfiles(dotfiles::Cmd),
 
-        query: Vec<String>,
-    },
+    #[structopt(about = "store small values that are synced to your other machines")]
+    Kv(kv::Cmd),
 
-    #[structopt(about = "sync with the configured server")]
-    Sync {
-        #[structopt(long, short, about = "force re-download everything")]
-        force: bool,
+    #[structopt(about = "save scripts and run them by name, on any of your machines")]
+    Scripts(scripts::Cmd),
+
+    #[structopt(about = "print the encryption key for transfer to another machine")]
+    Key(key::Cmd),
+
+    #[structopt(about = "run in the background, and handle requests from the shell hooks")]
+    Daemon,
+
+    #[structopt(about = "print diagnostics to include in a bug report")]
+    Doctor,
+
+    #[structopt(about = "print or change settings")]
+    Config(config::Cmd),
+
+    #[structopt(about = "print the commands most likely to be ran next")]
+    Predict(predict::Cmd),
+
+    #[structopt(about = "generate shell completions")]
+    GenCompletions {
+        #[structopt(
+            long,
+            short,
+            possible_values = &Shell::variants(),
+            case_insensitive = true,
+            about = "the shell to generate completions for"
+        )]
+        shell: Shell,
+
+        #[structopt(
+            long,
+            short,
+            about = "write the completions to a file in this directory, rather than to stdout"
+        )]
+        out_dir: Option<PathBuf>,
     },
 
-    #[structopt(about = "login to the configured server")]
-    Login(login::Cmd),
+    #[structopt(
+        about = "generate man pages for every subcommand",
+        setting = structopt::clap::AppSettings::Hidden
+    )]
+    GenManpages(manpages::Cmd),
+}
 
-    #[structopt(about = "register with the configured server")]
-    Register(register::Cmd),
+// Completions come straight from the clap definitions, so that they cover
+// every subcommand and flag
+fn gen_completions(shell: Shell, out_dir: Option<&Path>) -> Result<()> {
+    let mut app = crate::Atuin::clap();
 
-    #[structopt(about = "print the encryption key for transfer to another machine")]
-    Key,
+    match out_dir {
+        Some(dir) => {
+            std::fs::create_dir_all(dir)?;
+            app.gen_completions("atuin", shell, dir);
+        }
+        None => app.gen_completions_to("atuin", shell, &mut std::io::stdout()),
+    }
+
+    Ok(())
 }
 
 impl AtuinCmd {
     pub async fn run(self) -> Result<()> {
+        // for packagers, so this can't need any config or a database
+        if let Self::GenCompletions { shell, out_dir } = &self {
+            return gen_completions(*shell, out_dir.as_deref());
+        }
+
+        if let Self::GenManpages(manpages) = &self {
+            return manpages.run();
+        }
+
         let client_settings = ClientSettings::new()?;
         let server_settings = ServerSettings::new()?;
 
+        if let Self::History(history) = &self {
+            if history.run_daemon(&client_settings).await? {
+                return Ok(());
+            }
+        }
+
+        // these have nothing to fall back on without the server
+        if client_settings.offline
+            && matches!(self, Self::Login(_) | Self::Register(_) | Self::Account(_))
+        {
+            return Err(eyre!("this needs the sync server, and atuin is offline"));
+        }
+
         let db_path = PathBuf::from(client_settings.db_path.as_str());
+        let db = Sqlite::new(db_path).await?;
 
-        let mut db = Sqlite::new(db_path).await?;
+        self.run_with(client_settings, server_settings, db).await
+    }
 
+    // Everything past opening the database, which works with any history store
+    async fn run_with<D>(
+        self,
+        client_settings: ClientSettings,
+        server_settings: ServerSettings,
+        mut db: D,
+    ) -> Result<()>
+    where
+        D: Database + Clone + Send + Sync + 'static,
+    {
         match self {
             Self::History(history) => history.run(&client_settings, &mut db).await,
-            Self::Import(import) => import.run(&mut db).await,
+            Self::Import(import) => import.run(&client_settings, &mut db).await,
             Self::Server(server) => server.run(&server_settings).await,
             Self::Stats(stats) => stats.run(&mut db, &client_settings).await,
-            Self::Init(init) => init.run(),
-            Self::Search {
-                cwd,
-                exit,
-                interactive,
-                human,
-                exclude_exit,
-                exclude_cwd,
-                before,
-                after,
-                query,
-            } => {
-                search::run(
-                    cwd,
-                    exit,
-                    interactive,
-                    human,
-                    exclude_exit,
-                    exclude_cwd,
-                    before,
-                    after,
-                    &query,
-                    &mut db,
-                )
-                .await
-            }
-
-            Self::Sync { force } => sync::run(&client_settings, force, &mut db).await,
+            Self::Init(init) => init.run(&db).await,
+            Self::Search(search) => search.run(&client_settings, &mut db).await,
+            Self::Sync(s) => s.run(&client_settings, &mut db).await,
             Self::Login(l) => l.run(&client_settings),
             Self::Register(r) => register::run(
                 &client_settings,
                 r.username.as_str(),
                 r.email.as_str(),
                 r.password.as_str(),
+                r.invite.as_deref(),
+                r.device.as_deref(),
             ),
-            Self::Key => {
-                let key = std::fs::read(client_settings.key_path.as_str())?;
-                println!("{}", base64::encode(key));
-                Ok(())
-            }
+            Self::Logout => logout::run(&client_settings).await,
+            Self::Account(account) => account.run(&client_settings).await,
+            Self::Alias(alias) => alias.run(&db).await,
+            Self::Dotfiles(dotfiles) => dotfiles.run(&db).await,
+            Self::Kv(kv) => kv.run(&db).await,
+            Self::Scripts(scripts) => scripts.run(&db).await,
+            Self::Key(key) => key.run(&client_settings, &mut db).await,
+            Self::Daemon => daemon::serve(client_settings, db).await,
+            Self::Doctor => doctor::run(&client_settings, &db).await,
+            Self::Config(config) => config.run(),
+            Self::Predict(predict) => predict.run(&db).await,
+
+            Self::GenCompletions { shell, out_dir } => gen_completions(shell, out_dir.as_deref()),
+            Self::GenManpages(manpages) => manpages.run(),
 
             Self::Uuid => {
                 println!("{}", uuid_v4());
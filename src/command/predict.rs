@@ -0,0 +1,74 @@
+use std::env;
+
+use eyre::Result;
+use structopt::StructOpt;
+
+use atuin_client::database::Database;
+
+#[derive(StructOpt)]
+pub struct Cmd {
+    #[structopt(
+        long,
+        short,
+        about = "the directory to predict for, instead of the current one"
+    )]
+    cwd: Option<String>,
+
+    #[structopt(
+        long,
+        short,
+        about = "the command ran before, instead of the last one in this session"
+    )]
+    previous: Option<String>,
+
+    #[structopt(long, short, default_value = "5", about = "how many commands to print")]
+    limit: i64,
+}
+
+// The last command ran in this shell session. Only the most recent history is
+// looked at, as an old command says little about what comes next.
+async fn session_previous(db: &(impl Database + Send + Sync)) -> Result<Option<String>> {
+    let session = match env::var("ATUIN_SESSION") {
+        Ok(session) => session,
+        Err(_) => return Ok(None),
+    };
+
+    let previous = db
+        .before(chrono::Utc::now(), 100)
+        .await?
+        .into_iter()
+        .find(|h| h.session == session)
+        .map(|h| h.command);
+
+    Ok(previous)
+}
+
+impl Cmd {
+    pub async fn run(&self, db: &(impl Database + Send + Sync)) -> Result<()> {
+        let cwd = match &self.cwd {
+            Some(cwd) => cwd.clone(),
+            None => env::current_dir()?.display().to_string(),
+        };
+
+        let previous = match &self.previous {
+            Some(previous) => Some(previous.clone()),
+            None => session_previous(db).await?,
+        };
+
+        let mut predictions = db
+            .predict(previous.as_deref(), cwd.as_str(), self.limit)
+            .await?;
+
+        // nothing has followed this command before, so fall back to what is
+        // usually ran here
+        if predictions.is_empty() && previous.is_some() {
+            predictions = db.predict(None, cwd.as_str(), self.limit).await?;
+        }
+
+        for p in predictions {
+            println!("{}", p);
+        }
+
+        Ok(())
+    }
+}
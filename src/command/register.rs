@@ -18,10 +18,26 @@ pub struct Cmd {
 
     #[structopt(long, short)]
     pub password: String,
+
+    #[structopt(long, short, about = "an invite, if the server requires one")]
+    pub invite: Option<String>,
+
+    #[structopt(
+        long,
+        about = "a name to recognise this machine by in your device list"
+    )]
+    pub device: Option<String>,
 }
 
-pub fn run(settings: &Settings, username: &str, email: &str, password: &str) -> Result<()> {
-    let session = api_client::register(settings.sync_address.as_str(), username, email, password)?;
+pub fn run(
+    settings: &Settings,
+    username: &str,
+    email: &str,
+    password: &str,
+    invite: Option<&str>,
+    device: Option<&str>,
+) -> Result<()> {
+    let session = api_client::register(settings, username, email, password, invite, device)?;
 
     let path = settings.session_path.as_str();
     let mut file = File::create(path)?;
@@ -0,0 +1,175 @@
+use std::convert::TryFrom;
+use std::env;
+use std::fs;
+use std::io::{stdin, stdout, Read, Write};
+use std::path::PathBuf;
+use std::process::Command;
+
+use eyre::{eyre, Result};
+use structopt::StructOpt;
+use tabwriter::TabWriter;
+
+use atuin_client::database::{current_context, Database};
+use atuin_client::record::Record;
+use atuin_client::scripts::{self, NAMESPACE};
+use atuin_client::settings::{FilterMode, SearchMode};
+
+#[derive(StructOpt)]
+pub enum Cmd {
+    #[structopt(
+        about = "save a script, from a file, your last commands, or stdin. replaces any with the same name"
+    )]
+    Save {
+        name: String,
+
+        #[structopt(
+            long,
+            conflicts_with = "from-last",
+            about = "read the script from a file"
+        )]
+        from_file: Option<PathBuf>,
+
+        #[structopt(long, about = "save the last n commands ran in this shell")]
+        from_last: Option<usize>,
+    },
+
+    #[structopt(about = "run a script, passing along any arguments after its name")]
+    Run {
+        #[structopt(long, about = "the shell to run the script with, rather than $SHELL")]
+        shell: Option<String>,
+
+        name: String,
+        args: Vec<String>,
+    },
+
+    #[structopt(about = "list your scripts", aliases=&["l", "ls"])]
+    List,
+
+    #[structopt(about = "print a script")]
+    Show { name: String },
+
+    #[structopt(about = "delete a script", aliases=&["rm"])]
+    Delete { name: String },
+}
+
+async fn load(db: &(impl Database + Send + Sync), name: &str) -> Result<String> {
+    db.record(NAMESPACE, name)
+        .await?
+        .and_then(|r| r.value)
+        .ok_or_else(|| eyre!("there is no script named {}", name))
+}
+
+impl Cmd {
+    pub async fn run(&self, db: &(impl Database + Send + Sync)) -> Result<()> {
+        match self {
+            Self::Save {
+                name,
+                from_file,
+                from_last,
+            } => {
+                let script = match (from_file, from_last) {
+                    (Some(path), _) => fs::read_to_string(path)?,
+                    (None, Some(count)) => {
+                        // this shell's commands, if the hooks say which it is
+                        let context = current_context();
+                        let filter = if context.session.is_empty() {
+                            FilterMode::Global
+                        } else {
+                            FilterMode::Session
+                        };
+
+                        let history = db
+                            .search(
+                                Some(i64::try_from(*count)? + 1),
+                                SearchMode::Prefix,
+                                filter,
+                                &context,
+                                "",
+                                false,
+                            )
+                            .await?;
+
+                        let skip = env::var("ATUIN_HISTORY_ID").ok();
+
+                        scripts::from_history(&history, skip.as_deref(), *count)
+                    }
+                    (None, None) => {
+                        let mut script = String::new();
+                        stdin().read_to_string(&mut script)?;
+
+                        script
+                    }
+                };
+
+                scripts::check(name.as_str(), script.as_str())?;
+
+                db.save_record(&Record::new(NAMESPACE, name.as_str(), Some(script)))
+                    .await?;
+
+                println!("script {} saved", name);
+
+                Ok(())
+            }
+
+            Self::Run { shell, name, args } => {
+                let script = load(db, name.as_str()).await?;
+
+                let shell = shell
+                    .clone()
+                    .or_else(|| env::var("SHELL").ok())
+                    .unwrap_or_else(|| String::from("sh"));
+
+                // the name is $0, so the arguments start at $1 as they would
+                // for a script file
+                let status = Command::new(shell)
+                    .arg("-c")
+                    .arg(script)
+                    .arg(name)
+                    .args(args)
+                    .status()?;
+
+                std::process::exit(status.code().unwrap_or(1));
+            }
+
+            Self::List => {
+                let mut writer = TabWriter::new(stdout()).padding(2);
+
+                for r in db.records(NAMESPACE).await? {
+                    let script = r.value.unwrap_or_default();
+                    let first = script.lines().next().unwrap_or_default();
+                    let lines = script.lines().count();
+
+                    writeln!(
+                        writer,
+                        "{}\t{} line{}\t{}",
+                        r.key,
+                        lines,
+                        if lines == 1 { "" } else { "s" },
+                        first
+                    )?;
+                }
+
+                writer.flush()?;
+
+                Ok(())
+            }
+
+            Self::Show { name } => {
+                print!("{}", load(db, name.as_str()).await?);
+
+                Ok(())
+            }
+
+            Self::Delete { name } => {
+                load(db, name.as_str()).await?;
+
+                db.save_record(&Record::new(NAMESPACE, name.as_str(), None))
+                    .await?;
+
+                println!("script {} deleted", name);
+
+                Ok(())
+            }
+        }
+    }
+}
@@ -1,32 +1,492 @@
-use chrono::Utc;
-use eyre::Result;
+use eyre::{eyre, Result};
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::io::{stdout, Write};
+use std::ops::Sub;
 use std::time::Duration;
-use std::{io::stdout, ops::Sub};
+use structopt::StructOpt;
 
-use termion::{event::Key, input::MouseTerminal, raw::IntoRawMode, screen::AlternateScreen};
+use termion::{
+    clear, cursor, cursor::DetectCursorPos, event::Key, input::MouseTerminal, raw::IntoRawMode,
+    screen::AlternateScreen,
+};
 use tui::{
     backend::{Backend, TermionBackend},
-    layout::{Alignment, Constraint, Corner, Direction, Layout},
+    layout::{Alignment, Constraint, Corner, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Span, Spans, Text},
-    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
-    Frame, Terminal,
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
+    Frame, Terminal, TerminalOptions, Viewport,
 };
 use unicode_width::UnicodeWidthStr;
 
-use atuin_client::database::Database;
+use atuin_client::daemon::{self, Request, Response};
+use atuin_client::database::{current_context, Context, Database};
 use atuin_client::history::History;
+use atuin_client::settings::{FilterMode, JoinMode, KeymapMode, SearchMode, Settings};
+use atuin_client::tags;
 
 use crate::command::event::{Event, Events};
+use crate::command::history::ListMode;
+use crate::command::keybindings::{key_name, Keys};
+use crate::command::stats::parse_date;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+// Put in front of the selected command when it should be run straight away,
+// rather than left in the shell's buffer to edit. The shell scripts strip it.
+const ACCEPT_PREFIX: &str = "__atuin_accept__:";
+
+// Filters shared by anything that selects a subset of history, such as search
+// and delete. Flatten this into a command to accept the same arguments.
+#[derive(StructOpt)]
+pub struct Filters {
+    #[structopt(long, short, about = "filter search result by directory")]
+    pub cwd: Option<String>,
+
+    #[structopt(long = "exclude-cwd", about = "exclude directory from results")]
+    pub exclude_cwd: Option<String>,
+
+    #[structopt(long, short, about = "filter search result by exit code")]
+    pub exit: Option<i64>,
+
+    #[structopt(long = "exclude-exit", about = "exclude results with this exit code")]
+    pub exclude_exit: Option<i64>,
+
+    #[structopt(long, short, about = "only include results added before this date")]
+    pub before: Option<String>,
+
+    #[structopt(long, about = "only include results after this date")]
+    pub after: Option<String>,
+
+    #[structopt(long, about = "only include results from the current session")]
+    pub session: bool,
+
+    #[structopt(
+        long = "min-duration",
+        about = "only include results that took at least this long, eg 30s or 5m"
+    )]
+    pub min_duration: Option<String>,
+
+    #[structopt(
+        long = "max-duration",
+        about = "only include results that took at most this long, eg 30s or 5m"
+    )]
+    pub max_duration: Option<String>,
+
+    #[structopt(long, about = "only include results ran on this host")]
+    pub host: Option<String>,
+
+    #[structopt(long, about = "only include results ran by this user")]
+    pub user: Option<String>,
+
+    #[structopt(
+        long,
+        about = "only include results with this tag. give it more than once to need every tag"
+    )]
+    pub tag: Vec<String>,
+}
+
+#[derive(StructOpt)]
+pub struct Cmd {
+    #[structopt(flatten)]
+    filters: Filters,
+
+    #[structopt(
+        long = "search-mode",
+        about = "how to match the query, prefix or fuzzy"
+    )]
+    search_mode: Option<SearchMode>,
+
+    #[structopt(long, short, about = "open interactive search UI")]
+    interactive: bool,
+
+    #[structopt(
+        long,
+        about = "print the single best command starting with --prefix, for autosuggestions"
+    )]
+    suggest: bool,
+
+    #[structopt(long, about = "what has been typed so far, with --suggest")]
+    prefix: Option<String>,
+
+    #[structopt(
+        long,
+        about = "show each command once (true) or every run of it (false), instead of the dedup setting"
+    )]
+    dedup: Option<bool>,
+
+    #[structopt(long, short, about = "use human-readable formatting for time")]
+    human: bool,
+
+    #[structopt(long = "cmd-only", about = "only print the command of each result")]
+    cmd_only: bool,
+
+    #[structopt(
+        long,
+        short,
+        about = "print each result with this format, eg '{time} {exit} {command}', or json"
+    )]
+    format: Option<String>,
+
+    #[structopt(long, about = "only print this many of the most recent results")]
+    limit: Option<usize>,
+
+    #[structopt(long, about = "skip this many of the most recent results")]
+    offset: Option<usize>,
+
+    #[structopt(
+        long,
+        short,
+        about = "print the most recent result first. by default it is printed last"
+    )]
+    reverse: bool,
+
+    #[structopt(
+        long,
+        about = "terminate each result with a NUL, rather than a newline"
+    )]
+    print0: bool,
+
+    query: Vec<String>,
+}
+
+// history is stored with a hostname of host:user
+fn host_user(h: &History) -> (&str, &str) {
+    match h.hostname.rfind(':') {
+        Some(i) => (&h.hostname[..i], &h.hostname[i + 1..]),
+        None => (h.hostname.as_str(), ""),
+    }
+}
+
+#[derive(Clone, Copy)]
+struct DurationRange {
+    min: Option<i64>,
+    max: Option<i64>,
+}
+
+impl DurationRange {
+    const fn is_empty(&self) -> bool {
+        self.min.is_none() && self.max.is_none()
+    }
+
+    // history without a duration can't be said to be in any range
+    const fn contains(&self, h: &History) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+
+        if h.duration < 0 {
+            return false;
+        }
+
+        match (self.min, self.max) {
+            (Some(min), _) if h.duration < min => false,
+            (_, Some(max)) if h.duration > max => false,
+            _ => true,
+        }
+    }
+}
+
+impl Filters {
+    pub fn is_empty(&self) -> bool {
+        self.cwd.is_none()
+            && self.exclude_cwd.is_none()
+            && self.exit.is_none()
+            && self.exclude_exit.is_none()
+            && self.before.is_none()
+            && self.after.is_none()
+            && !self.session
+            && self.min_duration.is_none()
+            && self.max_duration.is_none()
+            && self.host.is_none()
+            && self.user.is_none()
+            && self.tag.is_empty()
+    }
+
+    // the duration limits, in nanoseconds to match the history
+    fn durations(&self) -> Result<DurationRange> {
+        let parse = |d: &Option<String>| -> Result<Option<i64>> {
+            d.as_deref()
+                .map(|d| {
+                    let d = humantime::parse_duration(d)
+                        .map_err(|e| eyre!("invalid duration {:?}: {}", d, e))?;
+                    Ok(i64::try_from(d.as_nanos())?)
+                })
+                .transpose()
+        };
+
+        Ok(DurationRange {
+            min: parse(&self.min_duration)?,
+            max: parse(&self.max_duration)?,
+        })
+    }
+
+    fn dir(&self) -> Result<Option<String>> {
+        let dir = if let Some(cwd) = &self.cwd {
+            if cwd == "." {
+                let current = std::env::current_dir()?;
+                let current = current.as_os_str();
+                let current = current.to_str().unwrap();
+
+                Some(current.to_owned())
+            } else {
+                Some(cwd.clone())
+            }
+        } else {
+            None
+        };
+
+        Ok(dir)
+    }
+
+    // everything the filters need, worked out once
+    pub async fn resolve(
+        &self,
+        settings: &Settings,
+        db: &(impl Database + Send + Sync),
+    ) -> Result<Filter> {
+        let before = self
+            .before
+            .as_deref()
+            .map(|d| parse_date(d, settings))
+            .transpose()?;
+        let after = self
+            .after
+            .as_deref()
+            .map(|d| parse_date(d, settings))
+            .transpose()?;
+
+        tags::check(&self.tag)?;
+
+        // tags are only in the database, so look up what has them up front
+        let tagged: Option<HashSet<String>> = if self.tag.is_empty() {
+            None
+        } else {
+            Some(db.tagged(&self.tag).await?.into_iter().collect())
+        };
+
+        Ok(Filter {
+            exit: self.exit,
+            exclude_exit: self.exclude_exit,
+            dir: self.dir()?,
+            exclude_cwd: self.exclude_cwd.clone(),
+            session: if self.session {
+                Some(current_context().session)
+            } else {
+                None
+            },
+            durations: self.durations()?,
+            host: self.host.clone(),
+            user: self.user.clone(),
+            before,
+            after,
+            tagged,
+        })
+    }
+
+    // TODO: This filtering would be better done in the SQL query, I just
+    // need a nice way of building queries.
+    pub async fn apply(
+        &self,
+        results: &[History],
+        settings: &Settings,
+        db: &(impl Database + Send + Sync),
+    ) -> Result<Vec<History>> {
+        let filter = self.resolve(settings, db).await?;
+
+        Ok(results
+            .iter()
+            .filter(|h| filter.matches(h))
+            .map(std::borrow::ToOwned::to_owned)
+            .collect())
+    }
+}
+
+// The filters, ready to be checked against history. The interactive search
+// checks every query's results against them
+pub struct Filter {
+    exit: Option<i64>,
+    exclude_exit: Option<i64>,
+    dir: Option<String>,
+    exclude_cwd: Option<String>,
+    session: Option<String>,
+    durations: DurationRange,
+    host: Option<String>,
+    user: Option<String>,
+    before: Option<chrono::DateTime<chrono::Local>>,
+    after: Option<chrono::DateTime<chrono::Local>>,
+    tagged: Option<HashSet<String>>,
+}
+
+impl Filter {
+    fn is_empty(&self) -> bool {
+        self.exit.is_none()
+            && self.exclude_exit.is_none()
+            && self.dir.is_none()
+            && self.exclude_cwd.is_none()
+            && self.session.is_none()
+            && self.durations.is_empty()
+            && self.host.is_none()
+            && self.user.is_none()
+            && self.before.is_none()
+            && self.after.is_none()
+            && self.tagged.is_none()
+    }
+
+    fn matches(&self, h: &History) -> bool {
+        if self.exit.map_or(false, |exit| h.exit != exit) {
+            return false;
+        }
+
+        if self.exclude_exit.map_or(false, |exit| h.exit == exit) {
+            return false;
+        }
+
+        if self.exclude_cwd.as_ref().map_or(false, |cwd| &h.cwd == cwd) {
+            return false;
+        }
+
+        if self.dir.as_ref().map_or(false, |cwd| &h.cwd != cwd) {
+            return false;
+        }
+
+        if self.session.as_ref().map_or(false, |s| &h.session != s) {
+            return false;
+        }
+
+        if !self.durations.contains(h) {
+            return false;
+        }
+
+        let (host, user) = host_user(h);
+
+        if self.host.as_ref().map_or(false, |h| h != host) {
+            return false;
+        }
+
+        if self.user.as_ref().map_or(false, |u| u != user) {
+            return false;
+        }
+
+        if self.before.map_or(false, |before| h.timestamp > before) {
+            return false;
+        }
+
+        if self.after.map_or(false, |after| h.timestamp < after) {
+            return false;
+        }
+
+        if self.tagged.as_ref().map_or(false, |t| !t.contains(&h.id)) {
+            return false;
+        }
+
+        true
+    }
+}
+
 struct State {
     input: String,
 
+    search_mode: SearchMode,
+
+    filter_mode: FilterMode,
+
+    context: Context,
+
     results: Vec<History>,
 
     results_state: ListState,
+
+    // show the duration, time and exit code of each result, not just the command
+    show_details: bool,
+
+    // show the full selected command, and where it was run, under the results
+    show_preview: bool,
+
+    // the current mode, which changes between the vim modes as keys are pressed
+    keymap: KeymapMode,
+
+    // waiting for the second g of gg
+    pending_g: bool,
+
+    keys: Keys,
+
+    // run the selected command with the accept key, rather than editing it
+    enter_accept: bool,
+
+    // ask for the delete key to be pressed twice
+    confirm_delete: bool,
+
+    // the history waiting for that second press
+    pending_delete: Option<String>,
+
+    // from the command line, such as --exit and --tag
+    filter: Filter,
+
+    // show each command once
+    dedup: bool,
+
+    // results marked to be accepted together, kept across queries
+    marked: Vec<History>,
+
+    // the pinned commands, which come first when nothing has been typed
+    pinned: Vec<String>,
+
+    join_marked: JoinMode,
+
+    // show that the copy key worked, until the next key
+    copied: bool,
+
+    // the selected history is open in the inspector, with how many times its
+    // command has been ran and how many of those failed
+    inspecting: Option<(i64, i64)>,
+}
+
+#[allow(clippy::clippy::cast_sign_loss)]
+fn display_duration(h: &History) -> String {
+    if h.duration == -1 {
+        String::from("running")
+    } else {
+        humantime::format_duration(Duration::from_millis(
+            std::cmp::max(h.duration, 0) as u64 / 1_000_000,
+        ))
+        .to_string()
+    }
+}
+
+// The OSC 52 escape sequence, which asks the terminal to put the text on the
+// clipboard. Unlike a clipboard crate, this reaches the local clipboard over
+// ssh. tmux needs it wrapped to pass it through to the terminal.
+fn osc52(text: &str) -> String {
+    let osc = format!("\x1b]52;c;{}\x07", base64::encode(text));
+
+    if std::env::var("TMUX").is_ok() {
+        format!("\x1bPtmux;\x1b{}\x1b\\", osc)
+    } else {
+        osc
+    }
+}
+
+fn copy(text: &str) -> Result<()> {
+    let mut stdout = stdout();
+    stdout.write_all(osc52(text).as_bytes())?;
+    stdout.flush()?;
+
+    Ok(())
+}
+
+// The marked commands, in the order they were ran, so that accepting them
+// replays the sequence
+fn join_marked(marked: &[History], join: JoinMode) -> String {
+    let mut marked: Vec<&History> = marked.iter().collect();
+    marked.sort_by_key(|h| h.timestamp);
+
+    marked
+        .iter()
+        .map(|h| h.command.as_str())
+        .collect::<Vec<&str>>()
+        .join(join.separator())
 }
 
 impl State {
@@ -71,6 +531,175 @@ impl State {
             .collect()
     }
 
+    // Results are drawn from the bottom, so up goes to older history
+    fn select_up(&mut self) {
+        let last = match self.results.len() {
+            0 => return,
+            n => n - 1,
+        };
+
+        let i = self
+            .results_state
+            .selected()
+            .map_or(0, |i| std::cmp::min(i + 1, last));
+        self.results_state.select(Some(i));
+    }
+
+    fn select_down(&mut self) {
+        let i = self
+            .results_state
+            .selected()
+            .map_or(0, |i| i.saturating_sub(1));
+        self.results_state.select(Some(i));
+    }
+
+    fn select_top(&mut self) {
+        if !self.results.is_empty() {
+            self.results_state.select(Some(self.results.len() - 1));
+        }
+    }
+
+    fn select_bottom(&mut self) {
+        if !self.results.is_empty() {
+            self.results_state.select(Some(0));
+        }
+    }
+
+    fn toggle_mark(&mut self) {
+        let h = match self.selected() {
+            Some(h) => h.clone(),
+            None => return,
+        };
+
+        if self.marked.iter().any(|m| m.id == h.id) {
+            self.marked.retain(|m| m.id != h.id);
+        } else {
+            self.marked.push(h);
+        }
+
+        // move on, so that a run of results can be marked quickly
+        self.select_up();
+    }
+
+    fn selected(&self) -> Option<&History> {
+        self.results_state
+            .selected()
+            .and_then(|i| self.results.get(i))
+    }
+
+    // Enough lines to show the whole selected command wrapped to the given
+    // width, plus a line of details and the borders. Never more than max.
+    #[allow(clippy::clippy::cast_possible_truncation)]
+    fn preview_height(&self, width: u16, max: u16) -> u16 {
+        if !self.show_preview || self.inspecting.is_some() {
+            return 0;
+        }
+
+        let width = usize::from(std::cmp::max(width, 1));
+
+        let lines = self.selected().map_or(1, |h| {
+            h.command
+                .lines()
+                .map(|l| std::cmp::max((l.width() + width - 1) / width, 1))
+                .sum()
+        });
+
+        std::cmp::min(lines as u16 + 3, max)
+    }
+
+    fn render_preview<T: tui::backend::Backend>(
+        &self,
+        f: &mut tui::Frame<T>,
+        r: tui::layout::Rect,
+    ) {
+        let mut text = Text::default();
+
+        if let Some(h) = self.selected() {
+            text = Text::from(h.command.as_str());
+
+            let duration = display_duration(h);
+
+            let exit_style = Style::default().fg(if h.exit != 0 && h.duration != -1 {
+                Color::Red
+            } else {
+                Color::Green
+            });
+
+            text.extend(Text::from(Spans::from(vec![
+                Span::styled("cwd: ", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(h.cwd.as_str()),
+                Span::styled("  host: ", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(h.hostname.as_str()),
+                Span::styled(
+                    "  duration: ",
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(duration),
+                Span::styled("  exit: ", Style::default().add_modifier(Modifier::BOLD)),
+                Span::styled(h.exit.to_string(), exit_style),
+            ])));
+        }
+
+        let preview = Paragraph::new(text)
+            .block(Block::default().borders(Borders::ALL).title("Preview"))
+            .wrap(Wrap { trim: false });
+
+        f.render_widget(preview, r);
+    }
+
+    fn render_inspector<T: tui::backend::Backend>(
+        &self,
+        f: &mut tui::Frame<T>,
+        r: tui::layout::Rect,
+        (runs, failures): (i64, i64),
+    ) {
+        let mut text = Text::default();
+
+        if let Some(h) = self.selected() {
+            let bold = Style::default().add_modifier(Modifier::BOLD);
+            let field = |name: &'static str, value: String| {
+                Spans::from(vec![Span::styled(name, bold), Span::raw(value)])
+            };
+
+            let exit = if h.duration == -1 {
+                String::from("-")
+            } else {
+                h.exit.to_string()
+            };
+
+            let failure_rate = if runs > 0 {
+                format!("{}%", failures * 100 / runs)
+            } else {
+                String::from("-")
+            };
+
+            text = Text::from(h.command.as_str());
+            text.extend(Text::raw(""));
+            text.extend(Text::from(vec![
+                field(
+                    "time:         ",
+                    h.timestamp
+                        .with_timezone(&chrono::Local)
+                        .format("%Y-%m-%d %H:%M:%S")
+                        .to_string(),
+                ),
+                field("duration:     ", display_duration(h)),
+                field("exit:         ", exit),
+                field("cwd:          ", h.cwd.clone()),
+                field("host:         ", h.hostname.clone()),
+                field("session:      ", h.session.clone()),
+                field("times ran:    ", runs.to_string()),
+                field("failure rate: ", failure_rate),
+            ]));
+        }
+
+        let inspector = Paragraph::new(text)
+            .block(Block::default().borders(Borders::ALL).title("Inspector"))
+            .wrap(Wrap { trim: false });
+
+        f.render_widget(inspector, r);
+    }
+
     fn render_results<T: tui::backend::Backend>(
         &mut self,
         f: &mut tui::Frame<T>,
@@ -81,6 +710,20 @@ impl State {
             std::cmp::max(largest, i.0.len() + i.1.len())
         });
 
+        // commands still running have no exit code yet
+        let exits: Vec<String> = self
+            .results
+            .iter()
+            .map(|h| {
+                if h.duration == -1 {
+                    String::new()
+                } else {
+                    h.exit.to_string()
+                }
+            })
+            .collect();
+        let max_exit = exits.iter().map(String::len).max().unwrap_or(0);
+
         let results: Vec<ListItem> = self
             .results
             .iter()
@@ -88,7 +731,26 @@ impl State {
             .map(|(i, m)| {
                 let command = m.command.to_string().replace("\n", " ").replace("\t", " ");
 
-                let mut command = Span::raw(command);
+                let marked = self.marked.iter().any(|h| h.id == m.id);
+
+                let mut command = if marked {
+                    Span::styled(format!("* {}", command), Style::default().fg(Color::Yellow))
+                } else if self.pinned.contains(&m.command) {
+                    Span::styled(command, Style::default().fg(Color::Cyan))
+                } else {
+                    Span::raw(command)
+                };
+
+                if let Some(selected) = self.results_state.selected() {
+                    if selected == i {
+                        command.style =
+                            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD);
+                    }
+                }
+
+                if !self.show_details {
+                    return ListItem::new(Spans::from(vec![command]));
+                }
 
                 let (duration, mut ago) = durations[i].clone();
 
@@ -96,33 +758,38 @@ impl State {
                     ago = " ".to_owned() + ago.as_str();
                 }
 
-                let duration = Span::styled(
-                    duration,
-                    Style::default().fg(if m.exit == 0 || m.duration == -1 {
-                        Color::Green
-                    } else {
-                        Color::Red
-                    }),
-                );
+                let failed = m.exit != 0 && m.duration != -1;
+                let colour = if failed { Color::Red } else { Color::Green };
 
+                let duration = Span::styled(duration, Style::default().fg(colour));
                 let ago = Span::styled(ago, Style::default().fg(Color::Blue));
+                let exit = Span::styled(
+                    format!("{:>width$}", exits[i], width = max_exit),
+                    Style::default().fg(colour),
+                );
 
-                if let Some(selected) = self.results_state.selected() {
-                    if selected == i {
-                        command.style =
-                            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD);
-                    }
-                }
-
-                let spans =
-                    Spans::from(vec![duration, Span::raw(" "), ago, Span::raw(" "), command]);
+                let spans = Spans::from(vec![
+                    duration,
+                    Span::raw(" "),
+                    ago,
+                    Span::raw(" "),
+                    exit,
+                    Span::raw(" "),
+                    command,
+                ]);
 
                 ListItem::new(spans)
             })
             .collect();
 
+        let title = if self.marked.is_empty() {
+            String::from("History")
+        } else {
+            format!("History ({} marked)", self.marked.len())
+        };
+
         let results = List::new(results)
-            .block(Block::default().borders(Borders::ALL).title("History"))
+            .block(Block::default().borders(Borders::ALL).title(title))
             .start_corner(Corner::BottomLeft)
             .highlight_symbol(">> ");
 
@@ -130,12 +797,208 @@ impl State {
     }
 }
 
+// A small skim-style fuzzy matcher. Every character of the query has to
+// appear in the command, in order. Consecutive matches and matches at the
+// start of a word score higher, and gaps between matches score lower.
+fn fuzzy_score(query: &str, command: &str) -> Option<i64> {
+    const MATCH: i64 = 16;
+    const CONSECUTIVE: i64 = 8;
+    const WORD_START: i64 = 12;
+    const GAP: i64 = 1;
+
+    let mut query = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_ascii_lowercase())
+        .peekable();
+
+    let mut score = 0;
+    let mut gap = 0;
+    let mut matched = false;
+    let mut prev: Option<char> = None;
+
+    for c in command.chars() {
+        let q = match query.peek() {
+            Some(q) => *q,
+            None => break,
+        };
+
+        if c.to_ascii_lowercase() == q {
+            score += MATCH;
+
+            if matched && gap == 0 {
+                score += CONSECUTIVE;
+            }
+
+            if prev.map_or(true, |p| matches!(p, ' ' | '/' | '-' | '_' | '.' | '=')) {
+                score += WORD_START;
+            }
+
+            score -= gap * GAP;
+            gap = 0;
+            matched = true;
+
+            query.next();
+        } else if matched {
+            gap += 1;
+        }
+
+        prev = Some(c);
+    }
+
+    if query.peek().is_some() {
+        None
+    } else {
+        Some(score)
+    }
+}
+
+// The daemon already has the database open, so it is asked first. Falls back
+// to the database if the daemon is not running
+async fn db_search(
+    limit: Option<i64>,
+    search_mode: SearchMode,
+    filter_mode: FilterMode,
+    context: &Context,
+    query: &str,
+    dedup: bool,
+    db: &mut (impl Database + Send + Sync),
+) -> Result<Vec<History>> {
+    let mut client = match daemon::Client::connect().await {
+        Some(c) => c,
+        None => {
+            return db
+                .search(limit, search_mode, filter_mode, context, query, dedup)
+                .await
+        }
+    };
+
+    let request = Request::Search {
+        limit,
+        search_mode,
+        filter_mode,
+        context: context.clone(),
+        query: query.to_string(),
+        unique: dedup,
+    };
+
+    match client.request(&request).await? {
+        Response::History(h) => Ok(h),
+        r => Err(eyre!("unexpected response from the daemon: {:?}", r)),
+    }
+}
+
+// Fuzzy results are ranked here rather than in the database, so everything
+// that could match is loaded before the limit is applied. An empty query
+// matches everything, so there is nothing to rank.
+async fn search(
+    limit: Option<i64>,
+    search_mode: SearchMode,
+    filter_mode: FilterMode,
+    context: &Context,
+    query: &str,
+    dedup: bool,
+    db: &mut (impl Database + Send + Sync),
+) -> Result<Vec<History>> {
+    match search_mode {
+        SearchMode::Fuzzy if !query.is_empty() => {
+            let results =
+                db_search(None, search_mode, filter_mode, context, query, dedup, db).await?;
+
+            let mut results: Vec<(i64, History)> = results
+                .into_iter()
+                .filter_map(|h| fuzzy_score(query, h.command.as_str()).map(|s| (s, h)))
+                .collect();
+
+            // stable, so equally good matches stay most recent first
+            results.sort_by(|a, b| b.0.cmp(&a.0));
+
+            if let Some(limit) = limit {
+                results.truncate(usize::try_from(limit)?);
+            }
+
+            Ok(results.into_iter().map(|(_, h)| h).collect())
+        }
+        _ => db_search(limit, search_mode, filter_mode, context, query, dedup, db).await,
+    }
+}
+
+// The most recent command that starts with what has been typed, preferring
+// those ran in dir. This runs on every key press, so only asks for a few
+// results. sqlite's like ignores case, where the suggestion has to match
+// exactly.
+async fn suggest(
+    prefix: &str,
+    dir: String,
+    db: &mut (impl Database + Send + Sync),
+) -> Result<Option<String>> {
+    if prefix.is_empty() {
+        return Ok(None);
+    }
+
+    let mut context = current_context();
+    context.cwd = dir;
+
+    for filter_mode in &[FilterMode::Directory, FilterMode::Global] {
+        let results = db
+            .search(
+                Some(20),
+                SearchMode::Prefix,
+                *filter_mode,
+                &context,
+                prefix,
+                false,
+            )
+            .await?;
+
+        let best = results
+            .into_iter()
+            .find(|h| h.command.starts_with(prefix) && h.command != prefix);
+
+        if let Some(h) = best {
+            return Ok(Some(h.command));
+        }
+    }
+
+    Ok(None)
+}
+
 async fn query_results(app: &mut State, db: &mut (impl Database + Send + Sync)) -> Result<()> {
-    let results = match app.input.as_str() {
-        "" => db.list(Some(200), true).await?,
-        i => db.search(Some(200), i).await?,
+    // the filters aren't known to the query, so filter everything it returns
+    let limit = if app.filter.is_empty() {
+        Some(200)
+    } else {
+        None
     };
 
+    let mut results = search(
+        limit,
+        app.search_mode,
+        app.filter_mode,
+        &app.context,
+        app.input.as_str(),
+        app.dedup,
+        db,
+    )
+    .await?;
+
+    if !app.filter.is_empty() {
+        results.retain(|h| app.filter.matches(h));
+        results.truncate(200);
+    }
+
+    let pinned = db.pinned().await?;
+    app.pinned = pinned.iter().map(|h| h.command.clone()).collect();
+
+    // pinned commands go first, in place of their own place in the results
+    if app.input.is_empty() {
+        let rest = results
+            .into_iter()
+            .filter(|h| !app.pinned.contains(&h.command));
+
+        results = pinned.into_iter().chain(rest).take(200).collect();
+    }
+
     app.results = results;
 
     if app.results.is_empty() {
@@ -147,56 +1010,147 @@ async fn query_results(app: &mut State, db: &mut (impl Database + Send + Sync))
     Ok(())
 }
 
+// Navigation in vim's normal mode. Returns false if the key isn't bound
+fn vim_normal_handler(input: Key, app: &mut State) -> bool {
+    let pending_g = app.pending_g;
+    app.pending_g = false;
+
+    match input {
+        Key::Char('j') => app.select_down(),
+        Key::Char('k') => app.select_up(),
+        Key::Char('g') if pending_g => app.select_top(),
+        Key::Char('g') => app.pending_g = true,
+        Key::Char('G') => app.select_bottom(),
+        Key::Char('/') | Key::Char('i') | Key::Char('a') => app.keymap = KeymapMode::VimInsert,
+        _ => return false,
+    }
+
+    true
+}
+
 async fn key_handler(
     input: Key,
     db: &mut (impl Database + Send + Sync),
     app: &mut State,
 ) -> Option<String> {
+    // deleting needs two presses in a row, so any other key cancels it
+    let pending_delete = app.pending_delete.take();
+    app.copied = false;
+
+    // only a few of the usual keys work in the inspector, and they do the same
+    // as they would on the results
+    if app.inspecting.is_some() {
+        match input {
+            k if k == app.keys.exit || k == app.keys.inspect => {
+                app.inspecting = None;
+                return None;
+            }
+            k if k == app.keys.accept
+                || k == app.keys.edit
+                || k == app.keys.copy
+                || k == app.keys.delete => {}
+            _ => return None,
+        }
+    }
+
+    if app.keymap == KeymapMode::VimNormal && vim_normal_handler(input, app) {
+        return None;
+    }
+
     match input {
-        Key::Esc => return Some(String::from("")),
-        Key::Char('\n') => {
+        Key::Esc if app.keymap == KeymapMode::VimInsert => app.keymap = KeymapMode::VimNormal,
+        k if k == app.keys.exit => return Some(String::from("")),
+        k if k == app.keys.accept || k == app.keys.edit => {
             let i = app.results_state.selected().unwrap_or(0);
 
-            return Some(
-                app.results
-                    .get(i)
-                    .map_or("".to_string(), |h| h.command.clone()),
-            );
+            // with anything marked, only the marked results are accepted
+            let command = if app.marked.is_empty() {
+                app.results.get(i).map(|h| h.command.clone())
+            } else {
+                Some(join_marked(&app.marked, app.join_marked))
+            };
+
+            return Some(command.map_or("".to_string(), |command| {
+                if k == app.keys.accept && app.enter_accept {
+                    format!("{}{}", ACCEPT_PREFIX, command)
+                } else {
+                    command
+                }
+            }));
         }
-        Key::Char(c) => {
-            app.input.push(c);
+        k if k == app.keys.mark => app.toggle_mark(),
+        k if k == app.keys.inspect => {
+            if let Some(command) = app.selected().map(|h| h.command.clone()) {
+                app.inspecting = Some(db.command_stats(command.as_str()).await.unwrap());
+            }
+        }
+        k if k == app.keys.copy => {
+            let command = if app.marked.is_empty() {
+                app.selected().map(|h| h.command.clone())
+            } else {
+                Some(join_marked(&app.marked, app.join_marked))
+            };
+
+            if let Some(command) = command {
+                copy(command.as_str()).unwrap();
+                app.copied = true;
+            }
+        }
+        k if k == app.keys.toggle_session => {
+            app.filter_mode = match app.filter_mode {
+                FilterMode::Session => FilterMode::Global,
+                _ => FilterMode::Session,
+            };
             query_results(app, db).await.unwrap();
         }
-        Key::Backspace => {
-            app.input.pop();
+        k if k == app.keys.cycle_filter => {
+            app.filter_mode = app.filter_mode.next();
             query_results(app, db).await.unwrap();
         }
-        Key::Down => {
-            let i = match app.results_state.selected() {
-                Some(i) => {
-                    if i == 0 {
-                        0
-                    } else {
-                        i - 1
+        k if k == app.keys.delete => {
+            if let Some(id) = app.selected().map(|h| h.id.clone()) {
+                if app.confirm_delete && pending_delete.as_ref() != Some(&id) {
+                    app.pending_delete = Some(id);
+                } else {
+                    // the deletion is synced, like history delete
+                    db.delete(&[id.clone()]).await.unwrap();
+                    app.marked.retain(|h| h.id != id);
+                    app.inspecting = None;
+
+                    // stay where we were in the list, rather than jumping back
+                    let selected = app.results_state.selected();
+                    query_results(app, db).await.unwrap();
+
+                    if let (Some(i), false) = (selected, app.results.is_empty()) {
+                        app.results_state
+                            .select(Some(std::cmp::min(i, app.results.len() - 1)));
                     }
                 }
-                None => 0,
-            };
-            app.results_state.select(Some(i));
-        }
-        Key::Up => {
-            let i = match app.results_state.selected() {
-                Some(i) => {
-                    if i >= app.results.len() - 1 {
-                        app.results.len() - 1
-                    } else {
-                        i + 1
-                    }
+            }
+        }
+        k if k == app.keys.pin => {
+            if let Some(command) = app.selected().map(|h| h.command.clone()) {
+                if app.pinned.contains(&command) {
+                    db.unpin(command.as_str()).await.unwrap();
+                } else {
+                    db.pin(command.as_str()).await.unwrap();
                 }
-                None => 0,
-            };
-            app.results_state.select(Some(i));
+
+                query_results(app, db).await.unwrap();
+            }
+        }
+        // typing does nothing in normal mode, apart from the bindings above
+        Key::Char(_) | Key::Backspace if app.keymap == KeymapMode::VimNormal => {}
+        Key::Char(c) => {
+            app.input.push(c);
+            query_results(app, db).await.unwrap();
+        }
+        Key::Backspace => {
+            app.input.pop();
+            query_results(app, db).await.unwrap();
         }
+        k if k == app.keys.down => app.select_down(),
+        k if k == app.keys.up => app.select_up(),
         _ => {}
     };
 
@@ -205,6 +1159,10 @@ async fn key_handler(
 
 #[allow(clippy::clippy::cast_possible_truncation)]
 fn draw<T: Backend>(f: &mut Frame<'_, T>, history_count: i64, app: &mut State) {
+    // inside the margin and the borders, and at most a third of the screen
+    let size = f.size();
+    let preview_height = app.preview_height(size.width.saturating_sub(4), size.height / 3);
+
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .margin(1)
@@ -212,11 +1170,12 @@ fn draw<T: Backend>(f: &mut Frame<'_, T>, history_count: i64, app: &mut State) {
             [
                 Constraint::Length(2),
                 Constraint::Min(1),
+                Constraint::Length(preview_height),
                 Constraint::Length(3),
             ]
             .as_ref(),
         )
-        .split(f.size());
+        .split(size);
 
     let top_chunks = Layout::default()
         .direction(Direction::Horizontal)
@@ -238,17 +1197,63 @@ fn draw<T: Backend>(f: &mut Frame<'_, T>, history_count: i64, app: &mut State) {
         Style::default().add_modifier(Modifier::BOLD),
     )));
 
-    let help = vec![
-        Span::raw("Press "),
-        Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
-        Span::raw(" to exit."),
-    ];
+    let help = if app.pending_delete.is_some() {
+        vec![
+            Span::raw("Press "),
+            Span::styled(
+                key_name(app.keys.delete),
+                Style::default().add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" again to delete."),
+        ]
+    } else if app.copied {
+        vec![Span::raw("Copied to the clipboard.")]
+    } else if app.inspecting.is_some() {
+        vec![
+            Span::raw("Press "),
+            Span::styled(
+                key_name(app.keys.exit),
+                Style::default().add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" to go back, "),
+            Span::styled(
+                key_name(app.keys.delete),
+                Style::default().add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" to delete, "),
+            Span::styled(
+                key_name(app.keys.copy),
+                Style::default().add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" to copy."),
+        ]
+    } else {
+        vec![
+            Span::raw("Press "),
+            Span::styled(
+                key_name(app.keys.exit),
+                Style::default().add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" to exit, "),
+            Span::styled(
+                key_name(app.keys.cycle_filter),
+                Style::default().add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" to change filter."),
+        ]
+    };
 
     let help = Text::from(Spans::from(help));
     let help = Paragraph::new(help);
 
+    let input_title = match app.keymap {
+        KeymapMode::Emacs => "Query",
+        KeymapMode::VimNormal => "Query [NORMAL]",
+        KeymapMode::VimInsert => "Query [INSERT]",
+    };
+
     let input = Paragraph::new(app.input.clone())
-        .block(Block::default().borders(Borders::ALL).title("Query"));
+        .block(Block::default().borders(Borders::ALL).title(input_title));
 
     let stats = Paragraph::new(Text::from(Span::raw(format!(
         "history count: {}",
@@ -256,154 +1261,382 @@ fn draw<T: Backend>(f: &mut Frame<'_, T>, history_count: i64, app: &mut State) {
     ))))
     .alignment(Alignment::Right);
 
+    let filter = Paragraph::new(Text::from(Span::raw(format!(
+        "filter: {}",
+        app.filter_mode.as_str(),
+    ))))
+    .alignment(Alignment::Right);
+
     f.render_widget(title, top_left_chunks[0]);
     f.render_widget(help, top_left_chunks[1]);
 
-    app.render_results(f, chunks[1]);
+    match app.inspecting {
+        Some(stats) => app.render_inspector(f, chunks[1], stats),
+        None => app.render_results(f, chunks[1]),
+    }
+
+    if preview_height > 0 {
+        app.render_preview(f, chunks[2]);
+    }
+
     f.render_widget(stats, top_right_chunks[0]);
-    f.render_widget(input, chunks[2]);
+    f.render_widget(filter, top_right_chunks[1]);
+    f.render_widget(input, chunks[3]);
 
     f.set_cursor(
         // Put cursor past the end of the input text
-        chunks[2].x + app.input.width() as u16 + 1,
+        chunks[3].x + app.input.width() as u16 + 1,
         // Move one line down, from the border to the input line
-        chunks[2].y + 1,
+        chunks[3].y + 1,
     );
 }
 
+// Make room for an inline search on the lines below the cursor, scrolling the
+// terminal up if there aren't enough of them
+fn inline_area(stdout: &mut impl Write, height: u16) -> Result<Rect> {
+    let (width, rows) = termion::terminal_size()?;
+    let height = std::cmp::max(std::cmp::min(height, rows.saturating_sub(1)), 2);
+
+    // moving down a line at a time scrolls the terminal when at the bottom
+    write!(
+        stdout,
+        "{}{}",
+        "\n".repeat(usize::from(height)),
+        cursor::Up(height - 1)
+    )?;
+    stdout.flush()?;
+
+    // 1 based, where the rect is not
+    let (_, row) = stdout.cursor_pos()?;
+
+    Ok(Rect::new(0, row - 1, width, height))
+}
+
 // this is a big blob of horrible! clean it up!
 // for now, it works. But it'd be great if it were more easily readable, and
 // modular. I'd like to add some more stats and stuff at some point
 #[allow(clippy::clippy::cast_possible_truncation)]
 async fn select_history(
     query: &[String],
+    settings: &Settings,
+    search_mode: SearchMode,
+    filter_mode: FilterMode,
+    filters: &Filters,
+    dedup: bool,
     db: &mut (impl Database + Send + Sync),
 ) -> Result<String> {
+    // before taking over the terminal, so that any error can be seen
+    let keys = Keys::new(&settings.keys)?;
+    let mut filter = filters.resolve(settings, &*db).await?;
+
+    // the filter mode sees to --session, so that it can be changed
+    filter.session = None;
+
     let stdout = stdout().into_raw_mode()?;
-    let stdout = MouseTerminal::from(stdout);
-    let stdout = AlternateScreen::from(stdout);
+    let mut stdout = MouseTerminal::from(stdout);
+
+    // inline, the search only takes over a few lines below the prompt
+    let inline = if settings.inline_height > 0 {
+        Some(inline_area(&mut stdout, settings.inline_height)?)
+    } else {
+        None
+    };
+
+    let stdout: Box<dyn Write> = if inline.is_some() {
+        Box::new(stdout)
+    } else {
+        Box::new(AlternateScreen::from(stdout))
+    };
+
     let backend = TermionBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+    let mut terminal = match inline {
+        Some(area) => Terminal::with_options(
+            backend,
+            TerminalOptions {
+                viewport: Viewport::fixed(area),
+            },
+        )?,
+        None => Terminal::new(backend)?,
+    };
 
     // Setup event handlers
     let events = Events::new();
 
     let mut app = State {
         input: query.join(" "),
+        search_mode,
+        filter_mode,
+        context: current_context(),
         results: Vec::new(),
         results_state: ListState::default(),
+        show_details: settings.show_details,
+        show_preview: settings.show_preview,
+        keymap: settings.keymap_mode,
+        pending_g: false,
+        keys,
+        enter_accept: settings.enter_accept,
+        confirm_delete: settings.confirm_delete,
+        pending_delete: None,
+        filter,
+        dedup,
+        marked: Vec::new(),
+        pinned: Vec::new(),
+        join_marked: settings.join_marked,
+        copied: false,
+        inspecting: None,
     };
 
     query_results(&mut app, db).await?;
 
-    loop {
+    let output = loop {
         let history_count = db.history_count().await?;
         // Handle input
         if let Event::Input(input) = events.next()? {
             if let Some(output) = key_handler(input, db, &mut app).await {
-                return Ok(output);
+                break output;
             }
         }
 
         terminal.draw(|f| draw(f, history_count, &mut app))?;
+    };
+
+    // leave the terminal as we found it, with the cursor back on the prompt
+    if let Some(area) = inline {
+        let stdout = terminal.backend_mut();
+        write!(
+            stdout,
+            "{}{}{}",
+            cursor::Goto(1, area.y + 1),
+            clear::AfterCursor,
+            cursor::Up(1)
+        )?;
+        stdout.flush()?;
     }
+
+    Ok(output)
 }
 
-// This is supposed to more-or-less mirror the command line version, so ofc
-// it is going to have a lot of args
-#[allow(clippy::clippy::clippy::too_many_arguments)]
-pub async fn run(
-    cwd: Option<String>,
-    exit: Option<i64>,
-    interactive: bool,
-    human: bool,
-    exclude_exit: Option<i64>,
-    exclude_cwd: Option<String>,
-    before: Option<String>,
-    after: Option<String>,
-    query: &[String],
-    db: &mut (impl Database + Send + Sync),
-) -> Result<()> {
-    let dir = if let Some(cwd) = cwd {
-        if cwd == "." {
-            let current = std::env::current_dir()?;
-            let current = current.as_os_str();
-            let current = current.to_str().unwrap();
-
-            Some(current.to_owned())
+// Results are most recent first, and printed the other way around. The offset
+// and limit count back from the most recent
+fn page(
+    results: Vec<History>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    reverse: bool,
+) -> Vec<History> {
+    let mut results: Vec<History> = results
+        .into_iter()
+        .skip(offset.unwrap_or(0))
+        .take(limit.unwrap_or(usize::MAX))
+        .collect();
+
+    if reverse {
+        results.reverse();
+    }
+
+    results
+}
+
+impl Cmd {
+    pub async fn run(
+        &self,
+        settings: &Settings,
+        db: &mut (impl Database + Send + Sync),
+    ) -> Result<()> {
+        let search_mode = self.search_mode.unwrap_or(settings.search_mode);
+        let dedup = self.dedup.unwrap_or(settings.dedup);
+        let filter_mode = if self.filters.session {
+            FilterMode::Session
         } else {
-            Some(cwd)
+            FilterMode::Global
+        };
+
+        if self.suggest {
+            let dir = match self.filters.dir()? {
+                Some(dir) => dir,
+                None => std::env::current_dir()?.display().to_string(),
+            };
+
+            let prefix = self.prefix.as_deref().unwrap_or_default();
+
+            if let Some(command) = suggest(prefix, dir, db).await? {
+                println!("{}", command);
+            }
+
+            return Ok(());
         }
-    } else {
-        None
-    };
 
-    if interactive {
-        let item = select_history(query, db).await?;
-        eprintln!("{}", item);
-    } else {
-        let results = db.search(None, query.join(" ").as_str()).await?;
+        if self.interactive {
+            let item = select_history(
+                &self.query,
+                settings,
+                search_mode,
+                filter_mode,
+                &self.filters,
+                dedup,
+                db,
+            )
+            .await?;
+            eprintln!("{}", item);
+        } else {
+            let results = search(
+                None,
+                search_mode,
+                filter_mode,
+                &current_context(),
+                self.query.join(" ").as_str(),
+                dedup,
+                db,
+            )
+            .await?;
+            let results = self.filters.apply(&results, settings, &*db).await?;
 
-        // TODO: This filtering would be better done in the SQL query, I just
-        // need a nice way of building queries.
-        let results: Vec<History> = results
-            .iter()
-            .filter(|h| {
-                if let Some(exit) = exit {
-                    if h.exit != exit {
-                        return false;
-                    }
-                }
+            let results = page(results, self.offset, self.limit, self.reverse);
 
-                if let Some(exit) = exclude_exit {
-                    if h.exit == exit {
-                        return false;
-                    }
-                }
+            super::history::print_formatted(
+                &results,
+                ListMode::from_flags(self.human, self.cmd_only),
+                self.format.as_deref(),
+                self.print0,
+            )?;
+        }
 
-                if let Some(cwd) = &exclude_cwd {
-                    if h.cwd.as_str() == cwd.as_str() {
-                        return false;
-                    }
-                }
+        Ok(())
+    }
+}
 
-                if let Some(cwd) = &dir {
-                    if h.cwd.as_str() != cwd.as_str() {
-                        return false;
-                    }
-                }
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
 
-                if let Some(before) = &before {
-                    let before = chrono_english::parse_date_string(
-                        before.as_str(),
-                        Utc::now(),
-                        chrono_english::Dialect::Uk,
-                    );
+    use atuin_client::history::History;
+    use atuin_client::settings::JoinMode;
 
-                    if before.is_err() || h.timestamp.gt(&before.unwrap()) {
-                        return false;
-                    }
-                }
+    use super::{fuzzy_score, join_marked, osc52, page, DurationRange, Filter};
+    use crate::command::history::print_order;
 
-                if let Some(after) = &after {
-                    let after = chrono_english::parse_date_string(
-                        after.as_str(),
-                        Utc::now(),
-                        chrono_english::Dialect::Uk,
-                    );
+    #[test]
+    fn test_fuzzy_score() {
+        assert!(fuzzy_score("gcm", "git commit -m 'beep'").is_some());
+        assert!(fuzzy_score("GCM", "git commit -m 'beep'").is_some());
+        assert!(fuzzy_score("gcm", "cargo build").is_none());
+        assert!(fuzzy_score("mcg", "git commit -m 'beep'").is_none());
 
-                    if after.is_err() || h.timestamp.lt(&after.unwrap()) {
-                        return false;
-                    }
-                }
+        // word starts and consecutive matches are preferred
+        assert!(
+            fuzzy_score("gcm", "git commit -m 'beep'").unwrap()
+                > fuzzy_score("gcm", "agacam").unwrap()
+        );
+        assert!(fuzzy_score("carg", "cargo").unwrap() > fuzzy_score("carg", "cxaxrxg").unwrap());
+    }
 
-                true
+    #[test]
+    fn test_osc52() {
+        if std::env::var("TMUX").is_err() {
+            assert_eq!(osc52("ls -la"), "\x1b]52;c;bHMgLWxh\x07");
+        }
+    }
+
+    #[test]
+    fn test_join_marked() {
+        let history = |ts: i64, command: &str| {
+            History::new(
+                Utc.timestamp(ts, 0),
+                command.to_string(),
+                "/".to_string(),
+                0,
+                0,
+                None,
+                None,
+            )
+        };
+
+        // marked newest first, but joined in the order they were ran
+        let marked = vec![history(20, "cargo test"), history(10, "cargo build")];
+
+        assert_eq!(
+            join_marked(&marked, JoinMode::And),
+            "cargo build && cargo test"
+        );
+        assert_eq!(
+            join_marked(&marked, JoinMode::Newline),
+            "cargo build\ncargo test"
+        );
+    }
+
+    #[test]
+    fn test_filter_tagged() {
+        let h = |command: &str| {
+            History::new(
+                Utc::now(),
+                command.into(),
+                "/".into(),
+                0,
+                1,
+                Some("session".into()),
+                Some("host:user".into()),
+            )
+        };
+
+        let tagged = h("kubectl apply");
+        let untagged = h("kubectl get pods");
+
+        let filter = Filter {
+            exit: None,
+            exclude_exit: None,
+            dir: None,
+            exclude_cwd: None,
+            session: None,
+            durations: DurationRange {
+                min: None,
+                max: None,
+            },
+            host: None,
+            user: None,
+            before: None,
+            after: None,
+            tagged: Some(vec![tagged.id.clone()].into_iter().collect()),
+        };
+
+        assert!(!filter.is_empty());
+        assert!(filter.matches(&tagged));
+        assert!(!filter.matches(&untagged));
+    }
+
+    #[test]
+    fn test_page_order() {
+        // most recent first, as the search returns them
+        let results: Vec<History> = (1..=5)
+            .rev()
+            .map(|i| {
+                History::new(
+                    Utc.timestamp(i, 0),
+                    format!("docker {}", i),
+                    "/".into(),
+                    0,
+                    1,
+                    Some("session".into()),
+                    Some("host:user".into()),
+                )
             })
-            .map(std::borrow::ToOwned::to_owned)
             .collect();
 
-        super::history::print_list(&results, human);
-    }
+        let printed = |h: &[History]| {
+            print_order(h)
+                .map(|h| h.command.clone())
+                .collect::<Vec<String>>()
+        };
 
-    Ok(())
+        // the most recent, newest last
+        assert_eq!(
+            printed(&page(results.clone(), None, Some(2), false)),
+            vec!["docker 4", "docker 5"]
+        );
+
+        // the two before those, newest first
+        assert_eq!(
+            printed(&page(results, Some(2), Some(2), true)),
+            vec!["docker 3", "docker 2"]
+        );
+    }
 }
@@ -1,8 +1,13 @@
-use eyre::Result;
+use std::io::Write;
+
+use eyre::{eyre, Result};
 use structopt::StructOpt;
+use tabwriter::TabWriter;
 
-use atuin_server::launch;
+use atuin_common::utils::uuid_v4;
+use atuin_server::database::{Database, Postgres, Sqlite};
 use atuin_server::settings::Settings;
+use atuin_server::{auto_migrate, launch, password};
 
 #[derive(StructOpt)]
 pub enum Cmd {
@@ -17,6 +22,147 @@ pub enum Cmd {
         #[structopt(about = "specify the port to bind", long, short)]
         port: Option<u16>,
     },
+
+    #[structopt(about = "manage the accounts on this server")]
+    Users(UsersCmd),
+
+    #[structopt(
+        about = "create an invite, allowing someone to register when registration is closed"
+    )]
+    Invite,
+
+    #[structopt(about = "apply any pending database migrations")]
+    Migrate {
+        #[structopt(
+            long,
+            about = "only list the pending migrations, failing if there are any"
+        )]
+        check: bool,
+    },
+}
+
+async fn migrate(db: &(impl Database + Send + Sync), check: bool) -> Result<()> {
+    let pending = db.pending_migrations().await?;
+
+    if check {
+        for m in &pending {
+            println!("{}", m);
+        }
+
+        if pending.is_empty() {
+            println!("The database is up to date");
+            return Ok(());
+        }
+
+        return Err(eyre!("{} migrations are pending", pending.len()));
+    }
+
+    db.migrate().await?;
+
+    for m in &pending {
+        println!("Applied {}", m);
+    }
+
+    println!("The database is up to date");
+
+    Ok(())
+}
+
+#[derive(StructOpt)]
+pub enum UsersCmd {
+    #[structopt(about = "list all users", aliases=&["l", "ls"])]
+    List,
+
+    #[structopt(about = "delete a user, along with all of their history")]
+    Delete { username: String },
+
+    #[structopt(about = "mark a user's email address as verified, so that they can sync")]
+    Verify { username: String },
+
+    #[structopt(
+        name = "disable-2fa",
+        about = "turn off 2fa for a user that has lost their authenticator"
+    )]
+    DisableTwoFactor { username: String },
+
+    #[structopt(about = "set a new password for a user, and log out all of their sessions")]
+    ResetPassword {
+        username: String,
+
+        #[structopt(long, short, about = "the new password. if not set, one is generated")]
+        password: Option<String>,
+    },
+}
+
+impl UsersCmd {
+    async fn run(&self, settings: &Settings, db: &(impl Database + Send + Sync)) -> Result<()> {
+        match self {
+            Self::List => {
+                let mut writer = TabWriter::new(std::io::stdout()).padding(2);
+                writeln!(writer, "ID\tUSERNAME\tEMAIL\tVERIFIED\tHISTORY")?;
+
+                for user in db.list_users().await? {
+                    let count = db.count_history(&user).await?;
+                    let verified = if user.verified_at.is_some() {
+                        "yes"
+                    } else {
+                        "no"
+                    };
+
+                    writeln!(
+                        writer,
+                        "{}\t{}\t{}\t{}\t{}",
+                        user.id, user.username, user.email, verified, count
+                    )?;
+                }
+
+                writer.flush()?;
+
+                Ok(())
+            }
+
+            Self::Delete { username } => {
+                let user = db.get_user(username.clone()).await?;
+                db.delete_user(&user).await?;
+
+                println!("Deleted user {}", user.username);
+
+                Ok(())
+            }
+
+            Self::Verify { username } => {
+                let user = db.get_user(username.clone()).await?;
+                db.set_user_verified(&user).await?;
+
+                println!("Verified {}", user.username);
+
+                Ok(())
+            }
+
+            Self::DisableTwoFactor { username } => {
+                let user = db.get_user(username.clone()).await?;
+                db.set_user_totp(&user, None, false).await?;
+
+                println!("Disabled 2fa for {}", user.username);
+
+                Ok(())
+            }
+
+            Self::ResetPassword { username, password } => {
+                let user = db.get_user(username.clone()).await?;
+
+                let password = password.clone().unwrap_or_else(uuid_v4);
+                let hashed = password::hash(&settings.password_hashing, password.as_str()).await?;
+
+                db.update_user_password(&user, hashed.as_str()).await?;
+                db.delete_user_sessions(&user).await?;
+
+                println!("Password for {} reset to {}", user.username, password);
+
+                Ok(())
+            }
+        }
+    }
 }
 
 impl Cmd {
@@ -30,6 +176,49 @@ impl Cmd {
 
                 launch(settings, host, port).await
             }
+
+            Self::Invite => {
+                let token = uuid_v4();
+                let uri = settings.db_uri.as_str();
+
+                if settings.is_sqlite() {
+                    let db = Sqlite::new(uri).await?;
+                    auto_migrate(settings, &db).await?;
+                    db.add_invite(token.as_str()).await?;
+                } else {
+                    let db = Postgres::new(uri).await?;
+                    auto_migrate(settings, &db).await?;
+                    db.add_invite(token.as_str()).await?;
+                }
+
+                println!("{}", token);
+
+                Ok(())
+            }
+
+            Self::Users(users) => {
+                let uri = settings.db_uri.as_str();
+
+                if settings.is_sqlite() {
+                    let db = Sqlite::new(uri).await?;
+                    auto_migrate(settings, &db).await?;
+                    users.run(settings, &db).await
+                } else {
+                    let db = Postgres::new(uri).await?;
+                    auto_migrate(settings, &db).await?;
+                    users.run(settings, &db).await
+                }
+            }
+
+            Self::Migrate { check } => {
+                let uri = settings.db_uri.as_str();
+
+                if settings.is_sqlite() {
+                    migrate(&Sqlite::new(uri).await?, *check).await
+                } else {
+                    migrate(&Postgres::new(uri).await?, *check).await
+                }
+            }
         }
     }
 }
@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
 
 use chrono::prelude::*;
 use chrono::Duration;
@@ -6,6 +7,7 @@ use chrono_english::{parse_date_string, Dialect};
 
 use cli_table::{format::Justify, print_stdout, Cell, Style, Table};
 use eyre::{eyre, Result};
+use serde_derive::Serialize;
 use structopt::StructOpt;
 
 use atuin_client::database::Database;
@@ -13,49 +15,344 @@ use atuin_client::history::History;
 use atuin_client::settings::Settings;
 
 #[derive(StructOpt)]
-pub enum Cmd {
+pub struct Cmd {
     #[structopt(
-        about="compute statistics for all of time",
-        aliases=&["d", "da"],
+        about = "the time to compute statistics for, such as \"today\", \"last week\", \"last 30 days\", \"day <date>\" or \"all\". defaults to all of time. \"wrapped [year]\" reviews a whole year"
     )]
-    All,
+    range: Vec<String>,
+
+    #[structopt(long, about = "only include history from this date onwards")]
+    from: Option<String>,
+
+    #[structopt(long, about = "only include history from before this date")]
+    to: Option<String>,
+
+    #[structopt(long, about = "break the statistics down by day, week or month")]
+    period: Option<Period>,
+
+    #[structopt(
+        long,
+        short,
+        default_value = "10",
+        about = "how many top commands to show"
+    )]
+    count: usize,
+
+    #[structopt(
+        long = "prefix-depth",
+        about = "count commands by their first few words, so that \"git commit\" and \"git push\" are counted apart with 2"
+    )]
+    prefix_depth: Option<usize>,
+
+    #[structopt(
+        long,
+        default_value = "table",
+        about = "how to print the statistics, table or json"
+    )]
+    format: Format,
 
     #[structopt(
-        about="compute statistics for a single day",
-        aliases=&["d", "da"],
+        long,
+        about = "draw a chart of how many commands ran each day, or --period"
     )]
-    Day { words: Vec<String> },
+    graph: bool,
+}
+
+// Also taken by atuin sync status, which prints text rather than a table
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Table,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "table" | "text" => Ok(Self::Table),
+            "json" => Ok(Self::Json),
+            _ => Err(eyre!("invalid format {}, expected table or json", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Period {
+    Day,
+    Week,
+    Month,
+}
+
+impl FromStr for Period {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "day" => Ok(Self::Day),
+            "week" => Ok(Self::Week),
+            "month" => Ok(Self::Month),
+            _ => Err(eyre!("invalid period {}, expected day, week or month", s)),
+        }
+    }
+}
+
+impl Period {
+    // The date the period containing this time starts on
+    fn start(self, time: DateTime<Local>) -> Date<Local> {
+        let date = time.date();
+
+        match self {
+            Self::Day => date,
+            Self::Week => date - Duration::days(i64::from(date.weekday().num_days_from_monday())),
+            Self::Month => date.with_day(1).unwrap(),
+        }
+    }
+
+    fn next(self, start: Date<Local>) -> Date<Local> {
+        match self {
+            Self::Day => start + Duration::days(1),
+            Self::Week => start + Duration::days(7),
+            Self::Month => add_months(start, 1),
+        }
+    }
+
+    fn label(self, start: Date<Local>) -> String {
+        match self {
+            Self::Day | Self::Week => start.format("%Y-%m-%d").to_string(),
+            Self::Month => start.format("%Y-%m").to_string(),
+        }
+    }
+}
+
+// The first day of the month, some number of months away
+#[allow(clippy::clippy::cast_possible_wrap, clippy::clippy::cast_sign_loss)]
+fn add_months(date: Date<Local>, months: i32) -> Date<Local> {
+    let months = date.year() * 12 + date.month0() as i32 + months;
+
+    Local.ymd(months.div_euclid(12), months.rem_euclid(12) as u32 + 1, 1)
+}
+
+// Dates can be given in words, such as "yesterday 3pm" or "2 weeks ago", or
+// as RFC3339
+pub fn parse_date(words: &str, settings: &Settings) -> Result<DateTime<Local>> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(words.trim()) {
+        return Ok(date.with_timezone(&Local));
+    }
+
+    let dialect = match settings.dialect.to_lowercase().as_str() {
+        "uk" => Dialect::Uk,
+        _ => Dialect::Us,
+    };
+
+    parse_date_string(words, Local::now(), dialect).map_err(|e| {
+        eyre!(
+            "could not understand the date {:?} ({}), try something like \"yesterday 3pm\", \"2 weeks ago\" or 2021-04-01T15:00:00Z",
+            words,
+            e
+        )
+    })
 }
 
-fn compute_stats(history: &[History]) -> Result<()> {
+// The start and end of a range given in words, or None for all of time
+fn parse_range(
+    words: &[String],
+    settings: &Settings,
+) -> Result<Option<(DateTime<Local>, DateTime<Local>)>> {
+    let now = Local::now();
+    let today = now.date();
+    let week = today - Duration::days(i64::from(today.weekday().num_days_from_monday()));
+    let month = today.with_day(1).unwrap();
+    let year = Local.ymd(today.year(), 1, 1);
+
+    let words: Vec<String> = words.iter().map(|w| w.to_lowercase()).collect();
+    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+
+    let (start, end) = match words.as_slice() {
+        [] | ["all"] => return Ok(None),
+        ["today"] => (today, today + Duration::days(1)),
+        ["this", "week"] => (week, week + Duration::days(7)),
+        ["last", "week"] => (week - Duration::days(7), week),
+        ["this", "month"] => (month, add_months(month, 1)),
+        ["last", "month"] => (add_months(month, -1), month),
+        ["this", "year"] => (year, Local.ymd(today.year() + 1, 1, 1)),
+        ["last", "year"] => (Local.ymd(today.year() - 1, 1, 1), year),
+
+        // rolling, up to now, eg "last 30 days"
+        ["last", n, unit] => {
+            let n: i64 = n
+                .parse()
+                .map_err(|_| eyre!("invalid range: {}", words.join(" ")))?;
+
+            let start = match unit.trim_end_matches('s') {
+                "day" => now - Duration::days(n),
+                "week" => now - Duration::weeks(n),
+                "month" => now - Duration::days(n * 30),
+                _ => return Err(eyre!("invalid range: {}", words.join(" "))),
+            };
+
+            return Ok(Some((start, now)));
+        }
+
+        // a single day, which defaults to yesterday for compatibility
+        ["day"] => (today - Duration::days(1), today),
+        ["day", date @ ..] | date => {
+            let start = parse_date(date.join(" ").as_str(), settings)?;
+            return Ok(Some((start, start + Duration::days(1))));
+        }
+    };
+
+    Ok(Some((start.and_hms(0, 0, 0), end.and_hms(0, 0, 0))))
+}
+
+// What a command is counted as. By default that's the whole command, but it
+// can be just the first few words
+fn command_key(command: &str, prefix_depth: Option<usize>) -> String {
+    match prefix_depth {
+        Some(depth) => command
+            .split_whitespace()
+            .take(depth)
+            .collect::<Vec<&str>>()
+            .join(" "),
+        None => command.trim().to_string(),
+    }
+}
+
+fn count_commands(history: &[&History], prefix_depth: Option<usize>) -> HashMap<String, i64> {
     let mut commands = HashMap::<String, i64>::new();
 
     for i in history {
-        *commands.entry(i.command.clone()).or_default() += 1;
+        *commands
+            .entry(command_key(i.command.as_str(), prefix_depth))
+            .or_default() += 1;
+    }
+
+    commands
+}
+
+// The most used commands first, and alphabetical when used as often
+fn top_commands(commands: &HashMap<String, i64>, count: usize) -> Vec<(&str, i64)> {
+    let mut top: Vec<(&str, i64)> = commands.iter().map(|(c, n)| (c.as_str(), *n)).collect();
+    top.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    top.truncate(count);
+
+    top
+}
+
+#[derive(Serialize)]
+struct TopCommand {
+    command: String,
+    ran: i64,
+}
+
+#[derive(Serialize)]
+struct Summary {
+    commands_ran: usize,
+    unique_commands_ran: usize,
+    most_used_command: Option<String>,
+    top_commands: Vec<TopCommand>,
+}
+
+#[derive(Serialize)]
+struct PeriodSummary {
+    period: String,
+    commands_ran: usize,
+    unique_commands_ran: usize,
+    most_used_command: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Report {
+    #[serde(flatten)]
+    summary: Summary,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    periods: Option<Vec<PeriodSummary>>,
+}
+
+fn compute_stats(history: &[History], count: usize, prefix_depth: Option<usize>) -> Summary {
+    let all: Vec<&History> = history.iter().collect();
+    let commands = count_commands(&all, prefix_depth);
+
+    Summary {
+        commands_ran: history.len(),
+        unique_commands_ran: commands.len(),
+        most_used_command: top_commands(&commands, 1).first().map(|c| c.0.to_string()),
+        top_commands: top_commands(&commands, count)
+            .into_iter()
+            .map(|(command, ran)| TopCommand {
+                command: command.to_string(),
+                ran,
+            })
+            .collect(),
+    }
+}
+
+// Every period from the first history to the last, including any without
+// history, so that gaps show up in graphs
+fn compute_period_stats(
+    history: &[History],
+    period: Period,
+    prefix_depth: Option<usize>,
+) -> Vec<PeriodSummary> {
+    let mut periods = BTreeMap::<Date<Local>, Vec<&History>>::new();
+
+    for i in history {
+        let start = period.start(i.timestamp.with_timezone(&Local));
+        periods.entry(start).or_default().push(i);
     }
 
-    let most_common_command = commands.iter().max_by(|a, b| a.1.cmp(b.1));
+    let (first, last) = match (periods.keys().next(), periods.keys().last()) {
+        (Some(first), Some(last)) => (*first, *last),
+        _ => return Vec::new(),
+    };
+
+    let mut summaries = Vec::new();
+    let mut start = first;
+
+    while start <= last {
+        let history = periods.get(&start).map_or(&[][..], Vec::as_slice);
+        let commands = count_commands(history, prefix_depth);
+
+        summaries.push(PeriodSummary {
+            period: period.label(start),
+            commands_ran: history.len(),
+            unique_commands_ran: commands.len(),
+            most_used_command: top_commands(&commands, 1).first().map(|c| c.0.to_string()),
+        });
 
-    if most_common_command.is_none() {
-        return Err(eyre!("No commands found"));
+        start = period.next(start);
     }
 
+    summaries
+}
+
+fn print_summary(summary: &Summary) -> Result<()> {
+    let most_common_command = match &summary.most_used_command {
+        Some(c) => c.as_str(),
+        None => return Err(eyre!("No commands found")),
+    };
+
     let table = vec![
         vec![
             "Most used command".cell(),
-            most_common_command
-                .unwrap()
-                .0
-                .cell()
-                .justify(Justify::Right),
+            most_common_command.cell().justify(Justify::Right),
         ],
         vec![
             "Commands ran".cell(),
-            history.len().to_string().cell().justify(Justify::Right),
+            summary
+                .commands_ran
+                .to_string()
+                .cell()
+                .justify(Justify::Right),
         ],
         vec![
             "Unique commands ran".cell(),
-            commands.len().to_string().cell().justify(Justify::Right),
+            summary
+                .unique_commands_ran
+                .to_string()
+                .cell()
+                .justify(Justify::Right),
         ],
     ]
     .table()
@@ -67,43 +364,360 @@ fn compute_stats(history: &[History]) -> Result<()> {
 
     print_stdout(table)?;
 
+    if summary.top_commands.is_empty() {
+        return Ok(());
+    }
+
+    let table = summary
+        .top_commands
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            vec![
+                (i + 1).to_string().cell().justify(Justify::Right),
+                c.command.as_str().cell(),
+                c.ran.to_string().cell().justify(Justify::Right),
+            ]
+        })
+        .collect::<Vec<_>>()
+        .table()
+        .title(vec![
+            "#".cell().bold(true),
+            "Command".cell().bold(true),
+            "Ran".cell().bold(true),
+        ])
+        .bold(true);
+
+    print_stdout(table)?;
+
     Ok(())
 }
 
+fn print_periods(periods: &[PeriodSummary]) -> Result<()> {
+    let table = periods
+        .iter()
+        .map(|p| {
+            vec![
+                p.period.as_str().cell(),
+                p.commands_ran.to_string().cell().justify(Justify::Right),
+                p.unique_commands_ran
+                    .to_string()
+                    .cell()
+                    .justify(Justify::Right),
+                p.most_used_command.as_deref().unwrap_or("").cell(),
+            ]
+        })
+        .collect::<Vec<_>>()
+        .table()
+        .title(vec![
+            "Period".cell().bold(true),
+            "Commands ran".cell().bold(true),
+            "Unique".cell().bold(true),
+            "Most used command".cell().bold(true),
+        ])
+        .bold(true);
+
+    print_stdout(table)?;
+
+    Ok(())
+}
+
+// Scale a count to a width, where max fills all of it
+#[allow(
+    clippy::clippy::cast_possible_truncation,
+    clippy::clippy::cast_sign_loss,
+    clippy::clippy::cast_precision_loss
+)]
+fn scale(count: usize, max: usize, width: usize) -> usize {
+    if max == 0 {
+        return 0;
+    }
+
+    ((count as f64 / max as f64) * width as f64).round() as usize
+}
+
+// A sparkline of the whole range, then a bar for each period
+fn print_graph(periods: &[PeriodSummary]) {
+    const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    const WIDTH: usize = 50;
+
+    let max = periods.iter().map(|p| p.commands_ran).max().unwrap_or(0);
+
+    let sparkline: String = periods
+        .iter()
+        .map(|p| SPARKS[std::cmp::min(scale(p.commands_ran, max, SPARKS.len()), SPARKS.len() - 1)])
+        .collect();
+
+    println!("{}\n", sparkline);
+
+    for p in periods {
+        println!(
+            "{:>10} {} {}",
+            p.period,
+            "█".repeat(scale(p.commands_ran, max, WIDTH)),
+            p.commands_ran
+        );
+    }
+}
+
+#[derive(Serialize)]
+struct Wrapped {
+    year: i32,
+    commands_ran: usize,
+    unique_commands_ran: usize,
+    most_used_command: Option<TopCommand>,
+    busiest_day: Option<BusiestDay>,
+    longest_command: Option<LongestCommand>,
+    most_failed_command: Option<TopCommand>,
+    commands_by_hour: Vec<usize>,
+}
+
+#[derive(Serialize)]
+struct BusiestDay {
+    day: String,
+    commands_ran: i64,
+}
+
+#[derive(Serialize)]
+struct LongestCommand {
+    command: String,
+    duration: i64,
+}
+
+fn top(counts: &HashMap<String, i64>) -> Option<TopCommand> {
+    top_commands(counts, 1)
+        .first()
+        .map(|(command, ran)| TopCommand {
+            command: (*command).to_string(),
+            ran: *ran,
+        })
+}
+
+// A year in review
+// range includes its end, so stop just before the next year begins
+async fn year_history(db: &(impl Database + Sync), year: i32) -> Result<Vec<History>> {
+    let start = Local.ymd(year, 1, 1).and_hms(0, 0, 0);
+    let end = Local.ymd(year + 1, 1, 1).and_hms(0, 0, 0) - Duration::nanoseconds(1);
+
+    db.range(start.into(), end.into()).await
+}
+
+fn compute_wrapped(history: &[History], year: i32) -> Wrapped {
+    let all: Vec<&History> = history.iter().collect();
+    let commands = count_commands(&all, None);
+
+    let mut days = HashMap::<String, i64>::new();
+    let mut failed = HashMap::<String, i64>::new();
+    let mut hours = vec![0; 24];
+
+    for h in history {
+        let time = h.timestamp.with_timezone(&Local);
+
+        *days.entry(time.format("%Y-%m-%d").to_string()).or_default() += 1;
+        hours[time.hour() as usize] += 1;
+
+        // running commands don't have an exit code yet
+        if h.exit != 0 && h.duration != -1 {
+            *failed.entry(h.command.trim().to_string()).or_default() += 1;
+        }
+    }
+
+    let longest_command = history
+        .iter()
+        .max_by_key(|h| h.duration)
+        .filter(|h| h.duration > 0)
+        .map(|h| LongestCommand {
+            command: h.command.trim().to_string(),
+            duration: h.duration,
+        });
+
+    Wrapped {
+        year,
+        commands_ran: history.len(),
+        unique_commands_ran: commands.len(),
+        most_used_command: top(&commands),
+        busiest_day: top(&days).map(|d| BusiestDay {
+            day: d.command,
+            commands_ran: d.ran,
+        }),
+        longest_command,
+        most_failed_command: top(&failed),
+        commands_by_hour: hours,
+    }
+}
+
+#[allow(clippy::clippy::cast_sign_loss)]
+fn print_wrapped(wrapped: &Wrapped) {
+    const WIDTH: usize = 40;
+
+    println!("Your {} in shell history\n", wrapped.year);
+    println!(
+        "You ran {} commands, {} of them unique",
+        wrapped.commands_ran, wrapped.unique_commands_ran
+    );
+
+    if let Some(c) = &wrapped.most_used_command {
+        println!("Your favourite was `{}`, ran {} times", c.command, c.ran);
+    }
+
+    if let Some(d) = &wrapped.busiest_day {
+        println!(
+            "Your busiest day was {}, with {} commands",
+            d.day, d.commands_ran
+        );
+    }
+
+    if let Some(c) = &wrapped.longest_command {
+        let duration = humantime::format_duration(std::time::Duration::from_secs(
+            c.duration as u64 / 1_000_000_000,
+        ));
+        println!("The longest running was `{}`, at {}", c.command, duration);
+    }
+
+    if let Some(c) = &wrapped.most_failed_command {
+        println!("`{}` failed the most, {} times", c.command, c.ran);
+    }
+
+    println!("\nCommands by hour\n");
+
+    let max = wrapped.commands_by_hour.iter().copied().max().unwrap_or(0);
+
+    for (hour, count) in wrapped.commands_by_hour.iter().enumerate() {
+        println!(
+            "{:02} {} {}",
+            hour,
+            "█".repeat(scale(*count, max, WIDTH)),
+            count
+        );
+    }
+}
+
 impl Cmd {
     pub async fn run(
         &self,
         db: &mut (impl Database + Send + Sync),
         settings: &Settings,
     ) -> Result<()> {
-        match self {
-            Self::Day { words } => {
-                let words = if words.is_empty() {
-                    String::from("yesterday")
-                } else {
-                    words.join(" ")
-                };
+        if self.range.first().map(String::as_str) == Some("wrapped") {
+            return self.wrapped(db).await;
+        }
 
-                let start = match settings.dialect.to_lowercase().as_str() {
-                    "uk" => parse_date_string(&words, Local::now(), Dialect::Uk)?,
-                    _ => parse_date_string(&words, Local::now(), Dialect::Us)?,
-                };
-                let end = start + Duration::days(1);
+        let range = parse_range(&self.range, settings)?;
 
-                let history = db.range(start.into(), end.into()).await?;
+        let from = self
+            .from
+            .as_deref()
+            .map(|d| parse_date(d, settings))
+            .transpose()?;
+        let to = self
+            .to
+            .as_deref()
+            .map(|d| parse_date(d, settings))
+            .transpose()?;
 
-                compute_stats(&history)?;
+        let history = match (range, from, to) {
+            (None, None, None) => db.list(None, false).await?,
+            (range, from, to) => {
+                let start = from
+                    .or_else(|| range.map(|r| r.0))
+                    .unwrap_or_else(|| Local.timestamp(0, 0));
+                let end = to.or_else(|| range.map(|r| r.1)).unwrap_or_else(Local::now);
 
-                Ok(())
+                db.range(start.into(), end.into()).await?
             }
+        };
 
-            Self::All => {
-                let history = db.list(None, false).await?;
+        let summary = compute_stats(&history, self.count, self.prefix_depth);
 
-                compute_stats(&history)?;
+        // graphs are per day unless asked otherwise
+        let period = match (self.period, self.graph) {
+            (None, true) => Some(Period::Day),
+            (period, _) => period,
+        };
+        let periods = period.map(|p| compute_period_stats(&history, p, self.prefix_depth));
 
-                Ok(())
-            }
+        if self.format == Format::Json {
+            let report = Report { summary, periods };
+            println!("{}", serde_json::to_string_pretty(&report)?);
+
+            return Ok(());
+        }
+
+        print_summary(&summary)?;
+
+        match periods {
+            Some(periods) if self.graph => print_graph(&periods),
+            Some(periods) => print_periods(&periods)?,
+            None => {}
+        }
+
+        Ok(())
+    }
+
+    async fn wrapped(&self, db: &mut (impl Database + Send + Sync)) -> Result<()> {
+        let year = match self.range.get(1) {
+            Some(year) => year.parse().map_err(|_| eyre!("invalid year: {}", year))?,
+            None => Local::now().year(),
+        };
+
+        let history = year_history(&*db, year).await?;
+
+        if history.is_empty() {
+            return Err(eyre!("No commands found in {}", year));
+        }
+
+        let wrapped = compute_wrapped(&history, year);
+
+        if self.format == Format::Json {
+            println!("{}", serde_json::to_string_pretty(&wrapped)?);
+        } else {
+            print_wrapped(&wrapped);
         }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::prelude::*;
+
+    use atuin_client::database::{Database, Sqlite};
+    use atuin_client::history::History;
+    use atuin_common::utils::uuid_v4;
+
+    use super::year_history;
+
+    #[tokio::test]
+    async fn test_year_history() {
+        let path = std::env::temp_dir().join(format!("atuin-test-{}.db", uuid_v4()));
+        let mut db = Sqlite::new(&path).await.unwrap();
+
+        let times = vec![
+            ("last year", Local.ymd(2020, 12, 31).and_hms(23, 59, 59)),
+            ("new year", Local.ymd(2021, 1, 1).and_hms(0, 0, 0)),
+            ("summer", Local.ymd(2021, 7, 1).and_hms(12, 0, 0)),
+            ("next year", Local.ymd(2022, 1, 1).and_hms(0, 0, 0)),
+        ];
+
+        for (command, time) in times {
+            let h = History::new(
+                time.into(),
+                command.into(),
+                "/".into(),
+                0,
+                1,
+                Some("session".into()),
+                Some("host:user".into()),
+            );
+            db.save(&h).await.unwrap();
+        }
+
+        let found = year_history(&db, 2021).await.unwrap();
+
+        std::fs::remove_file(&path).ok();
+
+        let commands: Vec<&str> = found.iter().map(|h| h.command.as_str()).collect();
+        assert_eq!(commands, vec!["new year", "summer"]);
     }
 }
@@ -1,19 +1,218 @@
-use eyre::Result;
+use eyre::{eyre, Result};
+use serde_derive::Serialize;
+use structopt::StructOpt;
 
+use atuin_client::api_client;
+use atuin_client::daemon::{self, Request};
 use atuin_client::database::Database;
 use atuin_client::settings::Settings;
 use atuin_client::sync;
 
-pub async fn run(
-    settings: &Settings,
+use super::stats::Format;
+
+#[derive(StructOpt)]
+pub struct Cmd {
+    #[structopt(long, short, about = "force re-download everything")]
     force: bool,
+
+    #[structopt(subcommand)]
+    cmd: Option<SyncCmd>,
+}
+
+#[derive(StructOpt)]
+pub enum SyncCmd {
+    #[structopt(about = "show what the server knows about, and when we last synced")]
+    Status {
+        #[structopt(
+            long,
+            default_value = "text",
+            about = "how to print the status, text or json"
+        )]
+        format: Format,
+    },
+}
+
+impl Cmd {
+    pub async fn run(
+        &self,
+        settings: &Settings,
+        db: &mut (impl Database + Send + Sync),
+    ) -> Result<()> {
+        if let Some(SyncCmd::Status { format }) = self.cmd {
+            return status(settings, db, format == Format::Json).await;
+        }
+
+        if settings.offline {
+            eprintln!("Offline, not syncing");
+            return Ok(());
+        }
+
+        // the daemon already has the database open, so it is left to sync if
+        // it is running
+        let synced = match daemon::Client::connect().await {
+            Some(mut client) => client
+                .request(&Request::Sync { force: self.force })
+                .await
+                .map(|_| ()),
+            None => sync::sync(settings, self.force, db).await,
+        };
+
+        // history is kept locally either way, so there's no need to fail
+        // because of the network. it goes up with the next sync
+        match synced {
+            Err(e) if api_client::is_unreachable(&e) => {
+                eprintln!(
+                    "Could not reach {}, history will sync next time: {}",
+                    settings.sync_address, e
+                );
+                return Ok(());
+            }
+            res => res?,
+        }
+
+        println!(
+            "Sync complete! {} items in database, force: {}",
+            db.history_count().await?,
+            self.force
+        );
+        Ok(())
+    }
+}
+
+// The output of sync status --format json. Other tools build on this, so fields
+// may be added but never renamed or removed.
+#[derive(Serialize)]
+struct StatusJson {
+    local: LocalStatus,
+    remote: Option<RemoteStatus>,
+
+    // why the remote status is missing, if it is
+    remote_error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct LocalStatus {
+    sync_address: String,
+    history_count: i64,
+    last_sync: Option<String>,
+}
+
+#[derive(Serialize)]
+struct RemoteStatus {
+    username: String,
+    history_count: i64,
+    hosts: Vec<HostStatus>,
+}
+
+#[derive(Serialize)]
+struct HostStatus {
+    host: String,
+    this_host: bool,
+}
+
+async fn remote_status(settings: &Settings) -> Result<std::result::Result<RemoteStatus, String>> {
+    if settings.offline {
+        return Ok(Err(String::from("Offline, not checking the server")));
+    }
+
+    let client = api_client::Client::new(settings)?;
+
+    let status = match client.status().await {
+        Err(e) if api_client::is_unreachable(&e) => {
+            return Ok(Err(format!("Could not reach the server: {}", e)));
+        }
+        res => res?,
+    };
+
+    // hostnames are hashed before they are uploaded, so the best we can do is
+    // point out which one is this machine
+    let this_host = api_client::this_host();
+
+    Ok(Ok(RemoteStatus {
+        username: status.username,
+        history_count: status.count,
+        hosts: status
+            .hosts
+            .into_iter()
+            .map(|host| HostStatus {
+                this_host: host == this_host,
+                host,
+            })
+            .collect(),
+    }))
+}
+
+async fn status(
+    settings: &Settings,
     db: &mut (impl Database + Send + Sync),
+    json: bool,
 ) -> Result<()> {
-    sync::sync(settings, force, db).await?;
+    if !settings.can_sync() {
+        return Err(eyre!("you are not logged in"));
+    }
+
+    let last_sync = Settings::last_sync()?;
+
+    let local = LocalStatus {
+        sync_address: settings.sync_address.clone(),
+        history_count: db.history_count().await?,
+
+        // last_sync defaults to the epoch if we have never synced
+        last_sync: if last_sync.timestamp() == 0 {
+            None
+        } else {
+            Some(last_sync.to_rfc3339())
+        },
+    };
+
+    let remote = remote_status(settings).await?;
+
+    if json {
+        let (remote, remote_error) = match remote {
+            Ok(remote) => (Some(remote), None),
+            Err(e) => (None, Some(e)),
+        };
+
+        let status = StatusJson {
+            local,
+            remote,
+            remote_error,
+        };
+        println!("{}", serde_json::to_string_pretty(&status)?);
+
+        return Ok(());
+    }
+
+    println!("[Local]");
+    println!("Sync address: {}", local.sync_address);
+    println!("History count: {}", local.history_count);
     println!(
-        "Sync complete! {} items in database, force: {}",
-        db.history_count().await?,
-        force
+        "Last sync: {}",
+        local.last_sync.as_deref().unwrap_or("never")
     );
+
+    println!();
+    println!("[Remote]");
+
+    let remote = match remote {
+        Ok(remote) => remote,
+        Err(e) => {
+            println!("{}", e);
+            return Ok(());
+        }
+    };
+
+    println!("Username: {}", remote.username);
+    println!("History count: {}", remote.history_count);
+
+    println!("Hosts:");
+    for host in &remote.hosts {
+        if host.this_host {
+            println!("  {} (this host)", host.host);
+        } else {
+            println!("  {}", host.host);
+        }
+    }
+
     Ok(())
 }
@@ -1,7 +1,7 @@
 #![warn(clippy::pedantic, clippy::nursery)]
 #![allow(clippy::use_self)] // not 100% reliable
 
-use eyre::Result;
+use eyre::{eyre, Result};
 use structopt::{clap::AppSettings, StructOpt};
 
 #[macro_use]
@@ -21,12 +21,42 @@ const VERSION: &str = env!("CARGO_PKG_VERSION");
     global_settings(&[AppSettings::ColoredHelp, AppSettings::DeriveDisplayOrder])
 )]
 struct Atuin {
+    #[structopt(
+        long,
+        global = true,
+        about = "use a named profile, with its own config, history, key and session"
+    )]
+    profile: Option<String>,
+
+    #[structopt(long, global = true, about = "don't talk to the sync server")]
+    offline: bool,
+
     #[structopt(subcommand)]
     atuin: AtuinCmd,
 }
 
 impl Atuin {
     async fn run(self) -> Result<()> {
+        // everything finds its files through the environment, and anything we
+        // start picks it up too. the same goes for --offline
+        if let Some(profile) = &self.profile {
+            std::env::set_var(atuin_common::utils::PROFILE_VAR, profile);
+        }
+
+        // read by the settings, as ATUIN_OFFLINE is for the offline setting
+        if self.offline {
+            std::env::set_var("ATUIN_OFFLINE", "true");
+        }
+
+        if let Some(profile) = atuin_common::utils::profile() {
+            if !atuin_common::utils::valid_profile(profile.as_str()) {
+                return Err(eyre!(
+                    "invalid profile {}, use only letters, numbers, - and _",
+                    profile
+                ));
+            }
+        }
+
         self.atuin.run().await
     }
 }
@@ -1,3 +1,5 @@
+export ATUIN_SESSION=$(atuin uuid)
+
 _atuin_preexec() {
 	id=$(atuin history start "$1")
 	export ATUIN_HISTORY_ID="$id"
@@ -16,9 +18,17 @@ _atuin_precmd() {
 __atuin_history ()
 {
 	tput rmkx
-    HISTORY="$(RUST_LOG=error atuin search -i $BUFFER 3>&1 1>&2 2>&3)"
+    # start from whatever has been typed so far. the -- stops anything in it
+    # from being read as a flag
+    HISTORY="$(RUST_LOG=error atuin search -i -- "$READLINE_LINE" 3>&1 1>&2 2>&3)"
 	tput smkx
 
+	# bash can't run the line from here, so enter_accept still leaves it to edit
+	HISTORY=${HISTORY#__atuin_accept__:}
+
+    # nothing was selected, so leave the line as it was
+    [[ -z "${HISTORY}" ]] && return
+
     READLINE_LINE=${HISTORY}
     READLINE_POINT=${#READLINE_LINE}
 }
@@ -26,5 +36,3 @@ __atuin_history ()
 
 preexec_functions+=(_atuin_preexec)
 precmd_functions+=(_atuin_precmd)
-
-bind -x '"\C-r": __atuin_history'
@@ -0,0 +1,35 @@
+# Source this in your ~/.config/fish/config.fish
+set -gx ATUIN_SESSION (atuin uuid)
+
+function _atuin_preexec --on-event fish_preexec
+	set -gx ATUIN_HISTORY_ID (atuin history start "$argv[1]")
+end
+
+function _atuin_postexec --on-event fish_postexec
+	set -l s $status
+
+	test -z "$ATUIN_HISTORY_ID"; and return
+
+	# fish times each command in milliseconds
+	set -l duration (math "$CMD_DURATION * 1000000")
+
+	RUST_LOG=error atuin history end $ATUIN_HISTORY_ID --exit $s --duration $duration &>/dev/null &
+	disown
+end
+
+function _atuin_search
+	# swap stderr and stdout, so that the tui stuff works
+	# TODO: not this
+	# start from whatever has been typed so far. the -- stops anything in it
+	# from being read as a flag
+	set -l h (RUST_LOG=error atuin search -i -- (commandline -b) 3>&1 1>&2 2>&3)
+	commandline -f repaint
+
+	if string match -q '__atuin_accept__:*' -- "$h"
+		# selected with enter_accept, so run it straight away
+		commandline -r (string replace '__atuin_accept__:' '' -- "$h")
+		commandline -f execute
+	else if test -n "$h"
+		commandline -r $h
+	end
+end
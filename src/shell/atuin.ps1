@@ -0,0 +1,90 @@
+# Add this to your $PROFILE
+#   atuin init powershell | Out-String | Invoke-Expression
+
+if (-not (Get-Module PSReadLine)) {
+    Write-Error "atuin requires the PSReadLine module"
+    return
+}
+
+$env:ATUIN_SESSION = (atuin uuid | Out-String).Trim()
+$env:ATUIN_HISTORY_ID = $null
+
+$script:atuinPreviousPrompt = $function:prompt
+$script:atuinPreviousAddToHistory = (Get-PSReadLineOption).AddToHistoryHandler
+
+# PSReadLine calls this just before running a command, so it works as preexec
+Set-PSReadLineOption -AddToHistoryHandler {
+    param([string]$line)
+
+    if ($line.Trim()) {
+        $env:ATUIN_HISTORY_ID = (atuin history start -- $line | Out-String).Trim()
+    }
+
+    if ($script:atuinPreviousAddToHistory) {
+        return $script:atuinPreviousAddToHistory.Invoke($line)
+    }
+
+    return $true
+}
+
+function global:prompt {
+    # grab these first, anything else we run will overwrite them
+    $success = $?
+    $exit = $global:LASTEXITCODE
+
+    if ($env:ATUIN_HISTORY_ID) {
+        # $LASTEXITCODE is only set by native commands, so fall back to $?
+        # for failing cmdlets
+        if (-not $success -and -not $exit) {
+            $exit = 1
+        } elseif ($null -eq $exit) {
+            $exit = 0
+        }
+
+        $env:RUST_LOG = "error"
+        atuin history end $env:ATUIN_HISTORY_ID --exit $exit *> $null
+        Remove-Item env:RUST_LOG
+
+        $env:ATUIN_HISTORY_ID = $null
+    }
+
+    $global:LASTEXITCODE = $exit
+    & $script:atuinPreviousPrompt
+}
+
+function Invoke-AtuinSearch {
+    $line = $null
+    $cursor = $null
+    [Microsoft.PowerShell.PSConsoleReadLine]::GetBufferState([ref]$line, [ref]$cursor)
+
+    # the tui draws on stdout, and writes the selected command to stderr
+    $output = New-TemporaryFile
+
+    try {
+        $env:RUST_LOG = "error"
+        Start-Process -Wait -NoNewWindow -FilePath atuin -ArgumentList "search", "-i", "--", $line -RedirectStandardError $output.FullName
+        $result = (Get-Content -Raw $output.FullName | Out-String).Trim()
+    }
+    finally {
+        Remove-Item env:RUST_LOG -ErrorAction Ignore
+        Remove-Item $output.FullName
+    }
+
+    [Microsoft.PowerShell.PSConsoleReadLine]::InvokePrompt()
+
+    # commands selected with enter_accept are prefixed, and run straight away
+    $accept = $result.StartsWith("__atuin_accept__:")
+
+    if ($accept) {
+        $result = $result.Substring("__atuin_accept__:".Length)
+    }
+
+    if ($result) {
+        [Microsoft.PowerShell.PSConsoleReadLine]::RevertLine()
+        [Microsoft.PowerShell.PSConsoleReadLine]::Insert($result)
+
+        if ($accept) {
+            [Microsoft.PowerShell.PSConsoleReadLine]::AcceptLine()
+        }
+    }
+}